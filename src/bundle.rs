@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::commands::DylibKind;
+use crate::error::{Error, Result};
+use crate::macho::MachO;
+use crate::resolve::Resolver;
+use crate::rpath::DuplicateRpath;
+use crate::tree::{DependencyTree, Resolution};
+
+/// How bundled libraries are referenced after they have been copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadStyle {
+    /// `@loader_path/<relative path>/libfoo.dylib`, which works without any
+    /// rpath but ties every binary to its place relative to the libraries.
+    #[default]
+    LoaderPath,
+    /// `@rpath/libfoo.dylib`, with an `LC_RPATH` pointing at the library
+    /// directory added to every binary that loads one.
+    Rpath,
+}
+
+/// A library that was copied next to the binary.
+#[derive(Debug, Clone)]
+pub struct BundledLibrary {
+    /// Where the library was found.
+    pub source: PathBuf,
+    /// Where it was copied to.
+    pub destination: PathBuf,
+    /// Its new `LC_ID_DYLIB`, which is also how the other bundled libraries
+    /// load it.
+    pub install_name: String,
+}
+
+/// Copies the libraries a binary needs into one directory and rewrites the
+/// binary and the copies to load them from there, like `delocate` does for
+/// Python wheels.
+///
+/// Libraries that ship with macOS stay where they are. Every rewritten file
+/// that was signed, and every arm64 one, is signed again ad hoc, since editing
+/// its load commands invalidates the old signature.
+#[derive(Debug, Clone)]
+pub struct Bundler {
+    library_dir: PathBuf,
+    resolver: Resolver,
+    load_style: LoadStyle,
+}
+
+impl Bundler {
+    /// Bundles into `library_dir`, which is created when missing.
+    pub fn new(library_dir: &Path) -> Bundler {
+        Bundler { library_dir: library_dir.to_path_buf(), resolver: Resolver::new(), load_style: LoadStyle::default() }
+    }
+
+    /// Finds dependencies with this resolver instead of a default one.
+    pub fn set_resolver(&mut self, resolver: Resolver) {
+        self.resolver = resolver;
+    }
+
+    pub fn set_load_style(&mut self, load_style: LoadStyle) {
+        self.load_style = load_style;
+    }
+
+    /// Bundles the dependencies of the binary at `path` and rewrites it in place.
+    ///
+    /// Nothing is written unless every dependency was found, apart from weak
+    /// ones which dyld skips when they are missing and which are left as they are.
+    pub fn bundle(&self, path: &Path) -> Result<Vec<BundledLibrary>> {
+        let tree = DependencyTree::walk(&self.resolver, path)?;
+
+        // Every library to copy, keyed by the file it really is, and what each
+        // file that gets rewritten calls its dependencies
+        let mut libraries: Vec<BundledLibrary> = Vec::new();
+        let mut sources: HashMap<PathBuf, usize> = HashMap::new();
+        let mut loads: Vec<(PathBuf, Vec<(String, usize)>)> = Vec::new();
+
+        let mut pending = vec![(path.to_path_buf(), tree.dependencies)];
+        while let Some((loader, nodes)) = pending.pop() {
+            let mut names = Vec::new();
+
+            for node in nodes {
+                let expanded = matches!(node.resolution, Resolution::Found(_));
+                let found = match node.resolution {
+                    Resolution::Found(found) | Resolution::AlreadyListed(found) | Resolution::Cycle(found) => found,
+                    Resolution::System => continue,
+                    Resolution::Missing(_) if node.kind == DylibKind::Weak => continue,
+                    Resolution::Missing(e) => return Err(e),
+                    Resolution::Unreadable(path, e) => return Err(Error::UnreadableLibrary(path, Box::new(e))),
+                };
+
+                let key = std::fs::canonicalize(&found)?;
+                let index = match sources.get(&key) {
+                    Some(&index) => index,
+                    None => {
+                        libraries.push(self.library(&key, &libraries)?);
+                        sources.insert(key, libraries.len() - 1);
+                        libraries.len() - 1
+                    }
+                };
+                names.push((node.install_name, index));
+
+                // Libraries met again were rewritten where they were found first
+                if expanded {
+                    pending.push((found, node.dependencies));
+                }
+            }
+
+            loads.push((loader, names));
+        }
+
+        // Every file is edited in memory first, so that a library which cannot
+        // be edited leaves nothing half bundled
+        let library_dir = absolute(&self.library_dir)?;
+        let mut outputs = Vec::new();
+        for (loader, names) in loads {
+            let loader = std::fs::canonicalize(&loader)?;
+            let copy = sources.get(&loader).map(|&index| &libraries[index]);
+
+            // The binary reaches the library directory from wherever it is, the
+            // copies find each other right next to them
+            let (file, relative) = match copy {
+                Some(library) => (library.destination.clone(), PathBuf::new()),
+                None => {
+                    let directory = loader.parent().map(Path::to_path_buf).unwrap_or_default();
+                    (loader.clone(), relative_path(&directory, &library_dir))
+                }
+            };
+
+            let mut macho = MachO::parse(std::fs::read(&loader)?)?;
+            macho.set_duplicate_rpath(DuplicateRpath::Ignore);
+            if let Some(name) = file.file_name().and_then(|name| name.to_str()) {
+                macho.set_signing_identifier(name);
+            }
+
+            if let Some(library) = copy {
+                macho.set_id(&library.install_name)?;
+            }
+            for (old_name, index) in &names {
+                macho.change_dylib(old_name, &self.reference(&relative, &libraries[*index]))?;
+            }
+            if self.load_style == LoadStyle::Rpath && !names.is_empty() {
+                macho.add_rpath(&loader_path(&relative, None))?;
+            }
+
+            outputs.push((copy, file, macho.into_bytes()));
+        }
+
+        std::fs::create_dir_all(&self.library_dir)?;
+        for (copy, file, data) in outputs {
+            // Copying first keeps the permissions of the original
+            if let Some(library) = copy {
+                if std::fs::canonicalize(&file).ok().as_ref() != Some(&library.source) {
+                    std::fs::copy(&library.source, &file)?;
+                }
+            }
+            std::fs::write(&file, data)?;
+        }
+
+        Ok(libraries)
+    }
+
+    /// Where the library at `source` goes, checking that no other library of
+    /// the same file name is bundled already.
+    fn library(&self, source: &Path, libraries: &[BundledLibrary]) -> Result<BundledLibrary> {
+        let name = source.file_name().and_then(|name| name.to_str()).ok_or(Error::InvalidString)?;
+
+        if let Some(other) = libraries.iter().find(|library| library.destination.file_name() == source.file_name()) {
+            return Err(Error::LibraryNameClash(other.source.clone(), source.to_path_buf()));
+        }
+
+        let install_name = match self.load_style {
+            LoadStyle::Rpath => format!("@rpath/{}", name),
+            LoadStyle::LoaderPath => loader_path(Path::new(""), Some(name)),
+        };
+
+        Ok(BundledLibrary { source: source.to_path_buf(), destination: self.library_dir.join(name), install_name })
+    }
+
+    /// How a binary `relative` to the library directory loads `library`.
+    fn reference(&self, relative: &Path, library: &BundledLibrary) -> String {
+        match self.load_style {
+            LoadStyle::Rpath => library.install_name.clone(),
+            LoadStyle::LoaderPath => {
+                loader_path(relative, library.destination.file_name().and_then(|name| name.to_str()))
+            }
+        }
+    }
+}
+
+/// `@loader_path` followed by `relative` and `name`, without empty components.
+fn loader_path(relative: &Path, name: Option<&str>) -> String {
+    let mut path = String::from("@loader_path");
+    for component in relative.iter().filter_map(|component| component.to_str()) {
+        path.push('/');
+        path.push_str(component);
+    }
+    if let Some(name) = name {
+        path.push('/');
+        path.push_str(name);
+    }
+    path
+}
+
+/// `path` made absolute without resolving symlinks in the part that may not
+/// exist yet.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    let path = if path.is_absolute() { path.to_path_buf() } else { std::env::current_dir()?.join(path) };
+
+    // Symlinks in the existing part still have to match the canonical binary paths
+    let mut existing = path.as_path();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => break,
+        }
+    }
+
+    let mut absolute = std::fs::canonicalize(existing)?;
+    absolute.extend(missing.iter().rev());
+    Ok(crate::resolve::normalize(&absolute))
+}
+
+/// The path that leads from the directory `from` to `to`, both absolute.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{LC_ID_DYLIB, LC_LOAD_DYLIB};
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, MH_DYLIB, MH_EXECUTE};
+    use crate::image::Image;
+    use crate::rpath::add_rpath;
+
+    fn image(filetype: u32, dependencies: &[&str], rpaths: &[&str]) -> Vec<u8> {
+        let mut dylibs = Vec::new();
+        if filetype == MH_DYLIB {
+            dylibs.push((LC_ID_DYLIB, "/opt/lib/self.dylib"));
+        }
+        dylibs.extend(dependencies.iter().map(|&name| (LC_LOAD_DYLIB, name)));
+
+        let mut data = fixtures::linking(true, true, CPU_TYPE_ARM64, filetype, &dylibs);
+        for rpath in rpaths {
+            add_rpath(&mut data, rpath, DuplicateRpath::Error).unwrap();
+        }
+        data
+    }
+
+    fn dependencies(path: &Path) -> Vec<String> {
+        let image = Image::parse(&std::fs::read(path).unwrap()).unwrap();
+        image.dependencies().iter().map(|dylib| dylib.name.clone()).collect()
+    }
+
+    #[test]
+    fn libraries_are_copied_and_loaded_from_the_library_directory() {
+        let dir = fixtures::temp_dir("bundle");
+        let tool = dir.join("bin/tool");
+        fixtures::write(&tool, &image(MH_EXECUTE, &["@rpath/libfoo.dylib"], &["@executable_path/../lib"]));
+        fixtures::write(
+            &dir.join("lib/libfoo.dylib"),
+            &image(MH_DYLIB, &["/usr/lib/libSystem.B.dylib", "@loader_path/libbar.dylib"], &[]),
+        );
+        fixtures::write(&dir.join("lib/libbar.dylib"), &image(MH_DYLIB, &[], &[]));
+
+        let libraries = Bundler::new(&dir.join("bin/libs")).bundle(&tool).unwrap();
+
+        let names: Vec<_> = libraries.iter().map(|library| library.install_name.as_str()).collect();
+        assert_eq!(names, ["@loader_path/libfoo.dylib", "@loader_path/libbar.dylib"]);
+        assert_eq!(dependencies(&tool), ["@loader_path/libs/libfoo.dylib"]);
+        let libfoo = dir.join("bin/libs/libfoo.dylib");
+        assert_eq!(dependencies(&libfoo), ["/usr/lib/libSystem.B.dylib", "@loader_path/libbar.dylib"]);
+
+        // The edits invalidated the signatures of the arm64 files, they are signed again
+        for file in [tool, libfoo, dir.join("bin/libs/libbar.dylib")] {
+            let image = Image::parse(&std::fs::read(&file).unwrap()).unwrap();
+            let signature = image.code_signature().unwrap().unwrap();
+            assert!(signature.verify(&image.data).unwrap().is_empty(), "{}", file.display());
+        }
+        // The originals are left alone
+        assert_eq!(dependencies(&dir.join("lib/libfoo.dylib"))[1], "@loader_path/libbar.dylib");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_libraries_fail_the_bundle_before_anything_is_written() {
+        let dir = fixtures::temp_dir("bundle-missing");
+        let tool = dir.join("bin/tool");
+        // A system rpath does not make any library a system one
+        let dependencies = ["@rpath/libfoo.dylib", "@rpath/libmissing.dylib"];
+        let original = image(MH_EXECUTE, &dependencies, &["/usr/lib/swift", "@executable_path/../lib"]);
+        fixtures::write(&tool, &original);
+        fixtures::write(&dir.join("lib/libfoo.dylib"), &image(MH_DYLIB, &[], &[]));
+
+        let error = Bundler::new(&dir.join("bin/libs")).bundle(&tool).unwrap_err();
+
+        assert!(
+            matches!(&error, Error::LibraryNotFound { name, .. } if name == "@rpath/libmissing.dylib"),
+            "{:?}",
+            error
+        );
+        assert_eq!(std::fs::read(&tool).unwrap(), original);
+        assert!(!dir.join("bin/libs").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,38 @@
+//! Command line frontends that mirror Apple's developer tools.
+
+mod bundle;
+mod codesign;
+mod install_name_tool;
+mod ldd;
+mod lipo;
+mod nm;
+mod otool;
+mod relocate;
+mod vtool;
+
+/// Tools the binary can act as, by invocation name or first argument.
+pub const TOOLS: &[&str] = &["install_name_tool", "codesign", "otool", "ldd", "bundle", "relocate", "vtool", "lipo", "nm"];
+
+pub fn run(tool: &str, args: &[String]) -> i32 {
+    match tool {
+        "codesign" => codesign::run(args),
+        "otool" => otool::run(args),
+        "ldd" => ldd::run(args),
+        "bundle" => bundle::run(args),
+        "relocate" => relocate::run(args),
+        "vtool" => vtool::run(args),
+        "lipo" => lipo::run(args),
+        "nm" => nm::run(args),
+        _ => install_name_tool::run(args),
+    }
+}
+
+/// Prints an error the way Apple's tools do.
+fn report(tool: &str, message: &str) {
+    eprintln!("error: {}: {}", tool, message);
+}
+
+/// The last component of a path.
+pub fn file_name(path: &str) -> &str {
+    std::path::Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path)
+}
@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+
+use stealthemoon::bundle::LoadStyle;
+use stealthemoon::{Bundler, Resolver};
+
+use super::report;
+
+const USAGE: &str = "Usage: bundle --lib-dir dir [--rpath] [--root dir] [--executable-path file] file ...";
+
+struct Options {
+    library_dir: Option<PathBuf>,
+    load_style: LoadStyle,
+    root: Option<PathBuf>,
+    executable_path: Option<PathBuf>,
+    files: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        library_dir: None,
+        load_style: LoadStyle::LoaderPath,
+        root: None,
+        executable_path: None,
+        files: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().cloned().ok_or_else(|| format!("missing argument to: {} option", option));
+
+        match arg.as_str() {
+            "--lib-dir" => options.library_dir = Some(PathBuf::from(value(arg)?)),
+            "--rpath" => options.load_style = LoadStyle::Rpath,
+            "--root" => options.root = Some(PathBuf::from(value(arg)?)),
+            "--executable-path" => options.executable_path = Some(PathBuf::from(value(arg)?)),
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            file => options.files.push(file.to_string()),
+        }
+    }
+
+    if options.library_dir.is_none() {
+        return Err("missing --lib-dir option".to_string());
+    }
+    if options.files.is_empty() {
+        return Err("missing input file".to_string());
+    }
+
+    Ok(options)
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            report("bundle", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    let mut resolver = Resolver::new();
+    resolver.set_root(options.root.as_deref());
+    resolver.set_executable_path(options.executable_path.as_deref());
+
+    let mut bundler = Bundler::new(options.library_dir.as_deref().unwrap_or(Path::new(".")));
+    bundler.set_resolver(resolver);
+    bundler.set_load_style(options.load_style);
+
+    let mut status = 0;
+    for file in &options.files {
+        match bundler.bundle(Path::new(file)) {
+            Ok(libraries) => {
+                for library in libraries {
+                    println!("{} => {}", library.source.display(), library.destination.display());
+                }
+            }
+            Err(e) => {
+                report("bundle", &format!("{}: {}", file, e));
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
@@ -0,0 +1,368 @@
+use std::io::Write;
+
+use stealthemoon::codesign::{
+    CSMAGIC_BLOBWRAPPER, CSMAGIC_CODEDIRECTORY, CSMAGIC_EMBEDDED_DER_ENTITLEMENTS, CSMAGIC_EMBEDDED_ENTITLEMENTS,
+    CSMAGIC_REQUIREMENTS, CS_ADHOC, CS_CHECK_EXPIRATION, CS_ENFORCEMENT, CS_EXECSEG_MAIN_BINARY, CS_HARD, CS_KILL,
+    CS_LINKER_SIGNED, CS_REQUIRE_LV, CS_RESTRICT, CS_RUNTIME, CSSLOT_REQUIREMENTS,
+};
+use stealthemoon::header::describe_arch;
+use stealthemoon::signature::{hash_type_name, CodeSignature, HashMismatch};
+use stealthemoon::{Image, MachO, SigningOptions};
+
+use super::{file_name, report};
+
+const USAGE: &str = "Usage: codesign -s - [-f] [-i identifier] [--entitlements file] file ...
+       codesign --remove-signature file ...
+       codesign -d [-v...] [--entitlements -] file ...
+       codesign -v [-v...] file ...";
+
+/// Names `codesign -d` prints for the CodeDirectory flags.
+const FLAG_NAMES: &[(u32, &str)] = &[
+    (CS_ADHOC, "adhoc"),
+    (CS_HARD, "hard"),
+    (CS_KILL, "kill"),
+    (CS_CHECK_EXPIRATION, "expires"),
+    (CS_RESTRICT, "restrict"),
+    (CS_ENFORCEMENT, "enforcement"),
+    (CS_REQUIRE_LV, "library-validation"),
+    (CS_RUNTIME, "runtime"),
+    (CS_LINKER_SIGNED, "linker-signed"),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Sign,
+    RemoveSignature,
+    Display,
+    Verify,
+}
+
+struct Options {
+    operation: Operation,
+    verbosity: usize,
+    identifier: Option<String>,
+    /// Entitlements to sign with, or `-` to print them when displaying.
+    entitlements: Option<String>,
+    files: Vec<String>,
+}
+
+/// Parses the subset of `codesign` arguments needed for ad-hoc signing and inspection.
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut identity = None;
+    let mut operation = None;
+    let mut options = Options {
+        operation: Operation::Sign,
+        verbosity: 0,
+        identifier: None,
+        entitlements: None,
+        files: Vec::new(),
+    };
+
+    let mut set_operation = |new: Operation| match operation.replace(new) {
+        Some(old) if old != new => Err("only one operation can be given".to_string()),
+        _ => Ok(()),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().cloned().ok_or_else(|| format!("missing argument to: {} option", option));
+
+        match arg.as_str() {
+            "-s" | "--sign" => {
+                identity = Some(value(arg)?);
+                set_operation(Operation::Sign)?;
+            }
+            "--remove-signature" => set_operation(Operation::RemoveSignature)?,
+            "-d" | "--display" => set_operation(Operation::Display)?,
+            "--verify" => set_operation(Operation::Verify)?,
+            "--verbose" => options.verbosity += 1,
+            // Verbosity stacks and combines with display, as in -dvvv
+            flags if flags.len() > 1 && flags[1..].chars().all(|c| c == 'd' || c == 'v') && !flags.starts_with("--") => {
+                options.verbosity += flags.matches('v').count();
+                if flags.contains('d') {
+                    set_operation(Operation::Display)?;
+                }
+            }
+            "-i" | "--identifier" => options.identifier = Some(value(arg)?),
+            "--entitlements" => options.entitlements = Some(value(arg)?),
+            // Existing signatures are always replaced
+            "-f" | "--force" => {}
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            file => options.files.push(file.to_string()),
+        }
+    }
+
+    // A bare -v means verify, as it does for codesign
+    options.operation = match operation {
+        Some(operation) => operation,
+        None if options.verbosity > 0 => Operation::Verify,
+        None => return Err("no operation given, use -s, -d, -v or --remove-signature".to_string()),
+    };
+
+    match identity.as_deref() {
+        _ if options.operation != Operation::Sign => {}
+        Some("-") => {}
+        Some(identity) => return Err(format!("only ad-hoc signing (-s -) is supported, not: {}", identity)),
+        None => return Err("no signing identity given, use -s -".to_string()),
+    }
+    if options.files.is_empty() {
+        return Err("missing input file".to_string());
+    }
+
+    Ok(options)
+}
+
+fn sign_file(path: &str, options: &Options) -> stealthemoon::Result<()> {
+    let mut macho = MachO::parse(std::fs::read(path)?)?;
+
+    if options.operation == Operation::RemoveSignature {
+        macho.remove_signature()?;
+    } else {
+        let entitlements = options.entitlements.as_ref().map(std::fs::read).transpose()?;
+        macho.sign_adhoc(&SigningOptions {
+            identifier: options.identifier.clone().unwrap_or_else(|| file_name(path).to_string()),
+            entitlements,
+            der_entitlements: None,
+        })?;
+    }
+
+    std::fs::write(path, macho.into_bytes())?;
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn arch_name(image: &Image) -> String {
+    describe_arch(image.header.cputype, image.header.cpusubtype)
+}
+
+fn flags_description(flags: u32) -> String {
+    let names: Vec<&str> = FLAG_NAMES.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| *name).collect();
+    let names = if names.is_empty() { "none".to_string() } else { names.join(",") };
+
+    format!("{:#x}({})", flags, names)
+}
+
+fn blob_name(magic: u32) -> &'static str {
+    match magic {
+        CSMAGIC_CODEDIRECTORY => "CodeDirectory",
+        CSMAGIC_REQUIREMENTS => "Requirements",
+        CSMAGIC_EMBEDDED_ENTITLEMENTS => "Entitlements",
+        CSMAGIC_EMBEDDED_DER_ENTITLEMENTS => "DER Entitlements",
+        CSMAGIC_BLOBWRAPPER => "CMS Signature",
+        _ => "unknown",
+    }
+}
+
+fn requirement_name(kind: u32) -> &'static str {
+    match kind {
+        1 => "host",
+        2 => "guest",
+        3 => "designated",
+        4 => "library",
+        5 => "plugin",
+        _ => "unknown",
+    }
+}
+
+/// Prints what `codesign -d` shows for one architecture, plus the raw layout
+/// of the signature and whether its hashes still match.
+fn display_signature(
+    out: &mut impl Write,
+    image: &Image,
+    signature: &CodeSignature,
+    options: &Options,
+) -> stealthemoon::Result<()> {
+    let mismatches = signature.verify(&image.data)?;
+
+    if let Some(directory) = signature.code_directory() {
+        writeln!(out, "Identifier={}", directory.identifier)?;
+    }
+    for directory in &signature.code_directories {
+        let size = signature.blob(directory.slot).map_or(0, |blob| blob.data.len());
+        writeln!(
+            out,
+            "CodeDirectory v={:x} size={} flags={} hashes={}+{} location=embedded",
+            directory.version,
+            size,
+            flags_description(directory.flags),
+            directory.code_hashes.len(),
+            directory.special_slot_hashes.len(),
+        )?;
+        let hash_name = hash_type_name(directory.hash_type).map(str::to_string);
+        writeln!(
+            out,
+            "Hash type={} size={}",
+            hash_name.unwrap_or_else(|| format!("unknown({})", directory.hash_type)),
+            directory.hash_size,
+        )?;
+        writeln!(out, "Page size={}", directory.page_size())?;
+        if directory.version >= 0x20400 {
+            let main_binary = if directory.exec_seg_flags & CS_EXECSEG_MAIN_BINARY != 0 { "(main-binary)" } else { "" };
+            writeln!(
+                out,
+                "Executable segment base={} limit={} flags={:#x}{}",
+                directory.exec_seg_base, directory.exec_seg_limit, directory.exec_seg_flags, main_binary,
+            )?;
+        }
+        if directory.runtime != 0 {
+            let runtime = directory.runtime;
+            writeln!(out, "Runtime Version={}.{}.{}", runtime >> 16, (runtime >> 8) & 0xff, runtime & 0xff)?;
+        }
+        if directory.platform != 0 {
+            writeln!(out, "Platform identifier={}", directory.platform)?;
+        }
+        if let Some(cdhash) = &directory.cdhash {
+            writeln!(out, "CDHash={}", hex(cdhash))?;
+        }
+
+        for (slot, hash) in (1..).zip(&directory.special_slot_hashes) {
+            writeln!(out, "    -{}={}", slot, hex(hash))?;
+        }
+        if options.verbosity >= 3 {
+            for (page, hash) in directory.code_hashes.iter().enumerate() {
+                writeln!(out, "    {}={}", page, hex(hash))?;
+            }
+        }
+    }
+
+    match signature.cms_signature() {
+        Some(cms) => writeln!(out, "Signature size={}", cms.len())?,
+        None => writeln!(out, "Signature=adhoc")?,
+    }
+    let team_id = signature.code_directory().and_then(|directory| directory.team_id.as_deref());
+    writeln!(out, "TeamIdentifier={}", team_id.unwrap_or("not set"))?;
+
+    let requirements = signature.requirements()?;
+    let requirements_size = signature.blob(CSSLOT_REQUIREMENTS).map_or(0, |blob| blob.data.len());
+    writeln!(out, "Internal requirements count={} size={}", requirements.len(), requirements_size)?;
+    for requirement in &requirements {
+        writeln!(out, "    {} size={}", requirement_name(requirement.kind), requirement.data.len())?;
+    }
+
+    match (signature.entitlements(), signature.der_entitlements()) {
+        (None, None) => writeln!(out, "Entitlements=none")?,
+        (xml, der) => writeln!(
+            out,
+            "Entitlements size={} DER size={}",
+            xml.map_or(0, <[u8]>::len),
+            der.map_or(0, <[u8]>::len),
+        )?,
+    }
+
+    writeln!(out, "SuperBlob count={}", signature.blobs.len())?;
+    for blob in &signature.blobs {
+        writeln!(
+            out,
+            "    slot={:#x} offset={} magic={:#010x} length={} ({})",
+            blob.slot,
+            blob.offset,
+            blob.magic,
+            blob.data.len(),
+            blob_name(blob.magic),
+        )?;
+    }
+
+    if mismatches.is_empty() {
+        writeln!(out, "Hashes=valid")?;
+    }
+    for mismatch in &mismatches {
+        match mismatch {
+            HashMismatch::CodePage { directory, page } => {
+                writeln!(out, "Hashes=page {} does not match CodeDirectory slot {:#x}", page, directory)?
+            }
+            HashMismatch::SpecialSlot { directory, slot } => {
+                writeln!(out, "Hashes=special slot -{} does not match CodeDirectory slot {:#x}", slot, directory)?
+            }
+        }
+    }
+
+    if let (Some("-" | ":-"), Some(entitlements)) = (options.entitlements.as_deref(), signature.entitlements()) {
+        out.write_all(entitlements)?;
+        if !entitlements.ends_with(b"\n") {
+            writeln!(out)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn display_file(path: &str, options: &Options) -> stealthemoon::Result<()> {
+    let macho = MachO::parse(std::fs::read(path)?)?;
+    let images = macho.images()?;
+    let mut out = std::io::stdout().lock();
+
+    let format = if macho.is_fat() { "universal" } else { "thin" };
+    let arches: Vec<String> = images.iter().map(arch_name).collect();
+    writeln!(out, "Executable={}", path)?;
+    writeln!(out, "Format=Mach-O {} ({})", format, arches.join(" "))?;
+
+    for (image, arch) in images.iter().zip(&arches) {
+        if macho.is_fat() {
+            writeln!(out, "Architecture={}", arch)?;
+        }
+        match image.code_signature()? {
+            Some(signature) => display_signature(&mut out, image, &signature, options)?,
+            None => writeln!(out, "Signature=none")?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks every architecture's hashes, returning what is wrong with the first
+/// one that fails.
+fn verify_file(path: &str, options: &Options) -> stealthemoon::Result<Option<&'static str>> {
+    let macho = MachO::parse(std::fs::read(path)?)?;
+
+    for image in macho.images()? {
+        let Some(signature) = image.code_signature()? else {
+            return Ok(Some("code object is not signed at all"));
+        };
+        if signature.code_directory().is_none() {
+            return Ok(Some("code signature has no CodeDirectory"));
+        }
+        if !signature.verify(&image.data)?.is_empty() {
+            return Ok(Some("invalid signature (code or signature have been modified)"));
+        }
+    }
+
+    if options.verbosity >= 2 {
+        eprintln!("{}: valid on disk", path);
+    }
+
+    Ok(None)
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            report("codesign", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    for file in &options.files {
+        let result = match options.operation {
+            Operation::Display => display_file(file, &options).map_err(|e| e.to_string()),
+            Operation::Verify => match verify_file(file, &options) {
+                Ok(None) => Ok(()),
+                Ok(Some(problem)) => Err(problem.to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+            Operation::Sign | Operation::RemoveSignature => sign_file(file, &options).map_err(|e| e.to_string()),
+        };
+        if let Err(message) = result {
+            report("codesign", &format!("{}: {}", file, message));
+            status = 1;
+        }
+    }
+
+    status
+}
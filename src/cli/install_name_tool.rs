@@ -0,0 +1,152 @@
+use stealthemoon::MachO;
+
+use super::{file_name, report};
+
+/// One `install_name_tool` option.
+#[derive(Debug)]
+enum Edit {
+    AddRpath(String),
+    PrependRpath(String),
+    DeleteRpath(String),
+    ChangeRpath(String, String),
+    Id(String),
+    Change(String, String),
+}
+
+impl Edit {
+    fn apply(&self, macho: &mut MachO) -> stealthemoon::Result<()> {
+        match self {
+            Edit::AddRpath(path) => macho.add_rpath(path),
+            Edit::PrependRpath(path) => macho.prepend_rpath(path),
+            Edit::DeleteRpath(path) => macho.delete_rpath(path),
+            Edit::ChangeRpath(old_path, new_path) => macho.change_rpath(old_path, new_path),
+            Edit::Id(name) => macho.set_id(name),
+            Edit::Change(old_name, new_name) => macho.change_dylib(old_name, new_name),
+        }
+    }
+}
+
+const USAGE: &str = "Usage: install_name_tool [-change old new] ... [-rpath old new] ... [-add_rpath new] ... \
+[-prepend_rpath new] ... [-delete_rpath old] ... [-id name] input ...";
+
+/// Parses `install_name_tool` style arguments into the edits and the files to apply them to.
+fn parse_args(args: &[String]) -> Result<(Vec<Edit>, Vec<String>), String> {
+    let mut edits = Vec::new();
+    let mut files = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().cloned().ok_or_else(|| format!("missing argument to: {} option", option));
+
+        match arg.as_str() {
+            "-add_rpath" => edits.push(Edit::AddRpath(value(arg)?)),
+            "-prepend_rpath" => edits.push(Edit::PrependRpath(value(arg)?)),
+            "-delete_rpath" => edits.push(Edit::DeleteRpath(value(arg)?)),
+            "-rpath" => edits.push(Edit::ChangeRpath(value(arg)?, value(arg)?)),
+            "-id" => {
+                if edits.iter().any(|edit| matches!(edit, Edit::Id(_))) {
+                    return Err("more than one: -id option specified".to_string());
+                }
+                edits.push(Edit::Id(value(arg)?))
+            }
+            "-change" => edits.push(Edit::Change(value(arg)?, value(arg)?)),
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            file => files.push(file.to_string()),
+        }
+    }
+
+    if files.is_empty() {
+        return Err("missing input file".to_string());
+    }
+
+    Ok((edits, files))
+}
+
+/// Applies every edit to an in-memory copy of the file and only writes it back
+/// once all of them have succeeded.
+fn edit_file(path: &str, edits: &[Edit]) -> stealthemoon::Result<()> {
+    let mut macho = MachO::parse(std::fs::read(path)?)?;
+    macho.set_signing_identifier(file_name(path));
+
+    for edit in edits {
+        edit.apply(&mut macho)?;
+    }
+
+    std::fs::write(path, macho.into_bytes())?;
+
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let (edits, files) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            report("install_name_tool", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    for file in &files {
+        if let Err(e) = edit_file(file, &edits) {
+            report("install_name_tool", &format!("{}: {}", file, e));
+            status = 1;
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_are_kept_in_command_line_order() {
+        let (edits, files) = parse_args(&args(&[
+            "-add_rpath", "@loader_path/../lib",
+            "-rpath", "/old", "/new",
+            "-change", "/usr/lib/libfoo.dylib", "@rpath/libfoo.dylib",
+            "-delete_rpath", "/gone",
+            "-prepend_rpath", "@executable_path",
+            "-id", "@rpath/libbar.dylib",
+            "libbar.dylib",
+            "libbaz.dylib",
+        ]))
+        .unwrap();
+
+        assert!(matches!(
+            edits.as_slice(),
+            [
+                Edit::AddRpath(added),
+                Edit::ChangeRpath(old_path, new_path),
+                Edit::Change(old_name, new_name),
+                Edit::DeleteRpath(deleted),
+                Edit::PrependRpath(prepended),
+                Edit::Id(id),
+            ] if added == "@loader_path/../lib"
+                && (old_path.as_str(), new_path.as_str()) == ("/old", "/new")
+                && (old_name.as_str(), new_name.as_str()) == ("/usr/lib/libfoo.dylib", "@rpath/libfoo.dylib")
+                && deleted == "/gone"
+                && prepended == "@executable_path"
+                && id == "@rpath/libbar.dylib"
+        ));
+        assert_eq!(files, ["libbar.dylib", "libbaz.dylib"]);
+    }
+
+    #[test]
+    fn malformed_command_lines_are_rejected() {
+        let error = |arguments: &[&str]| parse_args(&args(arguments)).unwrap_err();
+
+        assert_eq!(error(&["-add_rpath"]), "missing argument to: -add_rpath option");
+        assert_eq!(error(&["-rpath", "/old"]), "missing argument to: -rpath option");
+        assert_eq!(error(&["-id", "a", "-id", "b", "lib.dylib"]), "more than one: -id option specified");
+        assert_eq!(error(&["-frobnicate", "lib.dylib"]), "unknown option: -frobnicate");
+        assert_eq!(error(&["-add_rpath", "/lib"]), "missing input file");
+    }
+}
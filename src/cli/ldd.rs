@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use stealthemoon::commands::DylibKind;
+use stealthemoon::tree::{DependencyNode, Resolution};
+use stealthemoon::{DependencyTree, Resolver};
+
+use super::report;
+
+const USAGE: &str = "Usage: ldd [--tree] [--root dir] [--executable-path file] file ...";
+
+struct Options {
+    tree: bool,
+    root: Option<PathBuf>,
+    executable_path: Option<PathBuf>,
+    files: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options { tree: false, root: None, executable_path: None, files: Vec::new() };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().cloned().ok_or_else(|| format!("missing argument to: {} option", option));
+
+        match arg.as_str() {
+            "--tree" => options.tree = true,
+            "--root" => options.root = Some(PathBuf::from(value(arg)?)),
+            "--executable-path" => options.executable_path = Some(PathBuf::from(value(arg)?)),
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            file => options.files.push(file.to_string()),
+        }
+    }
+
+    if options.files.is_empty() {
+        return Err("missing input file".to_string());
+    }
+
+    Ok(options)
+}
+
+/// `name => path` plus what is special about the dependency.
+fn describe(node: &DependencyNode) -> String {
+    let kind = match node.kind {
+        DylibKind::Weak => " (weak)",
+        DylibKind::Reexport => " (reexport)",
+        DylibKind::Upward => " (upward)",
+        DylibKind::Lazy => " (lazy)",
+        DylibKind::Id | DylibKind::Load => "",
+    };
+
+    match &node.resolution {
+        Resolution::Found(path) => format!("{} => {}{}", node.install_name, path.display(), kind),
+        Resolution::AlreadyListed(path) => format!("{} => {}{} (*)", node.install_name, path.display(), kind),
+        Resolution::Cycle(path) => format!("{} => {}{} (cycle)", node.install_name, path.display(), kind),
+        Resolution::System => format!("{}{} (system)", node.install_name, kind),
+        Resolution::Unreadable(path, _) => format!("{} => {}{} (unreadable)", node.install_name, path.display(), kind),
+        Resolution::Missing(_) => format!("{} => not found{}", node.install_name, kind),
+    }
+}
+
+fn print_tree(nodes: &[DependencyNode], prefix: &str) {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index + 1 == nodes.len();
+        println!("{}{}{}", prefix, if last { "└── " } else { "├── " }, describe(node));
+        print_tree(&node.dependencies, &format!("{}{}", prefix, if last { "    " } else { "│   " }));
+    }
+}
+
+/// Prints every library `path` loads, directly or not. Returns whether all
+/// of them were found, leaving out weak libraries which may be missing.
+fn resolve_file(path: &str, resolver: &Resolver, options: &Options) -> stealthemoon::Result<bool> {
+    let tree = DependencyTree::walk(resolver, Path::new(path))?;
+    let flat = tree.flatten();
+
+    if options.tree {
+        println!("{}", path);
+        print_tree(&tree.dependencies, "");
+    } else {
+        println!("{}:", path);
+        for node in &flat {
+            println!("\t{}", describe(node));
+        }
+    }
+
+    let mut resolved = true;
+    for node in flat {
+        match &node.resolution {
+            Resolution::Missing(_) if node.kind == DylibKind::Weak => {}
+            Resolution::Missing(e) | Resolution::Unreadable(_, e) => {
+                report("ldd", &format!("{}: {}", path, e));
+                resolved = false;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(resolved)
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            report("ldd", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    let mut resolver = Resolver::new();
+    resolver.set_root(options.root.as_deref());
+    resolver.set_executable_path(options.executable_path.as_deref());
+
+    let mut status = 0;
+    for file in &options.files {
+        match resolve_file(file, &resolver, &options) {
+            Ok(true) => {}
+            Ok(false) => status = 1,
+            Err(e) => {
+                report("ldd", &format!("{}: {}", file, e));
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
@@ -0,0 +1,240 @@
+use std::io::Write;
+
+use stealthemoon::fat::{is_fat, FatBinary, FAT_MAGIC, FAT_MAGIC_64};
+use stealthemoon::header::{
+    describe_arch, parse_arch, parse_macho, CpuSubtype, CpuType, PtrauthAbi, CPU_SUBTYPE_LIB64, CPU_SUBTYPE_MASK,
+};
+use stealthemoon::Error;
+
+use super::report;
+
+const USAGE: &str = "Usage: lipo [-info | -detailed_info | -archs] file ...\n       \
+lipo -create file ... [-fat64] -output file\n       \
+lipo file [-thin arch | -extract arch ... | -remove arch ... | -replace arch file] [-fat64] -output file";
+
+/// The one thing `lipo` is asked to do.
+#[derive(Debug)]
+enum Operation {
+    Info,
+    DetailedInfo,
+    Archs,
+    Create,
+    Thin((i32, i32)),
+    Extract(Vec<(i32, i32)>),
+    Remove(Vec<(i32, i32)>),
+    Replace((i32, i32), String),
+}
+
+struct Options {
+    operation: Option<Operation>,
+    fat64: bool,
+    output: Option<String>,
+    files: Vec<String>,
+}
+
+fn arch(name: String) -> Result<(i32, i32), String> {
+    parse_arch(&name).ok_or_else(|| format!("unknown architecture specification flag: {}", name))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options { operation: None, fat64: false, output: None, files: Vec::new() };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().cloned().ok_or_else(|| format!("missing argument to: {} option", option));
+
+        let operation = match arg.as_str() {
+            "-info" => Operation::Info,
+            "-detailed_info" => Operation::DetailedInfo,
+            "-archs" => Operation::Archs,
+            "-create" => Operation::Create,
+            "-thin" => Operation::Thin(arch(value(arg)?)?),
+            "-replace" => Operation::Replace(arch(value(arg)?)?, value(arg)?),
+            // -extract and -remove can be given once per architecture
+            "-extract" | "-remove" => {
+                let arch = arch(value(arg)?)?;
+                match (&mut options.operation, arg.as_str()) {
+                    (Some(Operation::Extract(arches)), "-extract") | (Some(Operation::Remove(arches)), "-remove") => {
+                        arches.push(arch);
+                        continue;
+                    }
+                    (_, "-extract") => Operation::Extract(vec![arch]),
+                    _ => Operation::Remove(vec![arch]),
+                }
+            }
+            "-fat64" => {
+                options.fat64 = true;
+                continue;
+            }
+            "-output" | "-o" => {
+                options.output = Some(value(arg)?);
+                continue;
+            }
+            option if option.starts_with('-') => return Err(format!("unknown flag: {}", option)),
+            file => {
+                options.files.push(file.to_string());
+                continue;
+            }
+        };
+
+        if options.operation.is_some() {
+            return Err(format!("only one operation can be specified, {} was not the first", arg));
+        }
+        options.operation = Some(operation);
+    }
+
+    let Some(operation) = &options.operation else {
+        return Err("one of -create, -thin, -extract, -remove, -replace, -info, -detailed_info or -archs must be specified".to_string());
+    };
+    if options.files.is_empty() {
+        return Err("no input files specified".to_string());
+    }
+    match operation {
+        Operation::Info | Operation::DetailedInfo | Operation::Archs => {}
+        _ if options.output.is_none() => return Err("no output file specified".to_string()),
+        Operation::Create => {}
+        _ if options.files.len() > 1 => return Err("only one input file can be specified".to_string()),
+        _ => {}
+    }
+
+    Ok(options)
+}
+
+/// The architecture names of a thin or universal file.
+fn arch_names(data: &[u8]) -> stealthemoon::Result<Vec<String>> {
+    if is_fat(data) {
+        Ok(FatBinary::parse(data)?.arches.iter().map(|arch| arch.name()).collect())
+    } else {
+        let (header, _) = parse_macho(data)?;
+        Ok(vec![describe_arch(header.cputype, header.cpusubtype)])
+    }
+}
+
+fn print_info(out: &mut impl Write, path: &str, data: &[u8], operation: &Operation) -> stealthemoon::Result<()> {
+    let names = arch_names(data)?;
+
+    match operation {
+        Operation::Archs => writeln!(out, "{}", names.join(" "))?,
+        Operation::DetailedInfo if is_fat(data) => {
+            let fat = FatBinary::parse(data)?;
+            writeln!(out, "Fat header in: {}", path)?;
+            writeln!(out, "fat_magic {:#x}", if fat.is_64 { FAT_MAGIC_64 } else { FAT_MAGIC })?;
+            writeln!(out, "nfat_arch {}", fat.arches.len())?;
+            for arch in &fat.arches {
+                writeln!(out, "architecture {}", arch.name())?;
+                writeln!(out, "    cputype {}", CpuType::new(arch.cputype))?;
+                writeln!(out, "    cpusubtype {}", CpuSubtype::new(arch.cputype, arch.cpusubtype))?;
+                writeln!(out, "    capabilities {}", capabilities(arch.cputype, arch.cpusubtype))?;
+                writeln!(out, "    offset {}", arch.offset)?;
+                writeln!(out, "    size {}", arch.size)?;
+                writeln!(out, "    align 2^{} ({})", arch.align, 1u64 << arch.align)?;
+            }
+        }
+        _ if is_fat(data) => {
+            write!(out, "Architectures in the fat file: {} are: ", path)?;
+            for name in &names {
+                write!(out, "{} ", name)?;
+            }
+            writeln!(out)?;
+        }
+        _ => writeln!(out, "Non-fat file: {} is architecture: {}", path, names[0])?,
+    }
+
+    Ok(())
+}
+
+/// The capability bits of a subtype, named where `lipo` names them.
+fn capabilities(cputype: i32, cpusubtype: i32) -> String {
+    let capabilities = cpusubtype as u32 >> 24;
+
+    match (CpuType::new(cputype), PtrauthAbi::new(cputype, cpusubtype)) {
+        (_, Some(ptrauth_abi)) => format!(
+            "PTR_AUTH_VERSION {} {}",
+            if ptrauth_abi.kernel { "KERNEL" } else { "USERSPACE" },
+            ptrauth_abi.version
+        ),
+        (CpuType::X86_64, None) if cpusubtype as u32 & CPU_SUBTYPE_LIB64 != 0 => "CPU_SUBTYPE_LIB64".to_string(),
+        (_, None) => format!("{:#x}", capabilities),
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Builds the output file of an editing operation.
+fn build(options: &Options, operation: &Operation) -> Result<Vec<u8>, String> {
+    if let Operation::Create = operation {
+        let files = options.files.iter().map(|path| read(path)).collect::<Result<Vec<_>, _>>()?;
+        let mut fat = FatBinary::create(&files).map_err(|e| e.to_string())?;
+        fat.is_64 |= options.fat64;
+        return Ok(fat.to_bytes());
+    }
+
+    let path = &options.files[0];
+    let in_input = |e: Error| format!("{}: {}", path, e);
+    let data = read(path)?;
+    if !is_fat(&data) {
+        return Err(in_input(Error::NotFat));
+    }
+    let mut fat = FatBinary::parse(&data).map_err(in_input)?;
+    fat.is_64 |= options.fat64;
+
+    match operation {
+        Operation::Thin((cputype, cpusubtype)) => return fat.thin(*cputype, *cpusubtype).map_err(in_input),
+        Operation::Extract(arches) => fat = fat.extract(arches).map_err(in_input)?,
+        Operation::Remove(arches) => fat.remove(arches).map_err(in_input)?,
+        Operation::Replace((cputype, cpusubtype), replacement_path) => {
+            let replacement = read(replacement_path)?;
+            let (header, _) = parse_macho(&replacement).map_err(|e| format!("{}: {}", replacement_path, e))?;
+            if header.cputype != *cputype || (header.cpusubtype ^ cpusubtype) & !CPU_SUBTYPE_MASK != 0 {
+                return Err(format!(
+                    "{} is architecture {}, not {}",
+                    replacement_path,
+                    describe_arch(header.cputype, header.cpusubtype),
+                    describe_arch(*cputype, *cpusubtype)
+                ));
+            }
+            fat.replace(replacement).map_err(in_input)?;
+        }
+        _ => unreachable!("not an editing operation"),
+    }
+
+    Ok(fat.to_bytes())
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            report("lipo", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+    let Some(operation) = &options.operation else {
+        return 1;
+    };
+
+    if let Operation::Info | Operation::DetailedInfo | Operation::Archs = operation {
+        let mut status = 0;
+        let mut out = std::io::stdout().lock();
+        for file in &options.files {
+            let result = std::fs::read(file).map_err(Error::from).and_then(|data| print_info(&mut out, file, &data, operation));
+            if let Err(e) = result {
+                report("lipo", &format!("{}: {}", file, e));
+                status = 1;
+            }
+        }
+        return status;
+    }
+
+    let output = options.output.as_deref().unwrap_or_default();
+    match build(&options, operation).and_then(|bytes| std::fs::write(output, bytes).map_err(|e| format!("{}: {}", output, e))) {
+        Ok(()) => 0,
+        Err(message) => {
+            report("lipo", &message);
+            1
+        }
+    }
+}
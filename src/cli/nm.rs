@@ -0,0 +1,274 @@
+use std::io::Write;
+
+use stealthemoon::commands::{DylibCommand, Section};
+use stealthemoon::header::{describe_arch, HeaderFlags};
+use stealthemoon::symbols::{LibraryOrdinal, Symbol, SymbolType, REFERENCED_DYNAMICALLY};
+use stealthemoon::{Image, MachO};
+
+use super::report;
+
+const USAGE: &str = "Usage: nm [-g] [-u] [-m] [-j] file ...";
+
+struct Options {
+    external_only: bool,
+    undefined_only: bool,
+    mach_o: bool,
+    names_only: bool,
+    files: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options =
+        Options { external_only: false, undefined_only: false, mach_o: false, names_only: false, files: Vec::new() };
+
+    for arg in args {
+        match arg.as_str() {
+            // Options can be grouped, as in `-gj`
+            letters if letters.len() > 1 && letters[1..].chars().all(|letter| "gumj".contains(letter)) => {
+                for letter in letters[1..].chars() {
+                    match letter {
+                        'g' => options.external_only = true,
+                        'u' => options.undefined_only = true,
+                        'm' => options.mach_o = true,
+                        _ => options.names_only = true,
+                    }
+                }
+            }
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            file => options.files.push(file.to_string()),
+        }
+    }
+
+    if options.files.is_empty() {
+        return Err("missing input file".to_string());
+    }
+
+    Ok(options)
+}
+
+/// The one letter type of the classic `nm` output, lower case for symbols
+/// that are not external.
+fn type_letter(symbol: &Symbol, sections: &[&Section]) -> char {
+    let letter = match symbol.symbol_type() {
+        _ if symbol.is_common() => 'C',
+        SymbolType::Undefined | SymbolType::PreboundUndefined => 'U',
+        SymbolType::Absolute => 'A',
+        SymbolType::Indirect => 'I',
+        SymbolType::Section => match section(symbol, sections) {
+            Some(section) if section.segname == "__TEXT" && section.sectname == "__text" => 'T',
+            Some(section) if section.segname == "__DATA" && section.sectname == "__data" => 'D',
+            Some(section) if section.segname == "__DATA" && section.sectname == "__bss" => 'B',
+            _ => 'S',
+        },
+        SymbolType::Debug(_) | SymbolType::Unknown(_) => '?',
+    };
+
+    if symbol.is_external() {
+        letter
+    } else {
+        letter.to_ascii_lowercase()
+    }
+}
+
+fn section<'a>(symbol: &Symbol, sections: &[&'a Section]) -> Option<&'a Section> {
+    (symbol.n_sect as usize).checked_sub(1).and_then(|index| sections.get(index).copied())
+}
+
+/// `libSystem` for `/usr/lib/libSystem.B.dylib`, `Foundation` for the
+/// framework, as `nm -m` names libraries.
+fn library_name(dylib: &DylibCommand) -> &str {
+    let name = dylib.name.rsplit('/').next().unwrap_or(&dylib.name);
+    name.split('.').next().unwrap_or(name)
+}
+
+/// The `-m` form: where the symbol is, its attributes, its name, and for
+/// undefined symbols of two-level images the library it binds to.
+fn describe(symbol: &Symbol, image: &Image, sections: &[&Section]) -> String {
+    let place = match symbol.symbol_type() {
+        _ if symbol.is_common() => "(common)".to_string(),
+        SymbolType::Undefined => "(undefined)".to_string(),
+        SymbolType::PreboundUndefined => "(prebound undefined)".to_string(),
+        SymbolType::Absolute => "(absolute)".to_string(),
+        SymbolType::Indirect => "(indirect)".to_string(),
+        SymbolType::Section => match section(symbol, sections) {
+            Some(section) => format!("({},{})", section.segname, section.sectname),
+            None => "(?,?)".to_string(),
+        },
+        SymbolType::Debug(_) | SymbolType::Unknown(_) => "(?)".to_string(),
+    };
+
+    let mut attributes = String::new();
+    if symbol.n_desc & REFERENCED_DYNAMICALLY != 0 {
+        attributes.push_str("[referenced dynamically] ");
+    }
+    if symbol.is_weak_definition() || symbol.is_weak_reference() {
+        attributes.push_str("weak ");
+    }
+    attributes.push_str(match (symbol.is_external(), symbol.is_private_external()) {
+        (true, true) => "private external",
+        (true, false) => "external",
+        (false, true) => "non-external (was a private external)",
+        (false, false) => "non-external",
+    });
+
+    let mut line = format!("{} {} {}", place, attributes, symbol.name);
+    if image.header.header_flags().contains(HeaderFlags::TWOLEVEL) {
+        match symbol.library_ordinal() {
+            Some(LibraryOrdinal::Dependency(index)) => match image.dependencies().get(index) {
+                Some(dylib) => line.push_str(&format!(" (from {})", library_name(dylib))),
+                None => line.push_str(&format!(" (from bad library ordinal {})", index + 1)),
+            },
+            Some(LibraryOrdinal::DynamicLookup) => line.push_str(" (dynamically looked up)"),
+            Some(LibraryOrdinal::Executable) => line.push_str(" (from executable)"),
+            Some(LibraryOrdinal::SelfImage) | None => {}
+        }
+    }
+    line
+}
+
+fn print_image(out: &mut impl Write, image: &Image, options: &Options) -> stealthemoon::Result<()> {
+    let Some(table) = image.symbol_table()? else {
+        return Ok(());
+    };
+    let sections: Vec<&Section> = image.sections().collect();
+
+    let mut symbols: Vec<&Symbol> = table
+        .symbols
+        .iter()
+        .filter(|symbol| !symbol.is_debug())
+        .filter(|symbol| !options.external_only || symbol.is_external())
+        .filter(|symbol| !options.undefined_only || symbol.is_undefined())
+        .collect();
+    symbols.sort_by(|a, b| a.name.cmp(&b.name).then(a.n_value.cmp(&b.n_value)));
+
+    let width = if image.is_64 { 16 } else { 8 };
+    for symbol in symbols {
+        // Undefined symbols have no value to print, only their name with -u
+        if options.names_only || (options.undefined_only && !options.mach_o) {
+            writeln!(out, "{}", symbol.name)?;
+            continue;
+        }
+
+        let value = if symbol.is_undefined() {
+            " ".repeat(width)
+        } else {
+            format!("{:0width$x}", symbol.n_value, width = width)
+        };
+        if options.mach_o {
+            writeln!(out, "{} {}", value, describe(symbol, image, &sections))?;
+        } else {
+            writeln!(out, "{} {} {}", value, type_letter(symbol, &sections), symbol.name)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_file(path: &str, options: &Options) -> stealthemoon::Result<()> {
+    let macho = MachO::parse(std::fs::read(path)?)?;
+    let mut out = std::io::stdout().lock();
+
+    for image in macho.images()? {
+        if macho.is_fat() {
+            let arch = describe_arch(image.header.cputype, image.header.cpusubtype);
+            writeln!(out, "\n{} (for architecture {}):", path, arch)?;
+        } else if options.files.len() > 1 {
+            writeln!(out, "\n{}:", path)?;
+        }
+        print_image(&mut out, &image, options)?;
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            report("nm", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    for file in &options.files {
+        if let Err(e) = print_file(file, &options) {
+            report("nm", &format!("{}: {}", file, e));
+            status = 1;
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use stealthemoon::symbols::{N_ABS, N_EXT, N_INDR, N_PEXT, N_SECT, N_UNDF};
+
+    use super::*;
+
+    fn section(segname: &str, sectname: &str) -> Section {
+        Section {
+            sectname: sectname.to_string(),
+            segname: segname.to_string(),
+            addr: 0,
+            size: 0,
+            offset: 0,
+            align: 0,
+            reloff: 0,
+            nreloc: 0,
+            flags: 0,
+            reserved1: 0,
+            reserved2: 0,
+            reserved3: 0,
+        }
+    }
+
+    fn letter(n_type: u8, n_sect: u8, n_value: u64) -> char {
+        let sections = [
+            section("__TEXT", "__text"),
+            section("__DATA", "__data"),
+            section("__DATA", "__bss"),
+            section("__TEXT", "__cstring"),
+        ];
+        let symbol = Symbol { name: "_symbol".to_string(), n_type, n_sect, n_desc: 0, n_value };
+        type_letter(&symbol, &sections.iter().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn type_letters_follow_the_section_and_visibility() {
+        assert_eq!(letter(N_SECT | N_EXT, 1, 0), 'T');
+        assert_eq!(letter(N_SECT, 1, 0), 't');
+        assert_eq!(letter(N_SECT | N_EXT, 2, 0), 'D');
+        assert_eq!(letter(N_SECT | N_EXT, 3, 0), 'B');
+        assert_eq!(letter(N_SECT, 4, 0), 's');
+        // Private externs were made local by the static linker
+        assert_eq!(letter(N_SECT | N_PEXT, 1, 0), 't');
+        assert_eq!(letter(N_UNDF | N_EXT, 0, 0), 'U');
+        assert_eq!(letter(N_UNDF | N_EXT, 0, 16), 'C');
+        assert_eq!(letter(N_ABS | N_EXT, 0, 0), 'A');
+        assert_eq!(letter(N_INDR | N_EXT, 0, 0), 'I');
+        // A section number past the end of the section list
+        assert_eq!(letter(N_SECT | N_EXT, 9, 0), 'S');
+        assert_eq!(letter(0x24, 1, 0), '?');
+    }
+
+    #[test]
+    fn libraries_are_named_by_their_leaf_name() {
+        let dylib = |name: &str| DylibCommand {
+            cmd: 0xc,
+            cmdsize: 0,
+            name_offset: 24,
+            name: name.to_string(),
+            timestamp: 0,
+            current_version: 0,
+            compatibility_version: 0,
+        };
+
+        assert_eq!(library_name(&dylib("/usr/lib/libSystem.B.dylib")), "libSystem");
+        let foundation = dylib("/System/Library/Frameworks/Foundation.framework/Versions/C/Foundation");
+        assert_eq!(library_name(&foundation), "Foundation");
+        assert_eq!(library_name(&dylib("@rpath/libfoo.dylib")), "libfoo");
+    }
+}
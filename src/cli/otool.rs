@@ -0,0 +1,611 @@
+use std::io::Write;
+
+use stealthemoon::commands::{
+    command_name, Command, DylibCommand, DylibKind, Section, SegmentCommand, LC_SEGMENT_64, SECTION_TYPE,
+};
+use stealthemoon::header::{
+    describe_arch, CpuSubtype, CpuType, HeaderFlags, MachHeader, CPU_SUBTYPE_LIB64, MH_MAGIC, MH_MAGIC_64,
+};
+use stealthemoon::version::format_version;
+use stealthemoon::{Image, MachO};
+
+use super::report;
+
+const USAGE: &str = "Usage: otool [-h] [-l] [-L] [-v] [--json] file ...";
+
+const S_NON_LAZY_SYMBOL_POINTERS: u32 = 0x6;
+const S_LAZY_SYMBOL_POINTERS: u32 = 0x7;
+const S_SYMBOL_STUBS: u32 = 0x8;
+const S_LAZY_DYLIB_SYMBOL_POINTERS: u32 = 0x10;
+const S_THREAD_LOCAL_VARIABLE_POINTERS: u32 = 0x14;
+
+struct Options {
+    header: bool,
+    load_commands: bool,
+    libraries: bool,
+    verbose: bool,
+    json: bool,
+    files: Vec<String>,
+}
+
+/// What `--json` prints for each file.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct FileInspection<'a> {
+    path: &'a str,
+    #[serde(flatten)]
+    inspection: stealthemoon::Inspection,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        header: false,
+        load_commands: false,
+        libraries: false,
+        verbose: false,
+        json: false,
+        files: Vec::new(),
+    };
+
+    for arg in args {
+        match arg.as_str() {
+            // Single letter options can be grouped, as in `-hv`
+            letters if letters.len() > 1 && letters[1..].chars().all(|letter| "hlLv".contains(letter)) => {
+                for letter in letters[1..].chars() {
+                    match letter {
+                        'h' => options.header = true,
+                        'l' => options.load_commands = true,
+                        'L' => options.libraries = true,
+                        _ => options.verbose = true,
+                    }
+                }
+            }
+            "--json" if cfg!(feature = "serde") => options.json = true,
+            "--json" => return Err("--json needs a build with the serde feature".to_string()),
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            file => options.files.push(file.to_string()),
+        }
+    }
+
+    if !options.header && !options.load_commands && !options.libraries && !options.json {
+        return Err("one of -h, -l, -L or --json must be specified".to_string());
+    }
+    if options.files.is_empty() {
+        return Err("missing input file".to_string());
+    }
+
+    Ok(options)
+}
+
+/// `X.Y.Z` packed as `xxxx.yy.zz`, the way dylib versions are printed.
+fn dylib_version(version: u32) -> String {
+    format!("{}.{}.{}", version >> 16, (version >> 8) & 0xff, version & 0xff)
+}
+
+fn sdk_version(version: u32) -> String {
+    if version == 0 { "n/a".to_string() } else { format_version(version) }
+}
+
+/// `A.B.C.D.E` packed as `a24.b10.c10.d10.e10`, leaving out trailing zeros after `A.B`.
+fn source_version(version: u64) -> String {
+    let parts = [version >> 40, (version >> 30) & 0x3ff, (version >> 20) & 0x3ff, (version >> 10) & 0x3ff, version & 0x3ff];
+    let shown = parts.iter().rposition(|&part| part != 0).map_or(2, |last| (last + 1).max(2));
+
+    parts[..shown].iter().map(u64::to_string).collect::<Vec<_>>().join(".")
+}
+
+/// Formats a dylib timestamp like `ctime`, in UTC.
+fn timestamp(seconds: u32) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{} {} {:2} {:02}:{:02}:{:02} {}",
+        DAYS[(days % 7) as usize],
+        MONTHS[(month - 1) as usize],
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        year,
+    )
+}
+
+fn print_header(out: &mut impl Write, header: &MachHeader) -> std::io::Result<()> {
+    writeln!(out, "Mach header")?;
+    writeln!(out, "      magic  cputype cpusubtype  caps    filetype ncmds sizeofcmds      flags")?;
+    writeln!(
+        out,
+        " {:#010x} {:>8} {:>10}  0x{:02x} {:>11} {:>5} {:>10} {:#010x}",
+        header.magic,
+        header.cputype,
+        header.cpusubtype & 0x00ffffff,
+        (header.cpusubtype as u32) >> 24,
+        header.filetype,
+        header.ncmds,
+        header.sizeofcmds,
+        header.flags,
+    )
+}
+
+/// The `-hv` form, with names instead of numbers as far as they are known.
+fn print_symbolic_header(out: &mut impl Write, header: &MachHeader) -> std::io::Result<()> {
+    let magic = match header.magic {
+        MH_MAGIC => "MH_MAGIC".to_string(),
+        MH_MAGIC_64 => "MH_MAGIC_64".to_string(),
+        magic => format!("{:#010x}", magic),
+    };
+
+    // Subtypes are named after their CPU type, which the previous column shows
+    let cputype = header.cpu_type().to_string();
+    let cputype = cputype.trim_start_matches("CPU_TYPE_");
+    let cpusubtype = match header.cpu_subtype() {
+        CpuSubtype::Unknown(cpusubtype) => cpusubtype.to_string(),
+        known => {
+            let name = known.to_string();
+            let name = name.trim_start_matches("CPU_SUBTYPE_");
+            let name = name.strip_prefix(cputype).unwrap_or(name);
+            name.trim_start_matches('_').to_string()
+        }
+    };
+
+    let caps = match (header.cpu_type(), header.ptrauth_abi()) {
+        (_, Some(ptrauth_abi)) => format!("PAC{:02}", ptrauth_abi.version),
+        (CpuType::X86_64, None) if header.cpusubtype as u32 & CPU_SUBTYPE_LIB64 != 0 => "LIB64".to_string(),
+        (_, None) => format!("0x{:02x}", header.capabilities()),
+    };
+
+    let mut flags = header.header_flags().to_string();
+    let unknown = header.flags & !HeaderFlags::all().bits();
+    if unknown != 0 {
+        flags = format!("{} {:#x}", flags, unknown).trim_start().to_string();
+    }
+
+    writeln!(out, "Mach header")?;
+    writeln!(out, "      magic  cputype cpusubtype  caps    filetype ncmds sizeofcmds      flags")?;
+    writeln!(
+        out,
+        "{:>11} {:>8} {:>10} {:>5} {:>11} {:>5} {:>10}   {}",
+        magic,
+        cputype,
+        cpusubtype,
+        caps,
+        header.file_type().to_string().trim_start_matches("MH_"),
+        header.ncmds,
+        header.sizeofcmds,
+        flags,
+    )
+}
+
+fn print_section(out: &mut impl Write, section: &Section, is_64: bool) -> std::io::Result<()> {
+    writeln!(out, "Section")?;
+    writeln!(out, "  sectname {}", section.sectname)?;
+    writeln!(out, "   segname {}", section.segname)?;
+    if is_64 {
+        writeln!(out, "      addr {:#018x}", section.addr)?;
+        writeln!(out, "      size {:#018x}", section.size)?;
+    } else {
+        writeln!(out, "      addr {:#010x}", section.addr)?;
+        writeln!(out, "      size {:#010x}", section.size)?;
+    }
+    writeln!(out, "    offset {}", section.offset)?;
+    writeln!(out, "     align 2^{} ({})", section.align, 1u64 << section.align.min(63))?;
+    writeln!(out, "    reloff {}", section.reloff)?;
+    writeln!(out, "    nreloc {}", section.nreloc)?;
+    writeln!(out, "     flags {:#010x}", section.flags)?;
+
+    let section_type = section.flags & SECTION_TYPE;
+    let indirect = matches!(
+        section_type,
+        S_SYMBOL_STUBS
+            | S_LAZY_SYMBOL_POINTERS
+            | S_LAZY_DYLIB_SYMBOL_POINTERS
+            | S_NON_LAZY_SYMBOL_POINTERS
+            | S_THREAD_LOCAL_VARIABLE_POINTERS
+    );
+    if indirect {
+        writeln!(out, " reserved1 {} (index into indirect symbol table)", section.reserved1)?;
+    } else {
+        writeln!(out, " reserved1 {}", section.reserved1)?;
+    }
+    if section_type == S_SYMBOL_STUBS {
+        writeln!(out, " reserved2 {} (size of stubs)", section.reserved2)?;
+    } else {
+        writeln!(out, " reserved2 {}", section.reserved2)?;
+    }
+
+    Ok(())
+}
+
+fn print_segment(out: &mut impl Write, segment: &SegmentCommand) -> std::io::Result<()> {
+    let is_64 = segment.cmd == LC_SEGMENT_64;
+
+    writeln!(out, "  segname {}", segment.segname)?;
+    if is_64 {
+        writeln!(out, "   vmaddr {:#018x}", segment.vmaddr)?;
+        writeln!(out, "   vmsize {:#018x}", segment.vmsize)?;
+    } else {
+        writeln!(out, "   vmaddr {:#010x}", segment.vmaddr)?;
+        writeln!(out, "   vmsize {:#010x}", segment.vmsize)?;
+    }
+    writeln!(out, "  fileoff {}", segment.fileoff)?;
+    writeln!(out, " filesize {}", segment.filesize)?;
+    writeln!(out, "  maxprot {:#010x}", segment.maxprot)?;
+    writeln!(out, " initprot {:#010x}", segment.initprot)?;
+    writeln!(out, "   nsects {}", segment.sections.len())?;
+    writeln!(out, "    flags {:#x}", segment.flags)?;
+    for section in &segment.sections {
+        print_section(out, section, is_64)?;
+    }
+
+    Ok(())
+}
+
+/// Prints one load command the way `otool -l` does.
+fn print_command(out: &mut impl Write, index: usize, command: &Command, is_64: bool) -> std::io::Result<()> {
+    let cmd = command.cmd();
+    let name = command_name(cmd).map_or_else(|| format!("?({:#010x}) Unknown load command", cmd), str::to_string);
+    let cmdsize = match command {
+        Command::Unknown(command) => command.cmdsize,
+        command => command.cmdsize(is_64),
+    };
+
+    writeln!(out, "Load command {}", index)?;
+    match command {
+        Command::Segment(segment) => {
+            writeln!(out, "      cmd {}", name)?;
+            writeln!(out, "  cmdsize {}", cmdsize)?;
+            print_segment(out, segment)?;
+        }
+        Command::Dylib(dylib) => {
+            writeln!(out, "          cmd {}", name)?;
+            writeln!(out, "      cmdsize {}", cmdsize)?;
+            writeln!(out, "         name {} (offset {})", dylib.name, dylib.name_offset)?;
+            writeln!(out, "   time stamp {} {}", dylib.timestamp, timestamp(dylib.timestamp))?;
+            writeln!(out, "      current version {}", dylib_version(dylib.current_version))?;
+            writeln!(out, "compatibility version {}", dylib_version(dylib.compatibility_version))?;
+        }
+        Command::Dylinker(dylinker) => {
+            writeln!(out, "          cmd {}", name)?;
+            writeln!(out, "      cmdsize {}", cmdsize)?;
+            writeln!(out, "         name {} (offset {})", dylinker.name, dylinker.name_offset)?;
+        }
+        Command::Rpath(rpath) => {
+            writeln!(out, "          cmd {}", name)?;
+            writeln!(out, "      cmdsize {}", cmdsize)?;
+            writeln!(out, "         path {} (offset {})", rpath.path, rpath.path_offset)?;
+        }
+        Command::Symtab(symtab) => {
+            writeln!(out, "     cmd {}", name)?;
+            writeln!(out, " cmdsize {}", cmdsize)?;
+            writeln!(out, "  symoff {}", symtab.symoff)?;
+            writeln!(out, "   nsyms {}", symtab.nsyms)?;
+            writeln!(out, "  stroff {}", symtab.stroff)?;
+            writeln!(out, " strsize {}", symtab.strsize)?;
+        }
+        Command::Dysymtab(dysymtab) => {
+            writeln!(out, "            cmd {}", name)?;
+            writeln!(out, "        cmdsize {}", cmdsize)?;
+            writeln!(out, "      ilocalsym {}", dysymtab.ilocalsym)?;
+            writeln!(out, "      nlocalsym {}", dysymtab.nlocalsym)?;
+            writeln!(out, "     iextdefsym {}", dysymtab.iextdefsym)?;
+            writeln!(out, "     nextdefsym {}", dysymtab.nextdefsym)?;
+            writeln!(out, "      iundefsym {}", dysymtab.iundefsym)?;
+            writeln!(out, "      nundefsym {}", dysymtab.nundefsym)?;
+            writeln!(out, "         tocoff {}", dysymtab.tocoff)?;
+            writeln!(out, "           ntoc {}", dysymtab.ntoc)?;
+            writeln!(out, "      modtaboff {}", dysymtab.modtaboff)?;
+            writeln!(out, "        nmodtab {}", dysymtab.nmodtab)?;
+            writeln!(out, "   extrefsymoff {}", dysymtab.extrefsymoff)?;
+            writeln!(out, "    nextrefsyms {}", dysymtab.nextrefsyms)?;
+            writeln!(out, " indirectsymoff {}", dysymtab.indirectsymoff)?;
+            writeln!(out, "  nindirectsyms {}", dysymtab.nindirectsyms)?;
+            writeln!(out, "      extreloff {}", dysymtab.extreloff)?;
+            writeln!(out, "        nextrel {}", dysymtab.nextrel)?;
+            writeln!(out, "      locreloff {}", dysymtab.locreloff)?;
+            writeln!(out, "        nlocrel {}", dysymtab.nlocrel)?;
+        }
+        Command::DyldInfo(dyld_info) => {
+            writeln!(out, "            cmd {}", name)?;
+            writeln!(out, "        cmdsize {}", cmdsize)?;
+            writeln!(out, "     rebase_off {}", dyld_info.rebase_off)?;
+            writeln!(out, "    rebase_size {}", dyld_info.rebase_size)?;
+            writeln!(out, "       bind_off {}", dyld_info.bind_off)?;
+            writeln!(out, "      bind_size {}", dyld_info.bind_size)?;
+            writeln!(out, "  weak_bind_off {}", dyld_info.weak_bind_off)?;
+            writeln!(out, " weak_bind_size {}", dyld_info.weak_bind_size)?;
+            writeln!(out, "  lazy_bind_off {}", dyld_info.lazy_bind_off)?;
+            writeln!(out, " lazy_bind_size {}", dyld_info.lazy_bind_size)?;
+            writeln!(out, "     export_off {}", dyld_info.export_off)?;
+            writeln!(out, "    export_size {}", dyld_info.export_size)?;
+        }
+        Command::Uuid(uuid) => {
+            writeln!(out, "     cmd {}", name)?;
+            writeln!(out, " cmdsize {}", cmdsize)?;
+            writeln!(out, "    uuid {}", uuid)?;
+        }
+        Command::BuildVersion(build_version) => {
+            writeln!(out, "      cmd {}", name)?;
+            writeln!(out, "  cmdsize {}", cmdsize)?;
+            writeln!(out, " platform {}", build_version.platform)?;
+            writeln!(out, "    minos {}", format_version(build_version.minos))?;
+            writeln!(out, "      sdk {}", sdk_version(build_version.sdk))?;
+            writeln!(out, "   ntools {}", build_version.tools.len())?;
+            for tool in &build_version.tools {
+                writeln!(out, "     tool {}", tool.tool)?;
+                writeln!(out, "  version {}", format_version(tool.version))?;
+            }
+        }
+        Command::VersionMin(version_min) => {
+            writeln!(out, "      cmd {}", name)?;
+            writeln!(out, "  cmdsize {}", cmdsize)?;
+            writeln!(out, "  version {}", format_version(version_min.version))?;
+            writeln!(out, "      sdk {}", sdk_version(version_min.sdk))?;
+        }
+        Command::SourceVersion(source) => {
+            writeln!(out, "      cmd {}", name)?;
+            writeln!(out, "  cmdsize {}", cmdsize)?;
+            writeln!(out, "  version {}", source_version(source.version))?;
+        }
+        Command::Main(main) => {
+            writeln!(out, "       cmd {}", name)?;
+            writeln!(out, "   cmdsize {}", cmdsize)?;
+            writeln!(out, "  entryoff {}", main.entryoff)?;
+            writeln!(out, " stacksize {}", main.stacksize)?;
+        }
+        Command::LinkeditData(linkedit) => {
+            writeln!(out, "      cmd {}", name)?;
+            writeln!(out, "  cmdsize {}", cmdsize)?;
+            writeln!(out, "  dataoff {}", linkedit.dataoff)?;
+            writeln!(out, " datasize {}", linkedit.datasize)?;
+        }
+        Command::EncryptionInfo(encryption) => {
+            writeln!(out, "          cmd {}", name)?;
+            writeln!(out, "      cmdsize {}", cmdsize)?;
+            writeln!(out, "     cryptoff {}", encryption.cryptoff)?;
+            writeln!(out, "    cryptsize {}", encryption.cryptsize)?;
+            writeln!(out, "      cryptid {}", encryption.cryptid)?;
+            if cmdsize >= 24 {
+                writeln!(out, "          pad {}", encryption.pad)?;
+            }
+        }
+        Command::LinkerOption(linker_option) => {
+            writeln!(out, "     cmd {}", name)?;
+            writeln!(out, " cmdsize {}", cmdsize)?;
+            writeln!(out, "   count {}", linker_option.strings.len())?;
+            for (number, string) in (1..).zip(&linker_option.strings) {
+                writeln!(out, "  string #{} {}", number, string)?;
+            }
+        }
+        Command::Unknown(_) => {
+            writeln!(out, "      cmd {}", name)?;
+            writeln!(out, "  cmdsize {}", cmdsize)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a library line of `otool -L`.
+fn print_library(out: &mut impl Write, dylib: &DylibCommand) -> std::io::Result<()> {
+    let kind = match dylib.kind() {
+        DylibKind::Weak => ", weak",
+        DylibKind::Reexport => ", reexport",
+        DylibKind::Upward => ", upward",
+        DylibKind::Lazy => ", lazy",
+        DylibKind::Id | DylibKind::Load => "",
+    };
+
+    writeln!(
+        out,
+        "\t{} (compatibility version {}, current version {}{})",
+        dylib.name,
+        dylib_version(dylib.compatibility_version),
+        dylib_version(dylib.current_version),
+        kind,
+    )
+}
+
+fn print_image(out: &mut impl Write, image: &Image, options: &Options) -> std::io::Result<()> {
+    if options.header {
+        if options.verbose {
+            print_symbolic_header(out, &image.header)?;
+        } else {
+            print_header(out, &image.header)?;
+        }
+    }
+    if options.load_commands {
+        for (index, command) in image.commands.iter().enumerate() {
+            print_command(out, index, command, image.is_64)?;
+        }
+    }
+    if options.libraries {
+        // A dylib's own install name comes first, as with otool
+        for dylib in image.id_dylib().into_iter().chain(image.dependencies()) {
+            print_library(out, dylib)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_file(path: &str, options: &Options) -> stealthemoon::Result<()> {
+    let macho = MachO::parse(std::fs::read(path)?)?;
+    let mut out = std::io::stdout().lock();
+
+    #[cfg(feature = "serde")]
+    if options.json {
+        let inspection = FileInspection { path, inspection: stealthemoon::Inspection::new(&macho)? };
+        serde_json::to_writer_pretty(&mut out, &inspection).map_err(std::io::Error::from)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    for image in macho.images()? {
+        if macho.is_fat() {
+            let arch = describe_arch(image.header.cputype, image.header.cpusubtype);
+            writeln!(out, "{} (architecture {}):", path, arch)?;
+        } else {
+            writeln!(out, "{}:", path)?;
+        }
+        print_image(&mut out, &image, options)?;
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            report("otool", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    for file in &options.files {
+        if let Err(e) = print_file(file, &options) {
+            report("otool", &format!("{}: {}", file, e));
+            status = 1;
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use stealthemoon::commands::{
+        RpathCommand, LC_ID_DYLIB, LC_LAZY_LOAD_DYLIB, LC_LOAD_DYLIB, LC_LOAD_UPWARD_DYLIB, LC_LOAD_WEAK_DYLIB,
+        LC_REEXPORT_DYLIB,
+    };
+    use stealthemoon::header::{CPU_TYPE_ARM64, MH_DYLIB};
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn dylib(cmd: u32, name: &str) -> DylibCommand {
+        DylibCommand {
+            cmd,
+            cmdsize: DylibCommand::size_for(name, true),
+            name_offset: 24,
+            name: name.to_string(),
+            timestamp: 2,
+            current_version: 0x05016401,
+            compatibility_version: 0x10000,
+        }
+    }
+
+    fn printed(print: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>) -> String {
+        let mut out = Vec::new();
+        print(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn single_letter_options_can_be_grouped() {
+        let options = parse_args(&args(&["-hv", "-l", "a.out", "libfoo.dylib"])).unwrap();
+
+        assert!(options.header && options.verbose && options.load_commands);
+        assert!(!options.libraries && !options.json);
+        assert_eq!(options.files, ["a.out", "libfoo.dylib"]);
+    }
+
+    #[test]
+    fn malformed_command_lines_are_rejected() {
+        let error = |list: &[&str]| parse_args(&args(list)).err().unwrap();
+
+        assert_eq!(error(&["a.out"]), "one of -h, -l, -L or --json must be specified");
+        assert_eq!(error(&["-v", "a.out"]), "one of -h, -l, -L or --json must be specified");
+        assert_eq!(error(&["-l"]), "missing input file");
+        assert_eq!(error(&["-lx", "a.out"]), "unknown option: -lx");
+    }
+
+    #[test]
+    fn load_commands_print_like_otool() {
+        let rpath = Command::Rpath(RpathCommand::new("@loader_path/../lib", true));
+        let dylib = Command::Dylib(dylib(LC_LOAD_DYLIB, "/usr/lib/libSystem.B.dylib"));
+
+        assert_eq!(
+            printed(|out| print_command(out, 3, &rpath, true)),
+            "Load command 3\n          cmd LC_RPATH\n      cmdsize 32\n         path @loader_path/../lib (offset 12)\n"
+        );
+        assert_eq!(
+            printed(|out| print_command(out, 4, &dylib, true)),
+            "Load command 4\n          cmd LC_LOAD_DYLIB\n      cmdsize 56\n\
+             \x20        name /usr/lib/libSystem.B.dylib (offset 24)\n\
+             \x20  time stamp 2 Thu Jan  1 00:00:02 1970\n\
+             \x20     current version 1281.100.1\n\
+             compatibility version 1.0.0\n"
+        );
+    }
+
+    #[test]
+    fn timestamps_print_like_ctime_in_utc() {
+        assert_eq!(timestamp(0), "Thu Jan  1 00:00:00 1970");
+        assert_eq!(timestamp(951_782_400), "Tue Feb 29 00:00:00 2000");
+        assert_eq!(timestamp(1_700_000_000), "Tue Nov 14 22:13:20 2023");
+    }
+
+    #[test]
+    fn source_versions_drop_trailing_zeros_after_the_minor() {
+        assert_eq!(source_version(0), "0.0");
+        assert_eq!(source_version(1205 << 40 | 1 << 30), "1205.1");
+        assert_eq!(source_version(1205 << 40 | 1 << 30 | 3), "1205.1.0.0.3");
+        assert_eq!(source_version(1205 << 40 | 2 << 20), "1205.0.2");
+    }
+
+    #[test]
+    fn libraries_print_the_install_name_first_with_their_load_kind() {
+        let header = MachHeader {
+            magic: MH_MAGIC_64,
+            cputype: CPU_TYPE_ARM64,
+            cpusubtype: 0,
+            filetype: MH_DYLIB,
+            ncmds: 6,
+            sizeofcmds: 0,
+            flags: 0,
+            reserved: 0,
+        };
+        let commands = [
+            (LC_LOAD_DYLIB, "/usr/lib/libSystem.B.dylib"),
+            (LC_LOAD_WEAK_DYLIB, "/usr/lib/libweak.dylib"),
+            (LC_REEXPORT_DYLIB, "@rpath/libreexport.dylib"),
+            (LC_LOAD_UPWARD_DYLIB, "@rpath/libupward.dylib"),
+            (LC_LAZY_LOAD_DYLIB, "@rpath/liblazy.dylib"),
+            (LC_ID_DYLIB, "@rpath/libfoo.dylib"),
+        ];
+        let commands = commands.iter().map(|&(cmd, name)| Command::Dylib(dylib(cmd, name))).collect();
+        let image = Image { header, is_64: true, is_little_endian: true, commands, data: Vec::new() };
+        let options = parse_args(&args(&["-L", "libfoo.dylib"])).unwrap();
+
+        assert_eq!(
+            printed(|out| print_image(out, &image, &options)),
+            "\t@rpath/libfoo.dylib (compatibility version 1.0.0, current version 1281.100.1)\n\
+             \t/usr/lib/libSystem.B.dylib (compatibility version 1.0.0, current version 1281.100.1)\n\
+             \t/usr/lib/libweak.dylib (compatibility version 1.0.0, current version 1281.100.1, weak)\n\
+             \t@rpath/libreexport.dylib (compatibility version 1.0.0, current version 1281.100.1, reexport)\n\
+             \t@rpath/libupward.dylib (compatibility version 1.0.0, current version 1281.100.1, upward)\n\
+             \t@rpath/liblazy.dylib (compatibility version 1.0.0, current version 1281.100.1, lazy)\n"
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use stealthemoon::MachO;
+
+use super::{file_name, report};
+
+const USAGE: &str = "Usage: relocate old_prefix new_prefix file ...";
+
+/// Replaces the prefix in memory and only writes the file back once every
+/// slice has been edited.
+fn relocate_file(path: &str, old_prefix: &str, new_prefix: &str) -> stealthemoon::Result<usize> {
+    let mut macho = MachO::parse(std::fs::read(path)?)?;
+    macho.set_signing_identifier(file_name(path));
+
+    let count = macho.replace_prefix(old_prefix.as_bytes(), new_prefix.as_bytes())?;
+    if count > 0 {
+        std::fs::write(path, macho.into_bytes())?;
+    }
+
+    Ok(count)
+}
+
+pub fn run(args: &[String]) -> i32 {
+    if let Some(option) = args.iter().find(|arg| arg.starts_with('-')) {
+        report("relocate", &format!("unknown option: {}", option));
+        eprintln!("{}", USAGE);
+        return 1;
+    }
+    let [old_prefix, new_prefix, files @ ..] = args else {
+        report("relocate", "missing prefixes");
+        eprintln!("{}", USAGE);
+        return 1;
+    };
+    if files.is_empty() {
+        report("relocate", "missing input file");
+        eprintln!("{}", USAGE);
+        return 1;
+    }
+
+    let mut status = 0;
+    for file in files {
+        match relocate_file(file, old_prefix, new_prefix) {
+            Ok(count) => println!("{}: {} strings replaced", file, count),
+            Err(e) => {
+                report("relocate", &format!("{}: {}", file, e));
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
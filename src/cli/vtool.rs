@@ -0,0 +1,297 @@
+use std::io::Write;
+
+use stealthemoon::commands::{command_name, BuildToolVersion, BuildVersionCommand, Command};
+use stealthemoon::header::describe_arch;
+use stealthemoon::version::{format_version, parse_platform, parse_tool, parse_version, platform_name, tool_name};
+use stealthemoon::{Image, MachO};
+
+use super::{file_name, report};
+
+const USAGE: &str = "Usage: vtool [-show | -show-build] file ...\n       \
+vtool [-set-build-version platform minos sdk [-tool tool version] ...] [-set-version-min platform minos sdk] \
+[-remove-build-version platform] [-retarget platform [-minos version] [-sdk version]] [-replace] \
+[-output file] file ...";
+
+/// One `vtool` edit.
+#[derive(Debug)]
+enum Edit {
+    SetBuildVersion(BuildVersionCommand),
+    SetVersionMin { platform: u32, minos: u32, sdk: u32 },
+    RemoveBuildVersion(u32),
+    Retarget { platform: u32, minos: Option<u32>, sdk: Option<u32> },
+}
+
+struct Options {
+    show: bool,
+    edits: Vec<Edit>,
+    replace: bool,
+    output: Option<String>,
+    files: Vec<String>,
+}
+
+fn platform(value: String) -> Result<u32, String> {
+    parse_platform(&value).ok_or_else(|| format!("unknown platform: {}", value))
+}
+
+fn version(value: String) -> Result<u32, String> {
+    parse_version(&value).ok_or_else(|| format!("malformed version number: {}", value))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options { show: false, edits: Vec::new(), replace: false, output: None, files: Vec::new() };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().cloned().ok_or_else(|| format!("missing argument to: {} option", option));
+
+        match arg.as_str() {
+            "-show" | "-show-build" => options.show = true,
+            "-set-build-version" => {
+                let platform = platform(value(arg)?)?;
+                let (minos, sdk) = (version(value(arg)?)?, version(value(arg)?)?);
+                options.edits.push(Edit::SetBuildVersion(BuildVersionCommand { platform, minos, sdk, tools: Vec::new() }));
+            }
+            "-tool" => {
+                let tool = value(arg)?;
+                let tool = parse_tool(&tool).ok_or_else(|| format!("unknown tool: {}", tool))?;
+                let version = version(value(arg)?)?;
+                match options.edits.last_mut() {
+                    Some(Edit::SetBuildVersion(build_version)) => {
+                        build_version.tools.push(BuildToolVersion { tool, version })
+                    }
+                    _ => return Err("-tool must follow -set-build-version".to_string()),
+                }
+            }
+            "-set-version-min" => {
+                let platform = platform(value(arg)?)?;
+                let (minos, sdk) = (version(value(arg)?)?, version(value(arg)?)?);
+                options.edits.push(Edit::SetVersionMin { platform, minos, sdk });
+            }
+            "-retarget" => {
+                let platform = platform(value(arg)?)?;
+                options.edits.push(Edit::Retarget { platform, minos: None, sdk: None });
+            }
+            "-minos" | "-sdk" => {
+                let parsed = version(value(arg)?)?;
+                match options.edits.last_mut() {
+                    Some(Edit::Retarget { minos, .. }) if arg == "-minos" => *minos = Some(parsed),
+                    Some(Edit::Retarget { sdk, .. }) => *sdk = Some(parsed),
+                    _ => return Err(format!("{} must follow -retarget", arg)),
+                }
+            }
+            "-remove-build-version" => options.edits.push(Edit::RemoveBuildVersion(platform(value(arg)?)?)),
+            "-replace" => options.replace = true,
+            "-output" | "-o" => options.output = Some(value(arg)?),
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            file => options.files.push(file.to_string()),
+        }
+    }
+
+    match (options.show, options.edits.is_empty() && !options.replace) {
+        (true, false) => return Err("-show cannot be combined with edits".to_string()),
+        (false, true) => return Err("one of -show or an edit must be specified".to_string()),
+        _ => {}
+    }
+    // -replace drops every version command before the edits run, which would
+    // leave -retarget nothing to convert
+    if options.replace && options.edits.iter().any(|edit| matches!(edit, Edit::Retarget { .. })) {
+        return Err("-replace cannot be combined with -retarget, which replaces the version command itself".to_string());
+    }
+    if options.files.is_empty() {
+        return Err("missing input file".to_string());
+    }
+    if options.output.is_some() && options.files.len() > 1 {
+        return Err("-output needs exactly one input file".to_string());
+    }
+
+    Ok(options)
+}
+
+/// Prints the version commands the way `vtool -show-build` does, with
+/// platforms and tools by name.
+fn show_image(out: &mut impl Write, image: &Image) -> std::io::Result<()> {
+    for (index, command) in image.commands.iter().enumerate() {
+        let name = command_name(command.cmd()).unwrap_or("?");
+
+        match command {
+            Command::BuildVersion(build_version) => {
+                writeln!(out, "Load command {}", index)?;
+                writeln!(out, "      cmd {}", name)?;
+                writeln!(out, "  cmdsize {}", command.cmdsize(image.is_64))?;
+                writeln!(out, " platform {}", platform_label(build_version.platform))?;
+                writeln!(out, "    minos {}", format_version(build_version.minos))?;
+                writeln!(out, "      sdk {}", sdk_label(build_version.sdk))?;
+                writeln!(out, "   ntools {}", build_version.tools.len())?;
+                for tool in &build_version.tools {
+                    match tool_name(tool.tool) {
+                        Some(name) => writeln!(out, "     tool {}", name)?,
+                        None => writeln!(out, "     tool {}", tool.tool)?,
+                    }
+                    writeln!(out, "  version {}", format_version(tool.version))?;
+                }
+            }
+            Command::VersionMin(version_min) => {
+                writeln!(out, "Load command {}", index)?;
+                writeln!(out, "      cmd {}", name)?;
+                writeln!(out, "  cmdsize {}", command.cmdsize(image.is_64))?;
+                writeln!(out, "  version {}", format_version(version_min.version))?;
+                writeln!(out, "      sdk {}", sdk_label(version_min.sdk))?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn platform_label(platform: u32) -> String {
+    platform_name(platform).map_or_else(|| platform.to_string(), str::to_string)
+}
+
+fn sdk_label(sdk: u32) -> String {
+    if sdk == 0 { "n/a".to_string() } else { format_version(sdk) }
+}
+
+fn show_file(path: &str) -> stealthemoon::Result<()> {
+    let macho = MachO::parse(std::fs::read(path)?)?;
+    let mut out = std::io::stdout().lock();
+
+    for image in macho.images()? {
+        if macho.is_fat() {
+            let arch = describe_arch(image.header.cputype, image.header.cpusubtype);
+            writeln!(out, "{} (architecture {}):", path, arch)?;
+        } else {
+            writeln!(out, "{}:", path)?;
+        }
+        show_image(&mut out, &image)?;
+    }
+
+    Ok(())
+}
+
+/// Applies the edits to an in-memory copy and writes it to the output, or
+/// back to the input, once all of them have succeeded.
+fn edit_file(path: &str, options: &Options) -> stealthemoon::Result<()> {
+    let mut macho = MachO::parse(std::fs::read(path)?)?;
+    macho.set_signing_identifier(file_name(options.output.as_deref().unwrap_or(path)));
+
+    if options.replace {
+        macho.remove_build_version(None)?;
+    }
+    for edit in &options.edits {
+        match edit {
+            Edit::SetBuildVersion(build_version) => macho.set_build_version(build_version)?,
+            Edit::SetVersionMin { platform, minos, sdk } => macho.set_version_min(*platform, *minos, *sdk)?,
+            Edit::RemoveBuildVersion(platform) => macho.remove_build_version(Some(*platform))?,
+            Edit::Retarget { platform, minos, sdk } => macho.retarget_platform(*platform, *minos, *sdk)?,
+        }
+    }
+
+    std::fs::write(options.output.as_deref().unwrap_or(path), macho.into_bytes())?;
+
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            report("vtool", &message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    for file in &options.files {
+        let result = if options.show { show_file(file) } else { edit_file(file, &options) };
+        if let Err(e) = result {
+            report("vtool", &format!("{}: {}", file, e));
+            status = 1;
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use stealthemoon::version::{PLATFORM_MACCATALYST, PLATFORM_MACOS, TOOL_LD};
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn error(list: &[&str]) -> String {
+        parse_args(&args(list)).err().unwrap()
+    }
+
+    #[test]
+    fn tools_belong_to_the_build_version_before_them() {
+        let options = parse_args(&args(&[
+            "-set-build-version", "macos", "11.0", "14.2", "-tool", "ld", "1015.7",
+            "-remove-build-version", "maccatalyst",
+            "-output", "out.dylib",
+            "in.dylib",
+        ]))
+        .unwrap();
+
+        assert!(matches!(
+            options.edits.as_slice(),
+            [
+                Edit::SetBuildVersion(BuildVersionCommand {
+                    platform: PLATFORM_MACOS,
+                    minos: 0xb0000,
+                    sdk: 0xe0200,
+                    tools,
+                }),
+                Edit::RemoveBuildVersion(PLATFORM_MACCATALYST),
+            ] if tools.len() == 1 && tools[0].tool == TOOL_LD && tools[0].version == 0x3f70700
+        ), "{:?}", options.edits);
+        assert_eq!(options.output.as_deref(), Some("out.dylib"));
+        assert_eq!(error(&["-tool", "ld", "1.0", "in.dylib"]), "-tool must follow -set-build-version");
+    }
+
+    #[test]
+    fn retarget_takes_its_versions_from_the_options_after_it() {
+        let options = parse_args(&args(&["-retarget", "maccatalyst", "-minos", "14.0", "in.o"])).unwrap();
+
+        assert!(matches!(
+            options.edits.as_slice(),
+            [Edit::Retarget { platform: PLATFORM_MACCATALYST, minos: Some(0xe0000), sdk: None }]
+        ));
+        assert_eq!(error(&["-sdk", "14.0", "in.o"]), "-sdk must follow -retarget");
+    }
+
+    #[test]
+    fn replace_cannot_be_combined_with_retarget() {
+        // -replace on its own removes every version command
+        let options = parse_args(&args(&["-replace", "in.dylib"])).unwrap();
+        assert!(options.replace && options.edits.is_empty());
+
+        assert_eq!(
+            error(&["-replace", "-retarget", "maccatalyst", "in.o"]),
+            "-replace cannot be combined with -retarget, which replaces the version command itself"
+        );
+        assert_eq!(
+            error(&["-retarget", "macos", "-replace", "in.o"]),
+            "-replace cannot be combined with -retarget, which replaces the version command itself"
+        );
+    }
+
+    #[test]
+    fn malformed_command_lines_are_rejected() {
+        assert_eq!(error(&["in.dylib"]), "one of -show or an edit must be specified");
+        assert_eq!(error(&["-show", "-replace", "in.dylib"]), "-show cannot be combined with edits");
+        assert_eq!(error(&["-set-build-version", "macos", "11.0"]), "missing argument to: -set-build-version option");
+        assert_eq!(error(&["-set-build-version", "plan9", "11.0", "14.0", "in"]), "unknown platform: plan9");
+        assert_eq!(
+            error(&["-set-build-version", "macos", "11.0.0.1", "14.0", "in"]),
+            "malformed version number: 11.0.0.1"
+        );
+        assert_eq!(error(&["-replace", "-output", "out", "a", "b"]), "-output needs exactly one input file");
+        assert_eq!(error(&["-replace"]), "missing input file");
+    }
+}
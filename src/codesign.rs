@@ -0,0 +1,451 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use sha2::{Digest, Sha256};
+
+use crate::commands::{Command, LinkeditDataCommand, SegmentCommand, LC_CODE_SIGNATURE};
+use crate::edit::edit_load_commands;
+use crate::error::{Error, Result};
+use crate::header::{FileType, CPU_TYPE_ARM, CPU_TYPE_ARM64, CPU_TYPE_ARM64_32};
+use crate::image::Image;
+
+pub const CSMAGIC_REQUIREMENT: u32 = 0xfade0c00;
+pub const CSMAGIC_REQUIREMENTS: u32 = 0xfade0c01;
+pub const CSMAGIC_CODEDIRECTORY: u32 = 0xfade0c02;
+pub const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade0cc0;
+pub const CSMAGIC_EMBEDDED_ENTITLEMENTS: u32 = 0xfade7171;
+pub const CSMAGIC_EMBEDDED_DER_ENTITLEMENTS: u32 = 0xfade7172;
+pub const CSMAGIC_BLOBWRAPPER: u32 = 0xfade0b01;
+
+pub const CSSLOT_CODEDIRECTORY: u32 = 0;
+pub const CSSLOT_INFOSLOT: u32 = 1;
+pub const CSSLOT_REQUIREMENTS: u32 = 2;
+pub const CSSLOT_RESOURCEDIR: u32 = 3;
+pub const CSSLOT_APPLICATION: u32 = 4;
+pub const CSSLOT_ENTITLEMENTS: u32 = 5;
+pub const CSSLOT_DER_ENTITLEMENTS: u32 = 7;
+pub const CSSLOT_ALTERNATE_CODEDIRECTORIES: u32 = 0x1000;
+pub const CSSLOT_ALTERNATE_CODEDIRECTORY_MAX: u32 = 5;
+pub const CSSLOT_SIGNATURESLOT: u32 = 0x10000;
+
+pub const CS_ADHOC: u32 = 0x2;
+pub const CS_HARD: u32 = 0x100;
+pub const CS_KILL: u32 = 0x200;
+pub const CS_CHECK_EXPIRATION: u32 = 0x400;
+pub const CS_RESTRICT: u32 = 0x800;
+pub const CS_ENFORCEMENT: u32 = 0x1000;
+pub const CS_REQUIRE_LV: u32 = 0x2000;
+pub const CS_RUNTIME: u32 = 0x10000;
+pub const CS_LINKER_SIGNED: u32 = 0x20000;
+
+pub const CS_HASHTYPE_SHA1: u8 = 1;
+pub const CS_HASHTYPE_SHA256: u8 = 2;
+pub const CS_HASHTYPE_SHA256_TRUNCATED: u8 = 3;
+pub const CS_HASHTYPE_SHA384: u8 = 4;
+pub const CS_SHA256_LEN: usize = 32;
+
+pub const CS_EXECSEG_MAIN_BINARY: u64 = 0x1;
+
+/// `CS_SUPPORTSEXECSEG`, the CodeDirectory version that carries the executable segment fields.
+pub const CODEDIRECTORY_VERSION: u32 = 0x20400;
+
+/// Code is hashed in pages of `1 << PAGE_SIZE_SHIFT` bytes.
+const PAGE_SIZE_SHIFT: u8 = 12;
+
+/// Size of `CS_CodeDirectory` at version 0x20400, before the identifier.
+const CODEDIRECTORY_HEADER_SIZE: u32 = 88;
+
+/// What goes into an ad-hoc signature besides the code hashes.
+#[derive(Debug, Clone, Default)]
+pub struct SigningOptions {
+    /// The signing identifier, usually the file name or a bundle identifier.
+    pub identifier: String,
+    /// Entitlements as an XML property list.
+    pub entitlements: Option<Vec<u8>>,
+    /// Entitlements in their DER encoding.
+    pub der_entitlements: Option<Vec<u8>>,
+}
+
+impl SigningOptions {
+    /// Takes over the identifier and entitlements of the signature the file
+    /// already carries, so that re-signing after an edit keeps them.
+    pub fn from_existing(image: &Image) -> Option<SigningOptions> {
+        let signature = image.code_signature().ok()??;
+
+        Some(SigningOptions {
+            identifier: signature.code_directory()?.identifier.clone(),
+            entitlements: signature.entitlements().map(<[u8]>::to_vec),
+            der_entitlements: signature.der_entitlements().map(<[u8]>::to_vec),
+        })
+    }
+}
+
+/// The bytes `LC_CODE_SIGNATURE` points at, if the image has one.
+pub fn existing_signature(image: &Image) -> Option<&[u8]> {
+    let command = code_signature_command(&image.commands)?;
+    let start = command.dataoff as usize;
+
+    image.data.get(start..start + command.datasize as usize)
+}
+
+fn code_signature_command(commands: &[Command]) -> Option<&LinkeditDataCommand> {
+    commands.iter().find_map(|command| match command {
+        Command::LinkeditData(linkedit) if linkedit.cmd == LC_CODE_SIGNATURE => Some(linkedit),
+        _ => None,
+    })
+}
+
+/// Wraps `payload` in a blob header: magic and total length.
+fn blob(magic: u32, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + payload.len());
+    bytes.write_u32::<BigEndian>(magic).unwrap();
+    bytes.write_u32::<BigEndian>(8 + payload.len() as u32).unwrap();
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// An empty requirement set, which is what `codesign -s -` embeds.
+fn empty_requirements() -> Vec<u8> {
+    blob(CSMAGIC_REQUIREMENTS, &0u32.to_be_bytes())
+}
+
+/// Signs a thin image ad hoc, replacing any signature it already has.
+///
+/// The signature goes at the end of `__LINKEDIT`: `LC_CODE_SIGNATURE` is added
+/// when missing (which needs 16 bytes of header padding), the segment is grown
+/// or shrunk to cover the new blob, and the file is truncated right after it.
+pub(crate) fn sign_adhoc(data: &mut Vec<u8>, options: &SigningOptions) -> Result<()> {
+    let image = Image::parse(data)?;
+
+    let linkedit = image
+        .commands
+        .iter()
+        .find_map(|command| match command {
+            Command::Segment(segment) if segment.segname == "__LINKEDIT" => Some(segment),
+            _ => None,
+        })
+        .ok_or(Error::NoLinkedit)?;
+    let text = image.commands.iter().find_map(|command| match command {
+        Command::Segment(segment) if segment.segname == "__TEXT" => Some(segment),
+        _ => None,
+    });
+
+    // Reuse the old signature's spot, otherwise start right after the linkedit data
+    let dataoff = match code_signature_command(&image.commands) {
+        Some(command) => command.dataoff as u64,
+        None => (linkedit.fileoff + linkedit.filesize + 15) & !15,
+    };
+
+    let requirements = empty_requirements();
+    let entitlements = options.entitlements.as_ref().map(|xml| blob(CSMAGIC_EMBEDDED_ENTITLEMENTS, xml));
+    let der_entitlements = options.der_entitlements.as_ref().map(|der| blob(CSMAGIC_EMBEDDED_DER_ENTITLEMENTS, der));
+    let cms = blob(CSMAGIC_BLOBWRAPPER, &[]);
+
+    // Special slots run from 1 up to the highest one in use, unused ones hash to zeros
+    let mut special_slots: Vec<(u32, &[u8])> = vec![(CSSLOT_REQUIREMENTS, &requirements)];
+    if let Some(entitlements) = &entitlements {
+        special_slots.push((CSSLOT_ENTITLEMENTS, entitlements));
+    }
+    if let Some(der_entitlements) = &der_entitlements {
+        special_slots.push((CSSLOT_DER_ENTITLEMENTS, der_entitlements));
+    }
+    let n_special_slots = special_slots.iter().map(|(slot, _)| *slot).max().unwrap_or(0);
+
+    let page_size = 1u64 << PAGE_SIZE_SHIFT;
+    let n_code_slots = dataoff.div_ceil(page_size) as u32;
+    let hash_offset = CODEDIRECTORY_HEADER_SIZE + options.identifier.len() as u32 + 1 + n_special_slots * CS_SHA256_LEN as u32;
+    let code_directory_size = hash_offset + n_code_slots * CS_SHA256_LEN as u32;
+
+    let mut blobs: Vec<(u32, u32)> = vec![(CSSLOT_CODEDIRECTORY, code_directory_size)];
+    for (slot, bytes) in &special_slots {
+        blobs.push((*slot, bytes.len() as u32));
+    }
+    blobs.push((CSSLOT_SIGNATURESLOT, cms.len() as u32));
+    let superblob_size = 12 + blobs.len() as u32 * 8 + blobs.iter().map(|(_, size)| size).sum::<u32>();
+    let datasize = (superblob_size + 15) & !15;
+
+    // The header and load commands are hashed too, so update them before hashing
+    let cputype = image.header.cputype;
+    edit_load_commands(data, |commands, _| {
+        for command in commands.iter_mut() {
+            match command {
+                Command::Segment(segment) if segment.segname == "__LINKEDIT" => {
+                    set_segment_end(segment, dataoff + datasize as u64, cputype)?;
+                }
+                Command::LinkeditData(linkedit) if linkedit.cmd == LC_CODE_SIGNATURE => {
+                    linkedit.datasize = datasize;
+                }
+                _ => {}
+            }
+        }
+        if code_signature_command(commands).is_none() {
+            commands.push(Command::LinkeditData(LinkeditDataCommand {
+                cmd: LC_CODE_SIGNATURE,
+                dataoff: dataoff as u32,
+                datasize,
+            }));
+        }
+        Ok(())
+    })?;
+    data.resize(dataoff as usize, 0);
+
+    let mut code_directory = Vec::with_capacity(code_directory_size as usize);
+    code_directory.write_u32::<BigEndian>(CSMAGIC_CODEDIRECTORY).unwrap();
+    code_directory.write_u32::<BigEndian>(code_directory_size).unwrap();
+    code_directory.write_u32::<BigEndian>(CODEDIRECTORY_VERSION).unwrap();
+    code_directory.write_u32::<BigEndian>(CS_ADHOC).unwrap();
+    code_directory.write_u32::<BigEndian>(hash_offset).unwrap();
+    code_directory.write_u32::<BigEndian>(CODEDIRECTORY_HEADER_SIZE).unwrap(); // identOffset
+    code_directory.write_u32::<BigEndian>(n_special_slots).unwrap();
+    code_directory.write_u32::<BigEndian>(n_code_slots).unwrap();
+    code_directory.write_u32::<BigEndian>(dataoff.min(u32::MAX as u64) as u32).unwrap(); // codeLimit
+    code_directory.write_u8(CS_SHA256_LEN as u8).unwrap();
+    code_directory.write_u8(CS_HASHTYPE_SHA256).unwrap();
+    code_directory.write_u8(0).unwrap(); // platform
+    code_directory.write_u8(PAGE_SIZE_SHIFT).unwrap();
+    code_directory.write_u32::<BigEndian>(0).unwrap(); // spare2
+    code_directory.write_u32::<BigEndian>(0).unwrap(); // scatterOffset
+    code_directory.write_u32::<BigEndian>(0).unwrap(); // teamOffset
+    code_directory.write_u32::<BigEndian>(0).unwrap(); // spare3
+    code_directory.write_u64::<BigEndian>(if dataoff > u32::MAX as u64 { dataoff } else { 0 }).unwrap(); // codeLimit64
+    code_directory.write_u64::<BigEndian>(text.map_or(0, |text| text.fileoff)).unwrap(); // execSegBase
+    code_directory.write_u64::<BigEndian>(text.map_or(0, |text| text.filesize)).unwrap(); // execSegLimit
+    code_directory
+        .write_u64::<BigEndian>(if image.header.file_type() == FileType::Execute { CS_EXECSEG_MAIN_BINARY } else { 0 })
+        .unwrap();
+    code_directory.extend_from_slice(options.identifier.as_bytes());
+    code_directory.push(0);
+
+    // Special slot hashes are stored in reverse order, slot -n first and slot -1 last
+    for slot in (1..=n_special_slots).rev() {
+        match special_slots.iter().find(|(candidate, _)| *candidate == slot) {
+            Some((_, bytes)) => code_directory.extend_from_slice(&Sha256::digest(bytes)),
+            None => code_directory.extend_from_slice(&[0u8; CS_SHA256_LEN]),
+        }
+    }
+    for page in data.chunks(page_size as usize) {
+        code_directory.extend_from_slice(&Sha256::digest(page));
+    }
+
+    let mut contents: Vec<&[u8]> = vec![&code_directory];
+    contents.extend(special_slots.iter().map(|(_, bytes)| *bytes));
+    contents.push(&cms);
+
+    let mut superblob = Vec::with_capacity(datasize as usize);
+    superblob.write_u32::<BigEndian>(CSMAGIC_EMBEDDED_SIGNATURE).unwrap();
+    superblob.write_u32::<BigEndian>(superblob_size).unwrap();
+    superblob.write_u32::<BigEndian>(blobs.len() as u32).unwrap();
+    let mut offset = 12 + blobs.len() as u32 * 8;
+    for ((slot, _), bytes) in blobs.iter().zip(&contents) {
+        superblob.write_u32::<BigEndian>(*slot).unwrap();
+        superblob.write_u32::<BigEndian>(offset).unwrap();
+        offset += bytes.len() as u32;
+    }
+    for bytes in &contents {
+        superblob.extend_from_slice(bytes);
+    }
+    superblob.resize(datasize as usize, 0);
+
+    data.extend_from_slice(&superblob);
+
+    Ok(())
+}
+
+/// Removes the code signature of a thin image: drops `LC_CODE_SIGNATURE`,
+/// truncates the blob off the end of the file and shrinks `__LINKEDIT` to end
+/// where the signature started. Unsigned images are left alone.
+pub(crate) fn remove_signature(data: &mut Vec<u8>) -> Result<()> {
+    let image = Image::parse(data)?;
+
+    let Some(dataoff) = code_signature_command(&image.commands).map(|command| command.dataoff as u64) else {
+        return Ok(());
+    };
+
+    let cputype = image.header.cputype;
+    edit_load_commands(data, |commands, _| {
+        commands.retain(|command| command.cmd() != LC_CODE_SIGNATURE);
+        for command in commands.iter_mut() {
+            if let Command::Segment(segment) = command {
+                if segment.segname == "__LINKEDIT" {
+                    set_segment_end(segment, dataoff, cputype)?;
+                }
+            }
+        }
+        Ok(())
+    })?;
+    data.truncate(dataoff as usize);
+
+    Ok(())
+}
+
+/// Moves the end of a segment's file contents to `end`, keeping `vmsize`
+/// rounded to the page size of the architecture. An `end` before the start of
+/// the segment means the signature offset of the file is bogus.
+fn set_segment_end(segment: &mut SegmentCommand, end: u64, cputype: i32) -> Result<()> {
+    let vm_page_size = match cputype {
+        CPU_TYPE_ARM | CPU_TYPE_ARM64 | CPU_TYPE_ARM64_32 => 0x4000,
+        _ => 0x1000,
+    };
+
+    segment.filesize = end.checked_sub(segment.fileoff).ok_or(Error::MalformedSignature)?;
+    segment.vmsize = (segment.filesize + vm_page_size - 1) & !(vm_page_size - 1);
+    Ok(())
+}
+
+/// Re-signs a thin image ad hoc if it was signed before, keeping its identifier
+/// and entitlements. Unsigned arm64 and arm64e executables, dylibs and bundles
+/// are signed too, since the kernel refuses to run them without a signature.
+/// Other unsigned images are left alone.
+///
+/// `fallback_identifier` is used when there is no old signature with a
+/// readable identifier.
+pub(crate) fn resign_adhoc(data: &mut Vec<u8>, fallback_identifier: Option<&str>) -> Result<()> {
+    let image = Image::parse(data)?;
+
+    let needs_signature = image.header.cputype == CPU_TYPE_ARM64
+        && matches!(image.header.file_type(), FileType::Execute | FileType::Dylib | FileType::Bundle);
+    if code_signature_command(&image.commands).is_none() && !needs_signature {
+        return Ok(());
+    }
+
+    let options = SigningOptions::from_existing(&image).unwrap_or_else(|| SigningOptions {
+        identifier: fallback_identifier.unwrap_or("a.out").to_string(),
+        ..SigningOptions::default()
+    });
+    sign_adhoc(data, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_X86_64, MH_EXECUTE};
+    use crate::rpath::{add_rpath, DuplicateRpath};
+    use crate::signature::HashMismatch;
+
+    fn linkedit(image: &Image) -> &SegmentCommand {
+        image
+            .commands
+            .iter()
+            .find_map(|command| match command {
+                Command::Segment(segment) if segment.segname == "__LINKEDIT" => Some(segment),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// Checks every hash of the signature against the file it is embedded in.
+    fn assert_valid(data: &[u8], identifier: &str) {
+        let image = Image::parse(data).unwrap();
+        let signature = image.code_signature().unwrap().expect("image is not signed");
+
+        assert_eq!(signature.code_directory().unwrap().identifier, identifier);
+        assert_eq!(signature.verify(data).unwrap(), []);
+
+        // The blob is the last thing in the file and in __LINKEDIT
+        let command = code_signature_command(&image.commands).unwrap();
+        let end = command.dataoff as u64 + command.datasize as u64;
+        assert_eq!(data.len() as u64, end);
+        let linkedit = linkedit(&image);
+        assert_eq!(linkedit.fileoff + linkedit.filesize, end);
+    }
+
+    #[test]
+    fn signed_images_pass_verification() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        let options = SigningOptions {
+            identifier: "com.example.tool".to_string(),
+            entitlements: Some(b"<plist version=\"1.0\"><dict/></plist>".to_vec()),
+            der_entitlements: None,
+        };
+
+        sign_adhoc(&mut data, &options).unwrap();
+
+        assert_valid(&data, "com.example.tool");
+        let image = Image::parse(&data).unwrap();
+        assert_eq!(linkedit(&image).vmsize, 0x4000);
+        let signature = image.code_signature().unwrap().unwrap();
+        assert_eq!(signature.entitlements(), options.entitlements.as_deref());
+        assert_eq!(signature.code_directory().unwrap().flags, CS_ADHOC);
+    }
+
+    #[test]
+    fn changed_pages_fail_verification() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        sign_adhoc(&mut data, &SigningOptions { identifier: "tool".to_string(), ..SigningOptions::default() }).unwrap();
+
+        data[fixtures::TEXT_OFFSET as usize] ^= 0xff;
+
+        let signature = Image::parse(&data).unwrap().code_signature().unwrap().unwrap();
+        let mismatch = HashMismatch::CodePage { directory: CSSLOT_CODEDIRECTORY, page: 0 };
+        assert_eq!(signature.verify(&data).unwrap(), [mismatch]);
+    }
+
+    #[test]
+    fn edits_are_re_signed_with_the_old_identifier() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        sign_adhoc(&mut data, &SigningOptions { identifier: "com.example.tool".to_string(), ..SigningOptions::default() })
+            .unwrap();
+
+        add_rpath(&mut data, "@executable_path/../lib", DuplicateRpath::Error).unwrap();
+        resign_adhoc(&mut data, Some("fallback")).unwrap();
+
+        assert_valid(&data, "com.example.tool");
+    }
+
+    #[test]
+    fn unsigned_arm64_images_get_signed_and_others_are_left_alone() {
+        let mut arm64 = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        resign_adhoc(&mut arm64, Some("tool")).unwrap();
+        assert_valid(&arm64, "tool");
+
+        let mut x86_64 = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        let original = x86_64.clone();
+        resign_adhoc(&mut x86_64, Some("tool")).unwrap();
+        assert_eq!(x86_64, original);
+    }
+
+    #[test]
+    fn linkedit_vmsize_is_rounded_to_the_page_size() {
+        for (is_64, cputype, page_size) in
+            [(false, CPU_TYPE_ARM, 0x4000), (false, CPU_TYPE_ARM64_32, 0x4000), (true, CPU_TYPE_X86_64, 0x1000)]
+        {
+            let mut data = fixtures::thin(is_64, true, cputype, MH_EXECUTE);
+            sign_adhoc(&mut data, &SigningOptions { identifier: "tool".to_string(), ..SigningOptions::default() })
+                .unwrap();
+
+            assert_valid(&data, "tool");
+            assert_eq!(linkedit(&Image::parse(&data).unwrap()).vmsize, page_size);
+        }
+    }
+
+    #[test]
+    fn removing_a_signature_restores_linkedit() {
+        let original = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        let mut data = original.clone();
+        sign_adhoc(&mut data, &SigningOptions { identifier: "tool".to_string(), ..SigningOptions::default() }).unwrap();
+
+        remove_signature(&mut data).unwrap();
+
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn signature_offsets_before_linkedit_are_malformed() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        sign_adhoc(&mut data, &SigningOptions { identifier: "tool".to_string(), ..SigningOptions::default() }).unwrap();
+        edit_load_commands(&mut data, |commands, _| {
+            for command in commands.iter_mut() {
+                if let Command::LinkeditData(linkedit) = command {
+                    linkedit.dataoff = fixtures::TEXT_OFFSET as u32;
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+        let original = data.clone();
+
+        let error = sign_adhoc(&mut data, &SigningOptions::default()).unwrap_err();
+
+        assert!(matches!(error, Error::MalformedSignature), "{:?}", error);
+        assert_eq!(data, original);
+    }
+}
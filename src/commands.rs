@@ -0,0 +1,1223 @@
+use std::io::{Cursor, Read};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::error::{Error, Result};
+
+pub const LC_REQ_DYLD: u32 = 0x80000000;
+
+pub const LC_SEGMENT: u32 = 0x1;
+pub const LC_SYMTAB: u32 = 0x2;
+pub const LC_THREAD: u32 = 0x4;
+pub const LC_UNIXTHREAD: u32 = 0x5;
+pub const LC_DYSYMTAB: u32 = 0xb;
+pub const LC_LOAD_DYLIB: u32 = 0xc;
+pub const LC_ID_DYLIB: u32 = 0xd;
+pub const LC_LOAD_DYLINKER: u32 = 0xe;
+pub const LC_ID_DYLINKER: u32 = 0xf;
+pub const LC_PREBOUND_DYLIB: u32 = 0x10;
+pub const LC_ROUTINES: u32 = 0x11;
+pub const LC_SUB_FRAMEWORK: u32 = 0x12;
+pub const LC_SUB_UMBRELLA: u32 = 0x13;
+pub const LC_SUB_CLIENT: u32 = 0x14;
+pub const LC_SUB_LIBRARY: u32 = 0x15;
+pub const LC_TWOLEVEL_HINTS: u32 = 0x16;
+pub const LC_PREBIND_CKSUM: u32 = 0x17;
+pub const LC_LOAD_WEAK_DYLIB: u32 = 0x18 | LC_REQ_DYLD;
+pub const LC_SEGMENT_64: u32 = 0x19;
+pub const LC_ROUTINES_64: u32 = 0x1a;
+pub const LC_UUID: u32 = 0x1b;
+pub const LC_RPATH: u32 = 0x1c | LC_REQ_DYLD;
+pub const LC_CODE_SIGNATURE: u32 = 0x1d;
+pub const LC_SEGMENT_SPLIT_INFO: u32 = 0x1e;
+pub const LC_REEXPORT_DYLIB: u32 = 0x1f | LC_REQ_DYLD;
+pub const LC_LAZY_LOAD_DYLIB: u32 = 0x20;
+pub const LC_ENCRYPTION_INFO: u32 = 0x21;
+pub const LC_DYLD_INFO: u32 = 0x22;
+pub const LC_DYLD_INFO_ONLY: u32 = 0x22 | LC_REQ_DYLD;
+pub const LC_LOAD_UPWARD_DYLIB: u32 = 0x23 | LC_REQ_DYLD;
+pub const LC_VERSION_MIN_MACOSX: u32 = 0x24;
+pub const LC_VERSION_MIN_IPHONEOS: u32 = 0x25;
+pub const LC_FUNCTION_STARTS: u32 = 0x26;
+pub const LC_DYLD_ENVIRONMENT: u32 = 0x27;
+pub const LC_MAIN: u32 = 0x28 | LC_REQ_DYLD;
+pub const LC_DATA_IN_CODE: u32 = 0x29;
+pub const LC_SOURCE_VERSION: u32 = 0x2a;
+pub const LC_DYLIB_CODE_SIGN_DRS: u32 = 0x2b;
+pub const LC_ENCRYPTION_INFO_64: u32 = 0x2c;
+pub const LC_LINKER_OPTION: u32 = 0x2d;
+pub const LC_LINKER_OPTIMIZATION_HINT: u32 = 0x2e;
+pub const LC_VERSION_MIN_TVOS: u32 = 0x2f;
+pub const LC_VERSION_MIN_WATCHOS: u32 = 0x30;
+pub const LC_NOTE: u32 = 0x31;
+pub const LC_BUILD_VERSION: u32 = 0x32;
+pub const LC_DYLD_EXPORTS_TRIE: u32 = 0x33 | LC_REQ_DYLD;
+pub const LC_DYLD_CHAINED_FIXUPS: u32 = 0x34 | LC_REQ_DYLD;
+pub const LC_FILESET_ENTRY: u32 = 0x35 | LC_REQ_DYLD;
+
+pub const SECTION_TYPE: u32 = 0xff;
+pub const S_ZEROFILL: u32 = 0x1;
+pub const S_GB_ZEROFILL: u32 = 0xc;
+pub const S_THREAD_LOCAL_ZEROFILL: u32 = 0x12;
+pub const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x80000000;
+pub const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x400;
+
+/// The `LC_*` name of a load command type.
+pub fn command_name(cmd: u32) -> Option<&'static str> {
+    let name = match cmd {
+        LC_SEGMENT => "LC_SEGMENT",
+        LC_SYMTAB => "LC_SYMTAB",
+        LC_THREAD => "LC_THREAD",
+        LC_UNIXTHREAD => "LC_UNIXTHREAD",
+        LC_DYSYMTAB => "LC_DYSYMTAB",
+        LC_LOAD_DYLIB => "LC_LOAD_DYLIB",
+        LC_ID_DYLIB => "LC_ID_DYLIB",
+        LC_LOAD_DYLINKER => "LC_LOAD_DYLINKER",
+        LC_ID_DYLINKER => "LC_ID_DYLINKER",
+        LC_PREBOUND_DYLIB => "LC_PREBOUND_DYLIB",
+        LC_ROUTINES => "LC_ROUTINES",
+        LC_SUB_FRAMEWORK => "LC_SUB_FRAMEWORK",
+        LC_SUB_UMBRELLA => "LC_SUB_UMBRELLA",
+        LC_SUB_CLIENT => "LC_SUB_CLIENT",
+        LC_SUB_LIBRARY => "LC_SUB_LIBRARY",
+        LC_TWOLEVEL_HINTS => "LC_TWOLEVEL_HINTS",
+        LC_PREBIND_CKSUM => "LC_PREBIND_CKSUM",
+        LC_LOAD_WEAK_DYLIB => "LC_LOAD_WEAK_DYLIB",
+        LC_SEGMENT_64 => "LC_SEGMENT_64",
+        LC_ROUTINES_64 => "LC_ROUTINES_64",
+        LC_UUID => "LC_UUID",
+        LC_RPATH => "LC_RPATH",
+        LC_CODE_SIGNATURE => "LC_CODE_SIGNATURE",
+        LC_SEGMENT_SPLIT_INFO => "LC_SEGMENT_SPLIT_INFO",
+        LC_REEXPORT_DYLIB => "LC_REEXPORT_DYLIB",
+        LC_LAZY_LOAD_DYLIB => "LC_LAZY_LOAD_DYLIB",
+        LC_ENCRYPTION_INFO => "LC_ENCRYPTION_INFO",
+        LC_DYLD_INFO => "LC_DYLD_INFO",
+        LC_DYLD_INFO_ONLY => "LC_DYLD_INFO_ONLY",
+        LC_LOAD_UPWARD_DYLIB => "LC_LOAD_UPWARD_DYLIB",
+        LC_VERSION_MIN_MACOSX => "LC_VERSION_MIN_MACOSX",
+        LC_VERSION_MIN_IPHONEOS => "LC_VERSION_MIN_IPHONEOS",
+        LC_FUNCTION_STARTS => "LC_FUNCTION_STARTS",
+        LC_DYLD_ENVIRONMENT => "LC_DYLD_ENVIRONMENT",
+        LC_MAIN => "LC_MAIN",
+        LC_DATA_IN_CODE => "LC_DATA_IN_CODE",
+        LC_SOURCE_VERSION => "LC_SOURCE_VERSION",
+        LC_DYLIB_CODE_SIGN_DRS => "LC_DYLIB_CODE_SIGN_DRS",
+        LC_ENCRYPTION_INFO_64 => "LC_ENCRYPTION_INFO_64",
+        LC_LINKER_OPTION => "LC_LINKER_OPTION",
+        LC_LINKER_OPTIMIZATION_HINT => "LC_LINKER_OPTIMIZATION_HINT",
+        LC_VERSION_MIN_TVOS => "LC_VERSION_MIN_TVOS",
+        LC_VERSION_MIN_WATCHOS => "LC_VERSION_MIN_WATCHOS",
+        LC_NOTE => "LC_NOTE",
+        LC_BUILD_VERSION => "LC_BUILD_VERSION",
+        LC_DYLD_EXPORTS_TRIE => "LC_DYLD_EXPORTS_TRIE",
+        LC_DYLD_CHAINED_FIXUPS => "LC_DYLD_CHAINED_FIXUPS",
+        LC_FILESET_ENTRY => "LC_FILESET_ENTRY",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// A load command as it appears in the file, with the payload left undecoded.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LoadCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub data: Vec<u8>,
+}
+
+impl LoadCommand {
+    pub fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.cmdsize as usize);
+        bytes.write_u32::<T>(self.cmd).unwrap();
+        bytes.write_u32::<T>(self.cmdsize).unwrap();
+        bytes.extend_from_slice(&self.data);
+
+        bytes
+    }
+
+    /// Decodes the payload into a typed command.
+    ///
+    /// Commands that are not recognised, are malformed, or carry bytes the typed
+    /// representation would not reproduce (for example garbage after a string
+    /// terminator) come back as `Command::Unknown`, so that serializing the result
+    /// always gives back the original bytes.
+    pub fn decode<T: ByteOrder>(&self, is_64: bool) -> Command {
+        let bytes = self.to_bytes::<T>();
+
+        match Command::parse::<T>(&bytes) {
+            Ok(Some(command)) if command.to_bytes::<T>(is_64) == bytes => command,
+            _ => Command::Unknown(self.clone()),
+        }
+    }
+}
+
+/// Decodes every load command of a file, in load order.
+pub fn decode_commands<T: ByteOrder>(load_commands: &[LoadCommand], is_64: bool) -> Vec<Command> {
+    load_commands.iter().map(|command| command.decode::<T>(is_64)).collect()
+}
+
+/// A load command decoded into its fields.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "kind", rename_all = "snake_case"))]
+pub enum Command {
+    Segment(SegmentCommand),
+    Dylib(DylibCommand),
+    Dylinker(DylinkerCommand),
+    Rpath(RpathCommand),
+    Symtab(SymtabCommand),
+    Dysymtab(DysymtabCommand),
+    DyldInfo(DyldInfoCommand),
+    Uuid(UuidCommand),
+    BuildVersion(BuildVersionCommand),
+    VersionMin(VersionMinCommand),
+    SourceVersion(SourceVersionCommand),
+    Main(EntryPointCommand),
+    LinkeditData(LinkeditDataCommand),
+    EncryptionInfo(EncryptionInfoCommand),
+    LinkerOption(LinkerOptionCommand),
+    Unknown(LoadCommand),
+}
+
+impl Command {
+    /// Parses a whole load command, `cmd` and `cmdsize` included.
+    ///
+    /// Returns `None` for command types without a typed representation.
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<Option<Command>> {
+        let cmd = T::read_u32(bytes);
+
+        let command = match cmd {
+            LC_SEGMENT | LC_SEGMENT_64 => Command::Segment(SegmentCommand::parse::<T>(bytes)?),
+            LC_LOAD_DYLIB | LC_ID_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB | LC_LAZY_LOAD_DYLIB
+            | LC_LOAD_UPWARD_DYLIB => Command::Dylib(DylibCommand::parse::<T>(bytes)?),
+            LC_LOAD_DYLINKER | LC_ID_DYLINKER | LC_DYLD_ENVIRONMENT => {
+                Command::Dylinker(DylinkerCommand::parse::<T>(bytes)?)
+            }
+            LC_RPATH => Command::Rpath(RpathCommand::parse::<T>(bytes)?),
+            LC_SYMTAB => Command::Symtab(SymtabCommand::parse::<T>(bytes)?),
+            LC_DYSYMTAB => Command::Dysymtab(DysymtabCommand::parse::<T>(bytes)?),
+            LC_DYLD_INFO | LC_DYLD_INFO_ONLY => Command::DyldInfo(DyldInfoCommand::parse::<T>(bytes)?),
+            LC_UUID => Command::Uuid(UuidCommand::parse(bytes)?),
+            LC_BUILD_VERSION => Command::BuildVersion(BuildVersionCommand::parse::<T>(bytes)?),
+            LC_VERSION_MIN_MACOSX | LC_VERSION_MIN_IPHONEOS | LC_VERSION_MIN_TVOS | LC_VERSION_MIN_WATCHOS => {
+                Command::VersionMin(VersionMinCommand::parse::<T>(bytes)?)
+            }
+            LC_SOURCE_VERSION => Command::SourceVersion(SourceVersionCommand::parse::<T>(bytes)?),
+            LC_MAIN => Command::Main(EntryPointCommand::parse::<T>(bytes)?),
+            LC_CODE_SIGNATURE | LC_SEGMENT_SPLIT_INFO | LC_FUNCTION_STARTS | LC_DATA_IN_CODE
+            | LC_DYLIB_CODE_SIGN_DRS | LC_LINKER_OPTIMIZATION_HINT | LC_DYLD_EXPORTS_TRIE
+            | LC_DYLD_CHAINED_FIXUPS => Command::LinkeditData(LinkeditDataCommand::parse::<T>(bytes)?),
+            LC_ENCRYPTION_INFO | LC_ENCRYPTION_INFO_64 => {
+                Command::EncryptionInfo(EncryptionInfoCommand::parse::<T>(bytes)?)
+            }
+            LC_LINKER_OPTION => Command::LinkerOption(LinkerOptionCommand::parse::<T>(bytes)?),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(command))
+    }
+
+    pub fn cmd(&self) -> u32 {
+        match self {
+            Command::Segment(command) => command.cmd,
+            Command::Dylib(command) => command.cmd,
+            Command::Dylinker(command) => command.cmd,
+            Command::Rpath(command) => command.cmd,
+            Command::Symtab(_) => LC_SYMTAB,
+            Command::Dysymtab(_) => LC_DYSYMTAB,
+            Command::DyldInfo(command) => command.cmd,
+            Command::Uuid(_) => LC_UUID,
+            Command::BuildVersion(_) => LC_BUILD_VERSION,
+            Command::VersionMin(command) => command.cmd,
+            Command::SourceVersion(_) => LC_SOURCE_VERSION,
+            Command::Main(_) => LC_MAIN,
+            Command::LinkeditData(command) => command.cmd,
+            Command::EncryptionInfo(command) => command.cmd,
+            Command::LinkerOption(_) => LC_LINKER_OPTION,
+            Command::Unknown(command) => command.cmd,
+        }
+    }
+
+    /// The size the command takes up in the load command region.
+    pub fn cmdsize(&self, is_64: bool) -> u32 {
+        // Byte order does not change the size, and decoded commands serialize
+        // back to exactly what was read
+        self.to_bytes::<LittleEndian>(is_64).len() as u32
+    }
+
+    pub fn to_bytes<T: ByteOrder>(&self, is_64: bool) -> Vec<u8> {
+        match self {
+            Command::Segment(command) => command.to_bytes::<T>(),
+            Command::Dylib(command) => command.to_bytes::<T>(is_64),
+            Command::Dylinker(command) => command.to_bytes::<T>(is_64),
+            Command::Rpath(command) => command.to_bytes::<T>(is_64),
+            Command::Symtab(command) => command.to_bytes::<T>(),
+            Command::Dysymtab(command) => command.to_bytes::<T>(),
+            Command::DyldInfo(command) => command.to_bytes::<T>(),
+            Command::Uuid(command) => command.to_bytes::<T>(),
+            Command::BuildVersion(command) => command.to_bytes::<T>(),
+            Command::VersionMin(command) => command.to_bytes::<T>(),
+            Command::SourceVersion(command) => command.to_bytes::<T>(),
+            Command::Main(command) => command.to_bytes::<T>(),
+            Command::LinkeditData(command) => command.to_bytes::<T>(),
+            Command::EncryptionInfo(command) => command.to_bytes::<T>(),
+            Command::LinkerOption(command) => command.to_bytes::<T>(is_64),
+            Command::Unknown(command) => command.to_bytes::<T>(),
+        }
+    }
+}
+
+/// Rounds a command size up to the word size of the file.
+fn align_cmdsize(size: u32, is_64: bool) -> u32 {
+    let alignment = if is_64 { 8 } else { 4 };
+    (size + alignment - 1) & !(alignment - 1)
+}
+
+/// Reads a NUL-terminated string starting at `offset` bytes into the command.
+fn read_lc_str(bytes: &[u8], offset: u32) -> Result<String> {
+    let tail = bytes.get(offset as usize..).ok_or(Error::Truncated)?;
+    let end = tail.iter().position(|&byte| byte == 0).unwrap_or(tail.len());
+
+    String::from_utf8(tail[..end].to_vec()).map_err(|_| Error::InvalidString)
+}
+
+/// Reads a fixed 16 byte name such as `segname` or `sectname`.
+fn read_name(cursor: &mut Cursor<&[u8]>) -> Result<String> {
+    let mut name = [0u8; 16];
+    cursor.read_exact(&mut name)?;
+    let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+
+    String::from_utf8(name[..end].to_vec()).map_err(|_| Error::InvalidString)
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    let mut padded = [0u8; 16];
+    let len = name.len().min(16);
+    padded[..len].copy_from_slice(&name.as_bytes()[..len]);
+    bytes.extend_from_slice(&padded);
+}
+
+/// Skips `cmd` and `cmdsize`, which callers have already looked at.
+fn body_cursor(bytes: &[u8]) -> Cursor<&[u8]> {
+    let mut cursor = Cursor::new(bytes);
+    cursor.set_position(8);
+    cursor
+}
+
+fn command_header<T: ByteOrder>(cmd: u32, cmdsize: u32) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(cmdsize as usize);
+    bytes.write_u32::<T>(cmd).unwrap();
+    bytes.write_u32::<T>(cmdsize).unwrap();
+    bytes
+}
+
+/// `LC_SEGMENT` and `LC_SEGMENT_64`, with their section headers.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SegmentCommand {
+    pub cmd: u32,
+    pub segname: String,
+    pub vmaddr: u64,
+    pub vmsize: u64,
+    pub fileoff: u64,
+    pub filesize: u64,
+    pub maxprot: i32,
+    pub initprot: i32,
+    pub flags: u32,
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Section {
+    pub sectname: String,
+    pub segname: String,
+    pub addr: u64,
+    pub size: u64,
+    pub offset: u32,
+    pub align: u32,
+    pub reloff: u32,
+    pub nreloc: u32,
+    pub flags: u32,
+    pub reserved1: u32,
+    pub reserved2: u32,
+    /// Only present in `section_64`.
+    pub reserved3: u32,
+}
+
+impl SegmentCommand {
+    fn is_64(&self) -> bool {
+        self.cmd == LC_SEGMENT_64
+    }
+
+    fn header_size(is_64: bool) -> u32 {
+        if is_64 { 72 } else { 56 }
+    }
+
+    fn section_size(is_64: bool) -> u32 {
+        if is_64 { 80 } else { 68 }
+    }
+
+    pub fn cmdsize(&self) -> u32 {
+        let is_64 = self.is_64();
+        Self::header_size(is_64) + self.sections.len() as u32 * Self::section_size(is_64)
+    }
+
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<SegmentCommand> {
+        let cmd = T::read_u32(bytes);
+        let is_64 = cmd == LC_SEGMENT_64;
+        let mut cursor = body_cursor(bytes);
+
+        let read_word = |cursor: &mut Cursor<&[u8]>| -> std::io::Result<u64> {
+            if is_64 { cursor.read_u64::<T>() } else { cursor.read_u32::<T>().map(u64::from) }
+        };
+
+        let segname = read_name(&mut cursor)?;
+        let vmaddr = read_word(&mut cursor)?;
+        let vmsize = read_word(&mut cursor)?;
+        let fileoff = read_word(&mut cursor)?;
+        let filesize = read_word(&mut cursor)?;
+        let maxprot = cursor.read_i32::<T>()?;
+        let initprot = cursor.read_i32::<T>()?;
+        let nsects = cursor.read_u32::<T>()?;
+        let flags = cursor.read_u32::<T>()?;
+
+        let mut sections = Vec::new();
+        for _ in 0..nsects {
+            sections.push(Section {
+                sectname: read_name(&mut cursor)?,
+                segname: read_name(&mut cursor)?,
+                addr: read_word(&mut cursor)?,
+                size: read_word(&mut cursor)?,
+                offset: cursor.read_u32::<T>()?,
+                align: cursor.read_u32::<T>()?,
+                reloff: cursor.read_u32::<T>()?,
+                nreloc: cursor.read_u32::<T>()?,
+                flags: cursor.read_u32::<T>()?,
+                reserved1: cursor.read_u32::<T>()?,
+                reserved2: cursor.read_u32::<T>()?,
+                reserved3: if is_64 { cursor.read_u32::<T>()? } else { 0 },
+            });
+        }
+
+        Ok(SegmentCommand { cmd, segname, vmaddr, vmsize, fileoff, filesize, maxprot, initprot, flags, sections })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let is_64 = self.is_64();
+        let mut bytes = command_header::<T>(self.cmd, self.cmdsize());
+
+        let write_word = |bytes: &mut Vec<u8>, value: u64| {
+            if is_64 { bytes.write_u64::<T>(value).unwrap() } else { bytes.write_u32::<T>(value as u32).unwrap() }
+        };
+
+        write_name(&mut bytes, &self.segname);
+        write_word(&mut bytes, self.vmaddr);
+        write_word(&mut bytes, self.vmsize);
+        write_word(&mut bytes, self.fileoff);
+        write_word(&mut bytes, self.filesize);
+        bytes.write_i32::<T>(self.maxprot).unwrap();
+        bytes.write_i32::<T>(self.initprot).unwrap();
+        bytes.write_u32::<T>(self.sections.len() as u32).unwrap();
+        bytes.write_u32::<T>(self.flags).unwrap();
+
+        for section in &self.sections {
+            write_name(&mut bytes, &section.sectname);
+            write_name(&mut bytes, &section.segname);
+            write_word(&mut bytes, section.addr);
+            write_word(&mut bytes, section.size);
+            bytes.write_u32::<T>(section.offset).unwrap();
+            bytes.write_u32::<T>(section.align).unwrap();
+            bytes.write_u32::<T>(section.reloff).unwrap();
+            bytes.write_u32::<T>(section.nreloc).unwrap();
+            bytes.write_u32::<T>(section.flags).unwrap();
+            bytes.write_u32::<T>(section.reserved1).unwrap();
+            bytes.write_u32::<T>(section.reserved2).unwrap();
+            if is_64 {
+                bytes.write_u32::<T>(section.reserved3).unwrap();
+            }
+        }
+
+        bytes
+    }
+}
+
+impl Section {
+    pub fn is_zerofill(&self) -> bool {
+        matches!(self.flags & SECTION_TYPE, S_ZEROFILL | S_GB_ZEROFILL | S_THREAD_LOCAL_ZEROFILL)
+    }
+
+    /// Whether the section holds machine instructions rather than data.
+    pub fn contains_code(&self) -> bool {
+        self.flags & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS) != 0
+    }
+}
+
+/// `LC_ID_DYLIB` and the `LC_*_DYLIB` commands that reference a dependency.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DylibCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub name_offset: u32,
+    pub name: String,
+    pub timestamp: u32,
+    pub current_version: u32,
+    pub compatibility_version: u32,
+}
+
+/// How a dylib command refers to its library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "snake_case"))]
+pub enum DylibKind {
+    /// `LC_ID_DYLIB`, the library's own install name.
+    Id,
+    /// `LC_LOAD_DYLIB`
+    Load,
+    /// `LC_LOAD_WEAK_DYLIB`, the library may be missing at runtime.
+    Weak,
+    /// `LC_REEXPORT_DYLIB`, its symbols are exported as our own.
+    Reexport,
+    /// `LC_LOAD_UPWARD_DYLIB`, a dependency cycle that does not affect initialization order.
+    Upward,
+    /// `LC_LAZY_LOAD_DYLIB`, loaded on first use.
+    Lazy,
+}
+
+impl DylibCommand {
+    /// Size of `struct dylib_command` without the name.
+    const HEADER_SIZE: u32 = 24;
+
+    pub fn kind(&self) -> DylibKind {
+        match self.cmd {
+            LC_ID_DYLIB => DylibKind::Id,
+            LC_LOAD_WEAK_DYLIB => DylibKind::Weak,
+            LC_REEXPORT_DYLIB => DylibKind::Reexport,
+            LC_LOAD_UPWARD_DYLIB => DylibKind::Upward,
+            LC_LAZY_LOAD_DYLIB => DylibKind::Lazy,
+            _ => DylibKind::Load,
+        }
+    }
+
+    /// Size of the command holding `name`, padded to the word size of the file.
+    pub fn size_for(name: &str, is_64: bool) -> u32 {
+        align_cmdsize(Self::HEADER_SIZE + name.len() as u32 + 1, is_64)
+    }
+
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<DylibCommand> {
+        let mut cursor = body_cursor(bytes);
+        let name_offset = cursor.read_u32::<T>()?;
+
+        Ok(DylibCommand {
+            cmd: T::read_u32(bytes),
+            cmdsize: T::read_u32(&bytes[4..]),
+            name_offset,
+            timestamp: cursor.read_u32::<T>()?,
+            current_version: cursor.read_u32::<T>()?,
+            compatibility_version: cursor.read_u32::<T>()?,
+            name: read_lc_str(bytes, name_offset)?,
+        })
+    }
+
+    /// Keeps the current size when the name still fits, so that edits do not
+    /// move the commands that follow.
+    fn to_bytes<T: ByteOrder>(&self, is_64: bool) -> Vec<u8> {
+        let cmdsize = self.cmdsize.max(Self::size_for(&self.name, is_64));
+
+        let mut bytes = command_header::<T>(self.cmd, cmdsize);
+        bytes.write_u32::<T>(Self::HEADER_SIZE).unwrap();
+        bytes.write_u32::<T>(self.timestamp).unwrap();
+        bytes.write_u32::<T>(self.current_version).unwrap();
+        bytes.write_u32::<T>(self.compatibility_version).unwrap();
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.resize(cmdsize as usize, 0);
+
+        bytes
+    }
+}
+
+/// `LC_LOAD_DYLINKER`, `LC_ID_DYLINKER` and `LC_DYLD_ENVIRONMENT`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DylinkerCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub name_offset: u32,
+    pub name: String,
+}
+
+impl DylinkerCommand {
+    const HEADER_SIZE: u32 = 12;
+
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<DylinkerCommand> {
+        let name_offset = body_cursor(bytes).read_u32::<T>()?;
+
+        Ok(DylinkerCommand {
+            cmd: T::read_u32(bytes),
+            cmdsize: T::read_u32(&bytes[4..]),
+            name_offset,
+            name: read_lc_str(bytes, name_offset)?,
+        })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self, is_64: bool) -> Vec<u8> {
+        let required = align_cmdsize(Self::HEADER_SIZE + self.name.len() as u32 + 1, is_64);
+        let cmdsize = self.cmdsize.max(required);
+
+        let mut bytes = command_header::<T>(self.cmd, cmdsize);
+        bytes.write_u32::<T>(Self::HEADER_SIZE).unwrap();
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.resize(cmdsize as usize, 0);
+
+        bytes
+    }
+}
+
+/// `LC_RPATH`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RpathCommand {
+    pub cmd: u32,
+    pub cmdsize: u32,
+    pub path_offset: u32,
+    pub path: String,
+}
+
+impl RpathCommand {
+    /// Size of `struct rpath_command`: cmd, cmdsize and the path offset.
+    const HEADER_SIZE: u32 = 12;
+
+    pub fn new(path: &str, is_64: bool) -> RpathCommand {
+        RpathCommand {
+            cmd: LC_RPATH,
+            cmdsize: Self::size_for(path, is_64),
+            path_offset: Self::HEADER_SIZE,
+            path: path.to_string(),
+        }
+    }
+
+    /// Size of the command holding `path`, padded to the word size of the file.
+    pub fn size_for(path: &str, is_64: bool) -> u32 {
+        align_cmdsize(Self::HEADER_SIZE + path.len() as u32 + 1, is_64) // +1 for null terminator
+    }
+
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<RpathCommand> {
+        let path_offset = body_cursor(bytes).read_u32::<T>()?;
+
+        Ok(RpathCommand {
+            cmd: T::read_u32(bytes),
+            cmdsize: T::read_u32(&bytes[4..]),
+            path_offset,
+            path: read_lc_str(bytes, path_offset)?,
+        })
+    }
+
+    /// Serializes the command, with the path right after the fixed fields and
+    /// zero padding up to the word size of the file. An existing command that
+    /// is larger than it needs to be keeps its size.
+    pub fn to_bytes<T: ByteOrder>(&self, is_64: bool) -> Vec<u8> {
+        let cmdsize = self.cmdsize.max(Self::size_for(&self.path, is_64));
+
+        let mut bytes = command_header::<T>(self.cmd, cmdsize);
+        bytes.write_u32::<T>(Self::HEADER_SIZE).unwrap();
+        bytes.extend_from_slice(self.path.as_bytes());
+        bytes.resize(cmdsize as usize, 0); // null terminator and padding
+
+        bytes
+    }
+}
+
+/// `LC_SYMTAB`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SymtabCommand {
+    pub symoff: u32,
+    pub nsyms: u32,
+    pub stroff: u32,
+    pub strsize: u32,
+}
+
+impl SymtabCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<SymtabCommand> {
+        let mut cursor = body_cursor(bytes);
+
+        Ok(SymtabCommand {
+            symoff: cursor.read_u32::<T>()?,
+            nsyms: cursor.read_u32::<T>()?,
+            stroff: cursor.read_u32::<T>()?,
+            strsize: cursor.read_u32::<T>()?,
+        })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(LC_SYMTAB, 24);
+        for value in [self.symoff, self.nsyms, self.stroff, self.strsize] {
+            bytes.write_u32::<T>(value).unwrap();
+        }
+
+        bytes
+    }
+}
+
+/// `LC_DYSYMTAB`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DysymtabCommand {
+    pub ilocalsym: u32,
+    pub nlocalsym: u32,
+    pub iextdefsym: u32,
+    pub nextdefsym: u32,
+    pub iundefsym: u32,
+    pub nundefsym: u32,
+    pub tocoff: u32,
+    pub ntoc: u32,
+    pub modtaboff: u32,
+    pub nmodtab: u32,
+    pub extrefsymoff: u32,
+    pub nextrefsyms: u32,
+    pub indirectsymoff: u32,
+    pub nindirectsyms: u32,
+    pub extreloff: u32,
+    pub nextrel: u32,
+    pub locreloff: u32,
+    pub nlocrel: u32,
+}
+
+impl DysymtabCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<DysymtabCommand> {
+        let mut cursor = body_cursor(bytes);
+
+        Ok(DysymtabCommand {
+            ilocalsym: cursor.read_u32::<T>()?,
+            nlocalsym: cursor.read_u32::<T>()?,
+            iextdefsym: cursor.read_u32::<T>()?,
+            nextdefsym: cursor.read_u32::<T>()?,
+            iundefsym: cursor.read_u32::<T>()?,
+            nundefsym: cursor.read_u32::<T>()?,
+            tocoff: cursor.read_u32::<T>()?,
+            ntoc: cursor.read_u32::<T>()?,
+            modtaboff: cursor.read_u32::<T>()?,
+            nmodtab: cursor.read_u32::<T>()?,
+            extrefsymoff: cursor.read_u32::<T>()?,
+            nextrefsyms: cursor.read_u32::<T>()?,
+            indirectsymoff: cursor.read_u32::<T>()?,
+            nindirectsyms: cursor.read_u32::<T>()?,
+            extreloff: cursor.read_u32::<T>()?,
+            nextrel: cursor.read_u32::<T>()?,
+            locreloff: cursor.read_u32::<T>()?,
+            nlocrel: cursor.read_u32::<T>()?,
+        })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(LC_DYSYMTAB, 80);
+        for value in [
+            self.ilocalsym, self.nlocalsym, self.iextdefsym, self.nextdefsym, self.iundefsym, self.nundefsym,
+            self.tocoff, self.ntoc, self.modtaboff, self.nmodtab, self.extrefsymoff, self.nextrefsyms,
+            self.indirectsymoff, self.nindirectsyms, self.extreloff, self.nextrel, self.locreloff, self.nlocrel,
+        ] {
+            bytes.write_u32::<T>(value).unwrap();
+        }
+
+        bytes
+    }
+}
+
+/// `LC_DYLD_INFO` and `LC_DYLD_INFO_ONLY`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DyldInfoCommand {
+    pub cmd: u32,
+    pub rebase_off: u32,
+    pub rebase_size: u32,
+    pub bind_off: u32,
+    pub bind_size: u32,
+    pub weak_bind_off: u32,
+    pub weak_bind_size: u32,
+    pub lazy_bind_off: u32,
+    pub lazy_bind_size: u32,
+    pub export_off: u32,
+    pub export_size: u32,
+}
+
+impl DyldInfoCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<DyldInfoCommand> {
+        let mut cursor = body_cursor(bytes);
+
+        Ok(DyldInfoCommand {
+            cmd: T::read_u32(bytes),
+            rebase_off: cursor.read_u32::<T>()?,
+            rebase_size: cursor.read_u32::<T>()?,
+            bind_off: cursor.read_u32::<T>()?,
+            bind_size: cursor.read_u32::<T>()?,
+            weak_bind_off: cursor.read_u32::<T>()?,
+            weak_bind_size: cursor.read_u32::<T>()?,
+            lazy_bind_off: cursor.read_u32::<T>()?,
+            lazy_bind_size: cursor.read_u32::<T>()?,
+            export_off: cursor.read_u32::<T>()?,
+            export_size: cursor.read_u32::<T>()?,
+        })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(self.cmd, 48);
+        for value in [
+            self.rebase_off, self.rebase_size, self.bind_off, self.bind_size, self.weak_bind_off,
+            self.weak_bind_size, self.lazy_bind_off, self.lazy_bind_size, self.export_off, self.export_size,
+        ] {
+            bytes.write_u32::<T>(value).unwrap();
+        }
+
+        bytes
+    }
+}
+
+/// `LC_UUID`.
+#[derive(Debug, Clone)]
+pub struct UuidCommand {
+    pub uuid: [u8; 16],
+}
+
+impl UuidCommand {
+    fn parse(bytes: &[u8]) -> Result<UuidCommand> {
+        let mut uuid = [0u8; 16];
+        body_cursor(bytes).read_exact(&mut uuid)?;
+
+        Ok(UuidCommand { uuid })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(LC_UUID, 24);
+        bytes.extend_from_slice(&self.uuid);
+
+        bytes
+    }
+}
+
+/// The usual `8-4-4-4-12` form in upper case, as `otool` and `dwarfdump` print it.
+impl std::fmt::Display for UuidCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (index, byte) in self.uuid.iter().enumerate() {
+            if matches!(index, 4 | 6 | 8 | 10) {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialized as the string form rather than sixteen numbers.
+#[cfg(feature = "serde")]
+impl serde::Serialize for UuidCommand {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("UuidCommand", 1)?;
+        state.serialize_field("uuid", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// `LC_BUILD_VERSION`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BuildVersionCommand {
+    pub platform: u32,
+    /// X.Y.Z encoded in nibbles xxxx.yy.zz
+    pub minos: u32,
+    /// X.Y.Z encoded in nibbles xxxx.yy.zz
+    pub sdk: u32,
+    pub tools: Vec<BuildToolVersion>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BuildToolVersion {
+    pub tool: u32,
+    pub version: u32,
+}
+
+impl BuildVersionCommand {
+    pub fn cmdsize(&self) -> u32 {
+        24 + self.tools.len() as u32 * 8
+    }
+
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<BuildVersionCommand> {
+        let mut cursor = body_cursor(bytes);
+        let platform = cursor.read_u32::<T>()?;
+        let minos = cursor.read_u32::<T>()?;
+        let sdk = cursor.read_u32::<T>()?;
+        let ntools = cursor.read_u32::<T>()?;
+
+        let mut tools = Vec::new();
+        for _ in 0..ntools {
+            tools.push(BuildToolVersion { tool: cursor.read_u32::<T>()?, version: cursor.read_u32::<T>()? });
+        }
+
+        Ok(BuildVersionCommand { platform, minos, sdk, tools })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(LC_BUILD_VERSION, self.cmdsize());
+        bytes.write_u32::<T>(self.platform).unwrap();
+        bytes.write_u32::<T>(self.minos).unwrap();
+        bytes.write_u32::<T>(self.sdk).unwrap();
+        bytes.write_u32::<T>(self.tools.len() as u32).unwrap();
+        for tool in &self.tools {
+            bytes.write_u32::<T>(tool.tool).unwrap();
+            bytes.write_u32::<T>(tool.version).unwrap();
+        }
+
+        bytes
+    }
+}
+
+/// `LC_VERSION_MIN_MACOSX`, `LC_VERSION_MIN_IPHONEOS`, `LC_VERSION_MIN_TVOS` and `LC_VERSION_MIN_WATCHOS`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VersionMinCommand {
+    pub cmd: u32,
+    /// X.Y.Z encoded in nibbles xxxx.yy.zz
+    pub version: u32,
+    /// X.Y.Z encoded in nibbles xxxx.yy.zz
+    pub sdk: u32,
+}
+
+impl VersionMinCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<VersionMinCommand> {
+        let mut cursor = body_cursor(bytes);
+
+        Ok(VersionMinCommand { cmd: T::read_u32(bytes), version: cursor.read_u32::<T>()?, sdk: cursor.read_u32::<T>()? })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(self.cmd, 16);
+        bytes.write_u32::<T>(self.version).unwrap();
+        bytes.write_u32::<T>(self.sdk).unwrap();
+
+        bytes
+    }
+}
+
+/// `LC_SOURCE_VERSION`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SourceVersionCommand {
+    /// A.B.C.D.E packed as a24.b10.c10.d10.e10
+    pub version: u64,
+}
+
+impl SourceVersionCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<SourceVersionCommand> {
+        Ok(SourceVersionCommand { version: body_cursor(bytes).read_u64::<T>()? })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(LC_SOURCE_VERSION, 16);
+        bytes.write_u64::<T>(self.version).unwrap();
+
+        bytes
+    }
+}
+
+/// `LC_MAIN`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EntryPointCommand {
+    /// File offset of `main()`
+    pub entryoff: u64,
+    pub stacksize: u64,
+}
+
+impl EntryPointCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<EntryPointCommand> {
+        let mut cursor = body_cursor(bytes);
+
+        Ok(EntryPointCommand { entryoff: cursor.read_u64::<T>()?, stacksize: cursor.read_u64::<T>()? })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(LC_MAIN, 24);
+        bytes.write_u64::<T>(self.entryoff).unwrap();
+        bytes.write_u64::<T>(self.stacksize).unwrap();
+
+        bytes
+    }
+}
+
+/// Commands that point at a blob in `__LINKEDIT`: `LC_CODE_SIGNATURE`,
+/// `LC_FUNCTION_STARTS`, `LC_DATA_IN_CODE`, `LC_DYLD_CHAINED_FIXUPS`,
+/// `LC_DYLD_EXPORTS_TRIE` and friends.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LinkeditDataCommand {
+    pub cmd: u32,
+    pub dataoff: u32,
+    pub datasize: u32,
+}
+
+impl LinkeditDataCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<LinkeditDataCommand> {
+        let mut cursor = body_cursor(bytes);
+
+        Ok(LinkeditDataCommand { cmd: T::read_u32(bytes), dataoff: cursor.read_u32::<T>()?, datasize: cursor.read_u32::<T>()? })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let mut bytes = command_header::<T>(self.cmd, 16);
+        bytes.write_u32::<T>(self.dataoff).unwrap();
+        bytes.write_u32::<T>(self.datasize).unwrap();
+
+        bytes
+    }
+}
+
+/// `LC_ENCRYPTION_INFO` and `LC_ENCRYPTION_INFO_64`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EncryptionInfoCommand {
+    pub cmd: u32,
+    pub cryptoff: u32,
+    pub cryptsize: u32,
+    pub cryptid: u32,
+    /// Only present in `LC_ENCRYPTION_INFO_64`.
+    pub pad: u32,
+}
+
+impl EncryptionInfoCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<EncryptionInfoCommand> {
+        let cmd = T::read_u32(bytes);
+        let mut cursor = body_cursor(bytes);
+
+        Ok(EncryptionInfoCommand {
+            cmd,
+            cryptoff: cursor.read_u32::<T>()?,
+            cryptsize: cursor.read_u32::<T>()?,
+            cryptid: cursor.read_u32::<T>()?,
+            pad: if cmd == LC_ENCRYPTION_INFO_64 { cursor.read_u32::<T>()? } else { 0 },
+        })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self) -> Vec<u8> {
+        let is_64 = self.cmd == LC_ENCRYPTION_INFO_64;
+        let mut bytes = command_header::<T>(self.cmd, if is_64 { 24 } else { 20 });
+        bytes.write_u32::<T>(self.cryptoff).unwrap();
+        bytes.write_u32::<T>(self.cryptsize).unwrap();
+        bytes.write_u32::<T>(self.cryptid).unwrap();
+        if is_64 {
+            bytes.write_u32::<T>(self.pad).unwrap();
+        }
+
+        bytes
+    }
+}
+
+/// `LC_LINKER_OPTION`: linker flags recorded by the compiler in object files.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LinkerOptionCommand {
+    pub cmdsize: u32,
+    pub strings: Vec<String>,
+}
+
+impl LinkerOptionCommand {
+    fn parse<T: ByteOrder>(bytes: &[u8]) -> Result<LinkerOptionCommand> {
+        let count = body_cursor(bytes).read_u32::<T>()?;
+
+        let mut strings = Vec::new();
+        let mut offset = 12;
+        for _ in 0..count {
+            let string = read_lc_str(bytes, offset)?;
+            offset += string.len() as u32 + 1;
+            strings.push(string);
+        }
+
+        Ok(LinkerOptionCommand { cmdsize: T::read_u32(&bytes[4..]), strings })
+    }
+
+    fn to_bytes<T: ByteOrder>(&self, is_64: bool) -> Vec<u8> {
+        let strings_size: u32 = self.strings.iter().map(|string| string.len() as u32 + 1).sum();
+        let cmdsize = self.cmdsize.max(align_cmdsize(12 + strings_size, is_64));
+
+        let mut bytes = command_header::<T>(LC_LINKER_OPTION, cmdsize);
+        bytes.write_u32::<T>(self.strings.len() as u32).unwrap();
+        for string in &self.strings {
+            bytes.extend_from_slice(string.as_bytes());
+            bytes.push(0);
+        }
+        bytes.resize(cmdsize as usize, 0);
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::BigEndian;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::header::{parse_macho, MachHeader, CPU_TYPE_POWERPC, CPU_TYPE_X86_64, MH_EXECUTE};
+
+    /// A raw load command: `cmd`, `cmdsize`, then `fields` and `tail` as they
+    /// would sit in a file of byte order `T`.
+    fn raw<T: ByteOrder>(cmd: u32, fields: &[u32], tail: &[u8]) -> Vec<u8> {
+        let cmdsize = 8 + fields.len() as u32 * 4 + tail.len() as u32;
+        let mut bytes = command_header::<T>(cmd, cmdsize);
+        for &field in fields {
+            bytes.write_u32::<T>(field).unwrap();
+        }
+        bytes.extend_from_slice(tail);
+        bytes
+    }
+
+    /// A NUL-terminated string padded so that a command with `header` bytes
+    /// before it ends on a word boundary.
+    fn lc_str(string: &str, header: u32, is_64: bool) -> Vec<u8> {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.resize((align_cmdsize(header + string.len() as u32 + 1, is_64) - header) as usize, 0);
+        bytes
+    }
+
+    fn u64s<T: ByteOrder>(values: &[u64]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for &value in values {
+            bytes.write_u64::<T>(value).unwrap();
+        }
+        bytes
+    }
+
+    /// One command of every kind `Command` decodes, as a linker would write them.
+    fn samples<T: ByteOrder>(is_64: bool) -> Vec<Vec<u8>> {
+        let section = |sectname: &str, offset: u32| Section {
+            sectname: sectname.to_string(),
+            segname: "__TEXT".to_string(),
+            addr: 0x100000000 | offset as u64,
+            size: 0x123,
+            offset,
+            align: 4,
+            reloff: 0,
+            nreloc: 0,
+            flags: 0x80000400,
+            reserved1: 1,
+            reserved2: 2,
+            reserved3: if is_64 { 3 } else { 0 },
+        };
+        let segment = SegmentCommand {
+            cmd: if is_64 { LC_SEGMENT_64 } else { LC_SEGMENT },
+            segname: "__TEXT".to_string(),
+            vmaddr: if is_64 { 0x100000000 } else { 0x1000 },
+            vmsize: 0x4000,
+            fileoff: 0,
+            filesize: 0x4000,
+            maxprot: 5,
+            initprot: 5,
+            flags: 0,
+            sections: vec![section("__text", 0x800), section("__cstring", 0x923)],
+        };
+
+        let mut samples = vec![segment.to_bytes::<T>()];
+        for cmd in [LC_LOAD_DYLIB, LC_ID_DYLIB, LC_LOAD_WEAK_DYLIB, LC_REEXPORT_DYLIB, LC_LAZY_LOAD_DYLIB, LC_LOAD_UPWARD_DYLIB] {
+            samples.push(raw::<T>(cmd, &[24, 2, 0x10203, 0x10000], &lc_str("/usr/lib/libfoo.dylib", 24, is_64)));
+        }
+        for cmd in [LC_LOAD_DYLINKER, LC_ID_DYLINKER, LC_DYLD_ENVIRONMENT] {
+            samples.push(raw::<T>(cmd, &[12], &lc_str("/usr/lib/dyld", 12, is_64)));
+        }
+        samples.push(raw::<T>(LC_RPATH, &[12], &lc_str("@loader_path/../lib", 12, is_64)));
+        samples.push(raw::<T>(LC_SYMTAB, &[0x2000, 3, 0x2100, 0x40], &[]));
+        samples.push(raw::<T>(LC_DYSYMTAB, &(1..=18).collect::<Vec<u32>>(), &[]));
+        for cmd in [LC_DYLD_INFO, LC_DYLD_INFO_ONLY] {
+            samples.push(raw::<T>(cmd, &(1..=10).collect::<Vec<u32>>(), &[]));
+        }
+        samples.push(raw::<T>(LC_UUID, &[], &(0..16).collect::<Vec<u8>>()));
+        samples.push(raw::<T>(LC_BUILD_VERSION, &[1, 0xb0000, 0xe0500, 2, 3, 0x3b40000, 4, 0x4190000], &[]));
+        for cmd in [LC_VERSION_MIN_MACOSX, LC_VERSION_MIN_IPHONEOS, LC_VERSION_MIN_TVOS, LC_VERSION_MIN_WATCHOS] {
+            samples.push(raw::<T>(cmd, &[0xa0f00, 0xb0000], &[]));
+        }
+        samples.push(raw::<T>(LC_SOURCE_VERSION, &[], &u64s::<T>(&[0x1234 << 40 | 5 << 30])));
+        samples.push(raw::<T>(LC_MAIN, &[], &u64s::<T>(&[0x3f40, 0x100000])));
+        for cmd in [
+            LC_CODE_SIGNATURE, LC_SEGMENT_SPLIT_INFO, LC_FUNCTION_STARTS, LC_DATA_IN_CODE, LC_DYLIB_CODE_SIGN_DRS,
+            LC_LINKER_OPTIMIZATION_HINT, LC_DYLD_EXPORTS_TRIE, LC_DYLD_CHAINED_FIXUPS,
+        ] {
+            samples.push(raw::<T>(cmd, &[0x8000, 0x120], &[]));
+        }
+        samples.push(raw::<T>(LC_ENCRYPTION_INFO, &[0x4000, 0x8000, 1], &[]));
+        samples.push(raw::<T>(LC_ENCRYPTION_INFO_64, &[0x4000, 0x8000, 1, 0], &[]));
+        samples.push(raw::<T>(LC_LINKER_OPTION, &[2], &lc_str("-framework\0Foundation", 12, is_64)));
+        samples
+    }
+
+    fn check_round_trip<T: ByteOrder>(is_64: bool) {
+        for bytes in samples::<T>(is_64) {
+            let cmd = T::read_u32(&bytes);
+            let load_command = LoadCommand { cmd, cmdsize: T::read_u32(&bytes[4..]), data: bytes[8..].to_vec() };
+            let command = load_command.decode::<T>(is_64);
+
+            assert!(!matches!(command, Command::Unknown(_)), "{} did not decode", command_name(cmd).unwrap());
+            assert_eq!(command.cmd(), cmd);
+            assert_eq!(command.to_bytes::<T>(is_64), bytes, "{} changed", command_name(cmd).unwrap());
+            assert_eq!(command.cmdsize(is_64) as usize, bytes.len());
+        }
+    }
+
+    #[test]
+    fn round_trip_64_little_endian() {
+        check_round_trip::<LittleEndian>(true);
+    }
+
+    #[test]
+    fn round_trip_64_big_endian() {
+        check_round_trip::<BigEndian>(true);
+    }
+
+    #[test]
+    fn round_trip_32_little_endian() {
+        check_round_trip::<LittleEndian>(false);
+    }
+
+    #[test]
+    fn round_trip_32_big_endian() {
+        check_round_trip::<BigEndian>(false);
+    }
+
+    #[test]
+    fn unknown_and_irregular_commands_keep_their_bytes() {
+        // LC_NOTE has no typed form, the rpath has bytes after its terminator
+        let mut rpath = raw::<LittleEndian>(LC_RPATH, &[12], &lc_str("/lib", 12, true));
+        rpath[20] = 0xff;
+        for bytes in [raw::<LittleEndian>(LC_NOTE, &[1, 2, 3, 4, 5, 6], &[]), rpath] {
+            let load_command = LoadCommand {
+                cmd: LittleEndian::read_u32(&bytes),
+                cmdsize: LittleEndian::read_u32(&bytes[4..]),
+                data: bytes[8..].to_vec(),
+            };
+            let command = load_command.decode::<LittleEndian>(true);
+
+            assert!(matches!(command, Command::Unknown(_)));
+            assert_eq!(command.to_bytes::<LittleEndian>(true), bytes);
+        }
+    }
+
+    #[test]
+    fn decoded_files_serialize_to_their_load_command_region() {
+        for (is_64, is_little_endian, cputype) in
+            [(true, true, CPU_TYPE_X86_64), (false, false, CPU_TYPE_POWERPC), (true, false, CPU_TYPE_POWERPC)]
+        {
+            let data = fixtures::thin(is_64, is_little_endian, cputype, MH_EXECUTE);
+            let (header, load_commands) = parse_macho(&data).unwrap();
+            let region: Vec<u8> = if is_little_endian {
+                let commands = decode_commands::<LittleEndian>(&load_commands, is_64);
+                commands.iter().flat_map(|command| command.to_bytes::<LittleEndian>(is_64)).collect()
+            } else {
+                let commands = decode_commands::<BigEndian>(&load_commands, is_64);
+                commands.iter().flat_map(|command| command.to_bytes::<BigEndian>(is_64)).collect()
+            };
+
+            let start = MachHeader::size(is_64) as usize;
+            assert_eq!(region, data[start..start + header.sizeofcmds as usize]);
+        }
+    }
+}
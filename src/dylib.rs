@@ -0,0 +1,116 @@
+use crate::commands::{Command, DylibCommand, LC_ID_DYLIB};
+use crate::edit::edit_load_commands;
+use crate::error::{Error, Result};
+
+/// Sets the install name of a thin dylib, the name in its `LC_ID_DYLIB`.
+pub(crate) fn set_id(data: &mut Vec<u8>, name: &str) -> Result<()> {
+    edit_load_commands(data, |commands, is_64| {
+        let dylib = commands
+            .iter_mut()
+            .find_map(|command| match command {
+                Command::Dylib(dylib) if dylib.cmd == LC_ID_DYLIB => Some(dylib),
+                _ => None,
+            })
+            .ok_or(Error::NotADylib)?;
+
+        rename(dylib, name, is_64);
+        Ok(())
+    })
+}
+
+/// Points every load of the dylib `old_name` at `new_name` instead, whatever
+/// the kind of load (regular, weak, reexport, lazy or upward).
+///
+/// Like `install_name_tool -change`, a file that does not reference `old_name`
+/// is left as it is.
+pub(crate) fn change_dylib(data: &mut Vec<u8>, old_name: &str, new_name: &str) -> Result<()> {
+    edit_load_commands(data, |commands, is_64| {
+        for command in commands.iter_mut() {
+            if let Command::Dylib(dylib) = command {
+                if dylib.cmd != LC_ID_DYLIB && dylib.name == old_name {
+                    rename(dylib, new_name, is_64);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Swaps the name and resizes the command to fit it, so a shorter name hands
+/// space back to the header padding. Timestamp and versions stay untouched.
+fn rename(dylib: &mut DylibCommand, name: &str, is_64: bool) {
+    dylib.name = name.to_string();
+    dylib.cmdsize = DylibCommand::size_for(name, is_64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{DylibKind, LC_LAZY_LOAD_DYLIB, LC_LOAD_DYLIB, LC_LOAD_UPWARD_DYLIB, LC_LOAD_WEAK_DYLIB, LC_REEXPORT_DYLIB};
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, CPU_TYPE_X86, MH_DYLIB, MH_EXECUTE};
+    use crate::image::Image;
+
+    #[test]
+    fn set_id_keeps_the_versions_and_resizes_the_command() {
+        for is_64 in [false, true] {
+            let cputype = if is_64 { CPU_TYPE_ARM64 } else { CPU_TYPE_X86 };
+            let mut data = fixtures::linking(is_64, true, cputype, MH_DYLIB, &[(LC_ID_DYLIB, "libfoo.dylib")]);
+            let name = "@rpath/Frameworks/libfoo.1.dylib";
+
+            set_id(&mut data, name).unwrap();
+
+            let image = Image::parse(&data).unwrap();
+            let id = image.id_dylib().unwrap();
+            assert_eq!(id.name, name);
+            assert_eq!(id.cmdsize, DylibCommand::size_for(name, is_64));
+            assert_eq!(id.cmdsize % if is_64 { 8 } else { 4 }, 0);
+            assert_eq!((id.timestamp, id.current_version, id.compatibility_version), (2, 0x10203, 0x10000));
+        }
+    }
+
+    #[test]
+    fn set_id_needs_a_dylib() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        let original = data.clone();
+
+        assert!(matches!(set_id(&mut data, "libfoo.dylib"), Err(Error::NotADylib)));
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn change_dylib_renames_every_kind_of_load() {
+        let loads = [LC_LOAD_DYLIB, LC_LOAD_WEAK_DYLIB, LC_REEXPORT_DYLIB, LC_LAZY_LOAD_DYLIB, LC_LOAD_UPWARD_DYLIB];
+        let mut dylibs = vec![(LC_ID_DYLIB, "/usr/lib/libbar.dylib")];
+        dylibs.extend(loads.iter().map(|&cmd| (cmd, "/usr/lib/libbar.dylib")));
+        dylibs.push((LC_LOAD_DYLIB, "/usr/lib/libSystem.B.dylib"));
+        let mut data = fixtures::linking(true, true, CPU_TYPE_ARM64, MH_DYLIB, &dylibs);
+
+        change_dylib(&mut data, "/usr/lib/libbar.dylib", "@rpath/libbar.dylib").unwrap();
+
+        let image = Image::parse(&data).unwrap();
+        // A dylib's own install name is not a dependency, -id changes that
+        assert_eq!(image.id_dylib().unwrap().name, "/usr/lib/libbar.dylib");
+        let dependencies = image.dependencies();
+        let kinds: Vec<_> = dependencies.iter().map(|dylib| dylib.kind()).collect();
+        assert_eq!(
+            kinds,
+            [DylibKind::Load, DylibKind::Weak, DylibKind::Reexport, DylibKind::Lazy, DylibKind::Upward, DylibKind::Load]
+        );
+        for dylib in &dependencies[..5] {
+            assert_eq!(dylib.name, "@rpath/libbar.dylib");
+            assert_eq!((dylib.timestamp, dylib.current_version, dylib.compatibility_version), (2, 0x10203, 0x10000));
+        }
+        assert_eq!(dependencies[5].name, "/usr/lib/libSystem.B.dylib");
+    }
+
+    #[test]
+    fn change_dylib_leaves_unrelated_files_alone() {
+        let mut data = fixtures::linking(true, true, CPU_TYPE_ARM64, MH_EXECUTE, &[(LC_LOAD_DYLIB, "/usr/lib/libSystem.B.dylib")]);
+        let original = data.clone();
+
+        change_dylib(&mut data, "/usr/lib/libbar.dylib", "@rpath/libbar.dylib").unwrap();
+
+        assert_eq!(data, original);
+    }
+}
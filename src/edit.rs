@@ -0,0 +1,108 @@
+use std::io::{Cursor, Write};
+use byteorder::{ByteOrder, BigEndian, LittleEndian};
+
+use crate::commands::{decode_commands, Command};
+use crate::error::{Error, Result};
+use crate::header::{macho_kind, parse_macho, write_header, MachHeader};
+
+/// Decodes the load commands of a thin file, lets `edit` change them, and
+/// writes the result back into the load command region.
+pub(crate) fn edit_load_commands<F>(data: &mut Vec<u8>, edit: F) -> Result<()>
+where
+    F: FnOnce(&mut Vec<Command>, bool) -> Result<()>,
+{
+    let (is_64, is_little_endian) = macho_kind(data)?;
+
+    if is_little_endian {
+        edit_load_commands_with::<LittleEndian, F>(data, is_64, edit)
+    } else {
+        edit_load_commands_with::<BigEndian, F>(data, is_64, edit)
+    }
+}
+
+fn edit_load_commands_with<T: ByteOrder, F>(data: &mut Vec<u8>, is_64: bool, edit: F) -> Result<()>
+where
+    F: FnOnce(&mut Vec<Command>, bool) -> Result<()>,
+{
+    let (_, load_commands) = parse_macho(data)?;
+    let mut commands = decode_commands::<T>(&load_commands, is_64);
+
+    edit(&mut commands, is_64)?;
+
+    write_load_commands::<T>(data, is_64, &commands)
+}
+
+/// Finds the file offset of the first byte of section (or segment) content.
+///
+/// Everything between the end of the load commands and this offset is header
+/// padding that new load commands can be written into.
+pub(crate) fn first_content_offset(commands: &[Command], file_size: u64) -> u64 {
+    let mut first_offset = file_size;
+
+    for command in commands {
+        let Command::Segment(segment) = command else {
+            continue;
+        };
+
+        // Segments that start at offset 0 map the header itself, only their sections matter
+        if segment.fileoff != 0 && segment.filesize != 0 {
+            first_offset = first_offset.min(segment.fileoff);
+        }
+
+        for section in &segment.sections {
+            if section.offset != 0 && !section.is_zerofill() {
+                first_offset = first_offset.min(section.offset as u64);
+            }
+        }
+    }
+
+    first_offset
+}
+
+/// Replaces the load command region with `commands` and updates `ncmds` and
+/// `sizeofcmds` in the header.
+///
+/// The commands only ever grow into the zero-filled padding that precedes the
+/// first section, so nothing after the region moves and every file offset
+/// stored in the load commands stays valid. Space freed by a smaller region is
+/// zeroed so it can be reused as padding.
+pub(crate) fn write_load_commands<T: ByteOrder>(data: &mut Vec<u8>, is_64: bool, commands: &[Command]) -> Result<()> {
+    let (mut header, _) = parse_macho(data)?;
+
+    let header_size = MachHeader::size(is_64);
+
+    let mut region = Vec::new();
+    for command in commands {
+        region.extend_from_slice(&command.to_bytes::<T>(is_64));
+    }
+
+    let old_end = header_size + header.sizeofcmds as u64;
+    let new_end = header_size + region.len() as u64;
+    if new_end > old_end {
+        let content_offset = first_content_offset(commands, data.len() as u64);
+        let available = content_offset.saturating_sub(old_end);
+        let needed = new_end - old_end;
+        if needed > available {
+            return Err(Error::NotEnoughPadding { needed, available });
+        }
+    }
+
+    let mut cursor = Cursor::new(data);
+
+    cursor.set_position(header_size);
+    cursor.write_all(&region)?;
+    if old_end > new_end {
+        cursor.write_all(&vec![0u8; (old_end - new_end) as usize])?;
+    }
+
+    // Update the Mach-O header
+    header.ncmds = commands.len() as u32;
+    header.sizeofcmds = region.len() as u32;
+
+    cursor.set_position(0);
+    write_header::<T>(&mut cursor, &header, is_64)?;
+
+    Ok(())
+}
+
+
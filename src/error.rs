@@ -0,0 +1,113 @@
+/// Everything that can go wrong while reading or editing a Mach-O file.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("not a Mach-O file (magic {0:#010x})")]
+    NotMachO(u32),
+
+    #[error("static archives are not supported, extract the object files with ar first")]
+    StaticArchive,
+
+    #[error("file is truncated")]
+    Truncated,
+
+    #[error("load command {index} is malformed")]
+    MalformedLoadCommand { index: usize },
+
+    #[error("load command string is not valid UTF-8")]
+    InvalidString,
+
+    #[error("architecture slice {index} lies outside of the file")]
+    SliceOutOfBounds { index: usize },
+
+    #[error("architecture slice {index} has an alignment of 2^{align}, more than the 2^15 lipo allows")]
+    AlignmentTooLarge { index: usize, align: u32 },
+
+    #[error("no architecture with cputype {0:#x} in file")]
+    NoMatchingArchitecture(i32),
+
+    #[error("file does not contain architecture {0}")]
+    ArchitectureNotFound(String),
+
+    #[error("more than one input file contains architecture {0}")]
+    DuplicateArchitecture(String),
+
+    #[error("a universal binary needs at least one architecture")]
+    NoArchitectureLeft,
+
+    #[error("file is not a universal binary")]
+    NotFat,
+
+    #[error("cannot edit {0} files this way")]
+    UnsupportedFileType(crate::header::FileType),
+
+    #[error("no LC_RPATH load command with path: {0}")]
+    RpathNotFound(String),
+
+    #[error("would duplicate path, file already has LC_RPATH for: {0}")]
+    DuplicateRpath(String),
+
+    #[error("file is not a shared library, it has no LC_ID_DYLIB")]
+    NotADylib,
+
+    #[error("file has no __LINKEDIT segment to hold a code signature")]
+    NoLinkedit,
+
+    #[error("code signature is malformed")]
+    MalformedSignature,
+
+    #[error("unsupported code signature hash type {0}")]
+    UnsupportedHashType(u8),
+
+    #[error("library not found: {name} ({})", list_tried(.tried))]
+    LibraryNotFound { name: String, tried: Vec<std::path::PathBuf> },
+
+    #[error("cannot read library {}: {1}", .0.display())]
+    UnreadableLibrary(std::path::PathBuf, Box<Error>),
+
+    #[error("cannot bundle both {} and {} under the same name", .0.display(), .1.display())]
+    LibraryNameClash(std::path::PathBuf, std::path::PathBuf),
+
+    #[error("{} has no LC_VERSION_MIN load command", describe_platform(*.0))]
+    NoVersionMinCommand(u32),
+
+    #[error("cannot retarget to {}: {reason}", describe_platform(*.platform))]
+    IncompatiblePlatform { platform: u32, reason: String },
+
+    #[error("new prefix is longer than the old one ({new} > {old} bytes)")]
+    PrefixTooLong { old: usize, new: usize },
+
+    #[error("not enough header padding (need {needed}, have {available})")]
+    NotEnoughPadding { needed: u64, available: u64 },
+
+    #[error(transparent)]
+    Io(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Only `@rpath` names without any rpath to search leave nothing to try.
+fn list_tried(paths: &[std::path::PathBuf]) -> String {
+    if paths.is_empty() {
+        return "no LC_RPATH to search".to_string();
+    }
+    let paths: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+    format!("tried {}", paths.join(", "))
+}
+
+fn describe_platform(platform: u32) -> String {
+    match crate::version::platform_name(platform) {
+        Some(name) => name.to_string(),
+        None => format!("platform {}", platform),
+    }
+}
+
+impl From<std::io::Error> for Error {
+    /// Reading past the end of a buffer means the file is shorter than its
+    /// headers claim, which is worth its own variant.
+    fn from(error: std::io::Error) -> Error {
+        match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => Error::Truncated,
+            _ => Error::Io(error),
+        }
+    }
+}
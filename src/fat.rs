@@ -0,0 +1,475 @@
+use std::io::Cursor;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::error::{Error, Result};
+use crate::header::{describe_arch, parse_macho, FileType, CPU_SUBTYPE_MASK, CPU_TYPE_ARM, CPU_TYPE_ARM64, CPU_TYPE_ARM64_32};
+use crate::image::Image;
+
+pub const FAT_MAGIC: u32 = 0xcafebabe;
+pub const FAT_MAGIC_64: u32 = 0xcafebabf;
+
+/// The largest slice alignment, as a power of two, that `lipo` will write.
+pub const MAX_ALIGN: u32 = 15;
+
+/// One `fat_arch` or `fat_arch_64` entry of a universal binary.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FatArch {
+    pub cputype: i32,
+    pub cpusubtype: i32,
+    pub offset: u64,
+    pub size: u64,
+    /// Alignment of the slice as a power of two
+    pub align: u32,
+    /// Only present in `fat_arch_64`.
+    pub reserved: u32,
+}
+
+impl FatArch {
+    /// Whether the slice is for this CPU type and subtype, leaving the feature
+    /// flags in the high byte of the subtype out of the comparison.
+    pub fn matches(&self, cputype: i32, cpusubtype: i32) -> bool {
+        self.cputype == cputype && self.cpusubtype & !CPU_SUBTYPE_MASK == cpusubtype & !CPU_SUBTYPE_MASK
+    }
+
+    /// The architecture name, such as `arm64e`.
+    pub fn name(&self) -> String {
+        describe_arch(self.cputype, self.cpusubtype)
+    }
+}
+
+/// A universal binary split into its architecture slices.
+#[derive(Debug, Clone)]
+pub struct FatBinary {
+    pub is_64: bool,
+    pub arches: Vec<FatArch>,
+    pub slices: Vec<Vec<u8>>,
+}
+
+pub fn is_fat(data: &[u8]) -> bool {
+    let magic = Cursor::new(data).read_u32::<BigEndian>().unwrap_or(0);
+    matches!(magic, FAT_MAGIC | FAT_MAGIC_64)
+}
+
+/// Reads the fat header and the `fat_arch` table. The fat header is always big-endian.
+pub fn parse_fat_header(data: &[u8]) -> Result<(bool, Vec<FatArch>)> {
+    let mut cursor = Cursor::new(data);
+    let magic = cursor.read_u32::<BigEndian>()?;
+
+    let is_64 = match magic {
+        FAT_MAGIC => false,
+        FAT_MAGIC_64 => true,
+        _ => return Err(Error::NotMachO(magic)),
+    };
+
+    let nfat_arch = cursor.read_u32::<BigEndian>()?;
+    let mut arches = Vec::new();
+    for index in 0..nfat_arch as usize {
+        let cputype = cursor.read_i32::<BigEndian>()?;
+        let cpusubtype = cursor.read_i32::<BigEndian>()?;
+        let (offset, size) = if is_64 {
+            (cursor.read_u64::<BigEndian>()?, cursor.read_u64::<BigEndian>()?)
+        } else {
+            (cursor.read_u32::<BigEndian>()? as u64, cursor.read_u32::<BigEndian>()? as u64)
+        };
+        let align = cursor.read_u32::<BigEndian>()?;
+        if align > MAX_ALIGN {
+            return Err(Error::AlignmentTooLarge { index, align });
+        }
+        let reserved = if is_64 { cursor.read_u32::<BigEndian>()? } else { 0 };
+
+        arches.push(FatArch { cputype, cpusubtype, offset, size, align, reserved });
+    }
+
+    Ok((is_64, arches))
+}
+
+impl FatBinary {
+    pub fn parse(data: &[u8]) -> Result<FatBinary> {
+        let (is_64, arches) = parse_fat_header(data)?;
+
+        let mut slices = Vec::new();
+        for (index, arch) in arches.iter().enumerate() {
+            let slice = arch
+                .offset
+                .checked_add(arch.size)
+                .and_then(|end| data.get(arch.offset as usize..end as usize))
+                .ok_or(Error::SliceOutOfBounds { index })?;
+            slices.push(slice.to_vec());
+        }
+
+        Ok(FatBinary { is_64, arches, slices })
+    }
+
+    /// Combines thin files and universal binaries into one universal binary,
+    /// like `lipo -create`. Thin files are aligned to the page size of their
+    /// architecture, object files to their most aligned section; slices taken
+    /// from universal binaries keep their alignment.
+    pub fn create(files: &[Vec<u8>]) -> Result<FatBinary> {
+        let mut fat = FatBinary { is_64: false, arches: Vec::new(), slices: Vec::new() };
+
+        for data in files {
+            let (arches, slices) = if is_fat(data) {
+                let input = FatBinary::parse(data)?;
+                fat.is_64 |= input.is_64;
+                (input.arches, input.slices)
+            } else {
+                (vec![thin_arch(data)?], vec![data.clone()])
+            };
+
+            for (arch, slice) in arches.into_iter().zip(slices) {
+                if fat.position(arch.cputype, arch.cpusubtype).is_some() {
+                    return Err(Error::DuplicateArchitecture(arch.name()));
+                }
+                fat.arches.push(arch);
+                fat.slices.push(slice);
+            }
+        }
+
+        Ok(fat)
+    }
+
+    fn position(&self, cputype: i32, cpusubtype: i32) -> Option<usize> {
+        self.arches.iter().position(|arch| arch.matches(cputype, cpusubtype))
+    }
+
+    fn find(&self, cputype: i32, cpusubtype: i32) -> Result<usize> {
+        self.position(cputype, cpusubtype)
+            .ok_or_else(|| Error::ArchitectureNotFound(describe_arch(cputype, cpusubtype)))
+    }
+
+    /// The thin file of one architecture, like `lipo -thin`.
+    pub fn thin(&self, cputype: i32, cpusubtype: i32) -> Result<Vec<u8>> {
+        Ok(self.slices[self.find(cputype, cpusubtype)?].clone())
+    }
+
+    /// A universal binary with only the given architectures, like `lipo -extract`.
+    pub fn extract(&self, arches: &[(i32, i32)]) -> Result<FatBinary> {
+        let mut extracted = FatBinary { is_64: self.is_64, arches: Vec::new(), slices: Vec::new() };
+
+        for (index, arch) in self.arches.iter().enumerate() {
+            if arches.iter().any(|&(cputype, cpusubtype)| arch.matches(cputype, cpusubtype)) {
+                extracted.arches.push(arch.clone());
+                extracted.slices.push(self.slices[index].clone());
+            }
+        }
+        for &(cputype, cpusubtype) in arches {
+            extracted.find(cputype, cpusubtype)?;
+        }
+
+        Ok(extracted)
+    }
+
+    /// Drops the given architectures, like `lipo -remove`. At least one has
+    /// to stay.
+    pub fn remove(&mut self, arches: &[(i32, i32)]) -> Result<()> {
+        // Everything is checked first, so that a failed removal changes nothing
+        for &(cputype, cpusubtype) in arches {
+            self.find(cputype, cpusubtype)?;
+        }
+        let removed = |arch: &FatArch| arches.iter().any(|&(cputype, cpusubtype)| arch.matches(cputype, cpusubtype));
+        if self.arches.iter().all(removed) {
+            return Err(Error::NoArchitectureLeft);
+        }
+
+        let slices = std::mem::take(&mut self.slices);
+        (self.arches, self.slices) =
+            std::mem::take(&mut self.arches).into_iter().zip(slices).filter(|(arch, _)| !removed(arch)).unzip();
+        Ok(())
+    }
+
+    /// Puts the thin file `data` in place of the slice of the same
+    /// architecture, like `lipo -replace`.
+    pub fn replace(&mut self, data: Vec<u8>) -> Result<()> {
+        let (header, _) = parse_macho(&data)?;
+        let index = self.find(header.cputype, header.cpusubtype)?;
+
+        self.arches[index].cpusubtype = header.cpusubtype;
+        self.slices[index] = data;
+        Ok(())
+    }
+
+    /// Lays the slices out again, each one starting on its `2^align` boundary,
+    /// and fixes up the offsets and sizes in the `fat_arch` table.
+    ///
+    /// Falls back to `FAT_MAGIC_64` when a slice would not be addressable with
+    /// 32-bit offsets.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sizes: Vec<u64> = self.slices.iter().map(|slice| slice.len() as u64).collect();
+        let (is_64, arches, end) = layout(self.is_64, &self.arches, &sizes);
+
+        let mut bytes = Vec::with_capacity(end as usize);
+        bytes.write_u32::<BigEndian>(if is_64 { FAT_MAGIC_64 } else { FAT_MAGIC }).unwrap();
+        bytes.write_u32::<BigEndian>(arches.len() as u32).unwrap();
+        for arch in &arches {
+            bytes.write_i32::<BigEndian>(arch.cputype).unwrap();
+            bytes.write_i32::<BigEndian>(arch.cpusubtype).unwrap();
+            if is_64 {
+                bytes.write_u64::<BigEndian>(arch.offset).unwrap();
+                bytes.write_u64::<BigEndian>(arch.size).unwrap();
+            } else {
+                bytes.write_u32::<BigEndian>(arch.offset as u32).unwrap();
+                bytes.write_u32::<BigEndian>(arch.size as u32).unwrap();
+            }
+            bytes.write_u32::<BigEndian>(arch.align).unwrap();
+            if is_64 {
+                bytes.write_u32::<BigEndian>(arch.reserved).unwrap();
+            }
+        }
+
+        for (arch, slice) in arches.iter().zip(&self.slices) {
+            bytes.resize(arch.offset as usize, 0);
+            bytes.extend_from_slice(slice);
+        }
+
+        bytes
+    }
+}
+
+/// The `fat_arch` table for slices of `sizes` bytes placed one after the
+/// other, whether it needs `FAT_MAGIC_64`, and where the last slice ends.
+fn layout(is_64: bool, arches: &[FatArch], sizes: &[u64]) -> (bool, Vec<FatArch>, u64) {
+    let place = |is_64: bool, arches: &mut [FatArch]| -> u64 {
+        let arch_size = if is_64 { 32 } else { 20 };
+        let mut offset = 8 + arches.len() as u64 * arch_size;
+        for (arch, &size) in arches.iter_mut().zip(sizes) {
+            let alignment = 1u64 << arch.align;
+            offset = (offset + alignment - 1) & !(alignment - 1);
+            arch.offset = offset;
+            arch.size = size;
+            offset += size;
+        }
+        offset
+    };
+
+    let mut is_64 = is_64;
+    let mut arches = arches.to_vec();
+    let mut end = place(is_64, &mut arches);
+    if !is_64 && arches.iter().any(|arch| arch.offset > u32::MAX as u64 || arch.size > u32::MAX as u64) {
+        is_64 = true;
+        end = place(is_64, &mut arches);
+    }
+
+    (is_64, arches, end)
+}
+
+/// The `fat_arch` entry for a thin file, before it has been laid out.
+fn thin_arch(data: &[u8]) -> Result<FatArch> {
+    let (header, _) = parse_macho(data)?;
+
+    Ok(FatArch {
+        cputype: header.cputype,
+        cpusubtype: header.cpusubtype,
+        offset: 0,
+        size: data.len() as u64,
+        align: default_align(data)?,
+        reserved: 0,
+    })
+}
+
+/// The alignment `lipo` gives a thin file as a power of two: the page size of
+/// its architecture, 16 KiB on ARM and 4 KiB elsewhere, or for object files
+/// the alignment of their most aligned section, up to [`MAX_ALIGN`].
+pub fn default_align(data: &[u8]) -> Result<u32> {
+    let image = Image::parse(data)?;
+
+    if image.header.file_type() == FileType::Object {
+        return Ok(image.sections().map(|section| section.align).max().unwrap_or(0).min(MAX_ALIGN));
+    }
+
+    match image.header.cputype {
+        CPU_TYPE_ARM | CPU_TYPE_ARM64 | CPU_TYPE_ARM64_32 => Ok(14),
+        _ => Ok(12),
+    }
+}
+
+/// Applies `edit` to a thin Mach-O file, or to every slice of a universal
+/// binary whose CPU type matches `cputype` (all slices when it is `None`).
+///
+/// Universal binaries are re-assembled afterwards, so edits are free to change
+/// the size of a slice.
+pub fn edit_slices<F>(data: &mut Vec<u8>, cputype: Option<i32>, mut edit: F) -> Result<()>
+where
+    F: FnMut(&mut Vec<u8>) -> Result<()>,
+{
+    if !is_fat(data) {
+        if let Some(cputype) = cputype {
+            let (header, _) = parse_macho(data)?;
+            if header.cputype != cputype {
+                return Err(Error::NoMatchingArchitecture(cputype));
+            }
+        }
+        // An edit that fails halfway must not leave a half edited file behind
+        let mut edited = data.clone();
+        edit(&mut edited)?;
+        *data = edited;
+        return Ok(());
+    }
+
+    let mut fat = FatBinary::parse(data)?;
+    let mut edited = false;
+    for (arch, slice) in fat.arches.iter().zip(fat.slices.iter_mut()) {
+        if cputype.is_none_or(|cputype| cputype == arch.cputype) {
+            edit(slice)?;
+            edited = true;
+        }
+    }
+
+    if let (false, Some(cputype)) = (edited, cputype) {
+        return Err(Error::NoMatchingArchitecture(cputype));
+    }
+
+    *data = fat.to_bytes();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_POWERPC, CPU_TYPE_X86_64, MH_EXECUTE, MH_OBJECT};
+
+    fn universal() -> Vec<u8> {
+        let x86_64 = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        let arm64 = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        FatBinary::create(&[x86_64, arm64]).unwrap().to_bytes()
+    }
+
+    #[test]
+    fn edited_slices_are_laid_out_again_on_their_alignment() {
+        let mut data = universal();
+        let before = FatBinary::parse(&data).unwrap();
+
+        // Grow the x86_64 slice, which pushes the arm64 one to the next 16 KiB boundary
+        edit_slices(&mut data, Some(CPU_TYPE_X86_64), |slice| {
+            slice.resize(slice.len() + 0x5000, 0);
+            Ok(())
+        })
+        .unwrap();
+
+        let after = FatBinary::parse(&data).unwrap();
+        assert_eq!(after.arches[0].offset, before.arches[0].offset);
+        assert_eq!(after.arches[0].size, before.arches[0].size + 0x5000);
+        for arch in &after.arches {
+            assert_eq!(arch.offset % (1 << arch.align), 0);
+        }
+        assert!(after.arches[1].offset >= after.arches[0].offset + after.arches[0].size);
+        assert_eq!(after.slices[1], before.slices[1]);
+        assert_eq!(data.len() as u64, after.arches[1].offset + after.arches[1].size);
+    }
+
+    #[test]
+    fn edits_apply_to_the_selected_slices_only() {
+        let mut data = universal();
+        let before = FatBinary::parse(&data).unwrap();
+
+        edit_slices(&mut data, Some(CPU_TYPE_ARM64), |slice| {
+            slice[fixtures::TEXT_OFFSET as usize] = 0xff;
+            Ok(())
+        })
+        .unwrap();
+
+        let after = FatBinary::parse(&data).unwrap();
+        assert_eq!(after.slices[0], before.slices[0]);
+        assert_eq!(after.slices[1][fixtures::TEXT_OFFSET as usize], 0xff);
+        assert!(matches!(
+            edit_slices(&mut data, Some(CPU_TYPE_ARM), |_| Ok(())),
+            Err(Error::NoMatchingArchitecture(CPU_TYPE_ARM))
+        ));
+    }
+
+    #[test]
+    fn failed_edits_leave_thin_files_untouched() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        let original = data.clone();
+
+        let result = edit_slices(&mut data, None, |slice| {
+            slice[fixtures::TEXT_OFFSET as usize] = 0xff;
+            Err(Error::Truncated)
+        });
+
+        assert!(matches!(result, Err(Error::Truncated)));
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn failed_removals_leave_the_file_untouched() {
+        let mut fat = FatBinary::parse(&universal()).unwrap();
+        let original = fat.to_bytes();
+
+        let missing = fat.remove(&[(CPU_TYPE_X86_64, 0), (CPU_TYPE_POWERPC, 0)]);
+        assert!(matches!(missing, Err(Error::ArchitectureNotFound(name)) if name == "ppc"));
+        assert_eq!(fat.to_bytes(), original);
+
+        let everything = fat.remove(&[(CPU_TYPE_X86_64, 0), (CPU_TYPE_ARM64, 0)]);
+        assert!(matches!(everything, Err(Error::NoArchitectureLeft)));
+        assert_eq!(fat.to_bytes(), original);
+
+        fat.remove(&[(CPU_TYPE_X86_64, 0)]).unwrap();
+        assert_eq!(fat.arches.len(), 1);
+        assert_eq!(fat.arches[0].cputype, CPU_TYPE_ARM64);
+        assert_eq!(fat.slices[0], FatBinary::parse(&original).unwrap().slices[1]);
+    }
+
+    #[test]
+    fn create_aligns_slices_like_lipo() {
+        let object = fixtures::thin(true, true, CPU_TYPE_ARM64_32, MH_OBJECT);
+        let fat = FatBinary::create(&[universal(), object]).unwrap();
+        let data = fat.to_bytes();
+
+        let (is_64, arches) = parse_fat_header(&data).unwrap();
+        assert!(!is_64);
+        let aligns: Vec<u32> = arches.iter().map(|arch| arch.align).collect();
+        // x86_64 pages are 4 KiB and arm64 ones 16 KiB, the object file takes its `__text` alignment
+        assert_eq!(aligns, [12, 14, 4]);
+        for (arch, slice) in arches.iter().zip(&fat.slices) {
+            assert_eq!(arch.offset % (1 << arch.align), 0);
+            assert_eq!(&data[arch.offset as usize..(arch.offset + arch.size) as usize], slice.as_slice());
+        }
+
+        let arm64 = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        let duplicate = FatBinary::create(&[universal(), arm64]);
+        assert!(matches!(duplicate, Err(Error::DuplicateArchitecture(name)) if name == "arm64"));
+    }
+
+    #[test]
+    fn slices_past_4_gib_switch_to_fat_magic_64() {
+        let arches = FatBinary::parse(&universal()).unwrap().arches;
+
+        let (is_64, small, _) = layout(false, &arches, &[0x1000, 0x1000]);
+        assert!(!is_64);
+        assert_eq!(small[1].offset, 0x4000);
+
+        let (is_64, large, end) = layout(false, &arches, &[5 << 30, 0x1000]);
+        assert!(is_64);
+        // The 32 byte fat_arch_64 entries still fit in the first page
+        assert_eq!(large[0].offset, 0x1000);
+        assert_eq!(large[1].offset, (0x1000 + (5 << 30) + 0x3fff) & !0x3fff);
+        assert_eq!(end, large[1].offset + 0x1000);
+    }
+
+    #[test]
+    fn fat_magic_64_files_round_trip() {
+        let mut fat = FatBinary::parse(&universal()).unwrap();
+        fat.is_64 = true;
+        fat.arches[1].reserved = 7;
+        let data = fat.to_bytes();
+
+        assert_eq!(&data[..4], FAT_MAGIC_64.to_be_bytes());
+        let parsed = FatBinary::parse(&data).unwrap();
+        assert!(parsed.is_64);
+        assert_eq!(parsed.arches[1].reserved, 7);
+        assert_eq!(parsed.slices, fat.slices);
+        assert_eq!(parsed.to_bytes(), data);
+    }
+
+    #[test]
+    fn alignments_above_2_15_are_rejected() {
+        let mut data = universal();
+        // align of the second fat_arch
+        data[8 + 20 + 16..8 + 20 + 20].copy_from_slice(&64u32.to_be_bytes());
+
+        assert!(matches!(parse_fat_header(&data), Err(Error::AlignmentTooLarge { index: 1, align: 64 })));
+    }
+}
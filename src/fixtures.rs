@@ -0,0 +1,144 @@
+//! Small synthetic Mach-O files for the unit tests. They are written out field
+//! by field, so that they don't depend on the serializers under test.
+
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+
+/// Where `__text` starts. Everything between the load commands and here is
+/// header padding.
+pub(crate) const TEXT_OFFSET: u64 = 0x800;
+/// Where `__LINKEDIT` starts, right after the `__TEXT` page.
+pub(crate) const LINKEDIT_OFFSET: u64 = 0x1000;
+pub(crate) const LINKEDIT_SIZE: u64 = 0x40;
+
+/// A thin image with a `__TEXT` segment holding one `__text` section and a
+/// `__LINKEDIT` segment at the end of the file, in the given word size and
+/// byte order.
+pub(crate) fn thin(is_64: bool, is_little_endian: bool, cputype: i32, filetype: u32) -> Vec<u8> {
+    linking(is_64, is_little_endian, cputype, filetype, &[])
+}
+
+/// Like [`thin`], with a dylib command for each `(cmd, name)` after the
+/// segments. They all have timestamp 2, current version 1.2.3 and
+/// compatibility version 1.0.0.
+pub(crate) fn linking(is_64: bool, is_little_endian: bool, cputype: i32, filetype: u32, dylibs: &[(u32, &str)]) -> Vec<u8> {
+    if is_little_endian {
+        build::<LittleEndian>(is_64, cputype, filetype, dylibs)
+    } else {
+        build::<BigEndian>(is_64, cputype, filetype, dylibs)
+    }
+}
+
+fn write_word<T: ByteOrder>(bytes: &mut Vec<u8>, is_64: bool, value: u64) {
+    if is_64 {
+        bytes.write_u64::<T>(value).unwrap();
+    } else {
+        bytes.write_u32::<T>(value as u32).unwrap();
+    }
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    let mut padded = [0u8; 16];
+    padded[..name.len()].copy_from_slice(name.as_bytes());
+    bytes.extend_from_slice(&padded);
+}
+
+/// `LC_SEGMENT` or `LC_SEGMENT_64`, with `section` as its only section when given.
+fn write_segment<T: ByteOrder>(
+    bytes: &mut Vec<u8>,
+    is_64: bool,
+    segname: &str,
+    fileoff: u64,
+    filesize: u64,
+    section: Option<(&str, u64, u64)>,
+) {
+    let (cmd, header_size, section_size) = if is_64 { (0x19, 72, 80) } else { (0x1, 56, 68) };
+    let nsects = section.is_some() as u32;
+
+    bytes.write_u32::<T>(cmd).unwrap();
+    bytes.write_u32::<T>(header_size + nsects * section_size).unwrap();
+    write_name(bytes, segname);
+    write_word::<T>(bytes, is_64, fileoff); // vmaddr
+    write_word::<T>(bytes, is_64, (filesize + 0xfff) & !0xfff); // vmsize
+    write_word::<T>(bytes, is_64, fileoff);
+    write_word::<T>(bytes, is_64, filesize);
+    bytes.write_i32::<T>(1).unwrap(); // maxprot
+    bytes.write_i32::<T>(1).unwrap(); // initprot
+    bytes.write_u32::<T>(nsects).unwrap();
+    bytes.write_u32::<T>(0).unwrap(); // flags
+
+    if let Some((sectname, offset, size)) = section {
+        write_name(bytes, sectname);
+        write_name(bytes, segname);
+        write_word::<T>(bytes, is_64, offset); // addr
+        write_word::<T>(bytes, is_64, size);
+        bytes.write_u32::<T>(offset as u32).unwrap();
+        bytes.write_u32::<T>(4).unwrap(); // align
+        bytes.write_u32::<T>(0).unwrap(); // reloff
+        bytes.write_u32::<T>(0).unwrap(); // nreloc
+        bytes.write_u32::<T>(0x80000400).unwrap(); // S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS
+        bytes.write_u32::<T>(0).unwrap(); // reserved1
+        bytes.write_u32::<T>(0).unwrap(); // reserved2
+        if is_64 {
+            bytes.write_u32::<T>(0).unwrap(); // reserved3
+        }
+    }
+}
+
+/// `struct dylib_command` with the name right after it, padded to the word size.
+fn write_dylib<T: ByteOrder>(bytes: &mut Vec<u8>, is_64: bool, cmd: u32, name: &str) {
+    let align = if is_64 { 8 } else { 4 };
+    let cmdsize = (24 + name.len() + 1).div_ceil(align) * align;
+
+    bytes.write_u32::<T>(cmd).unwrap();
+    bytes.write_u32::<T>(cmdsize as u32).unwrap();
+    bytes.write_u32::<T>(24).unwrap(); // name offset
+    bytes.write_u32::<T>(2).unwrap(); // timestamp
+    bytes.write_u32::<T>(0x10203).unwrap(); // current_version
+    bytes.write_u32::<T>(0x10000).unwrap(); // compatibility_version
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.resize(bytes.len() + cmdsize - 24 - name.len(), 0);
+}
+
+fn build<T: ByteOrder>(is_64: bool, cputype: i32, filetype: u32, dylibs: &[(u32, &str)]) -> Vec<u8> {
+    let mut commands = Vec::new();
+    write_segment::<T>(&mut commands, is_64, "__TEXT", 0, LINKEDIT_OFFSET, Some(("__text", TEXT_OFFSET, 0x100)));
+    write_segment::<T>(&mut commands, is_64, "__LINKEDIT", LINKEDIT_OFFSET, LINKEDIT_SIZE, None);
+    for &(cmd, name) in dylibs {
+        write_dylib::<T>(&mut commands, is_64, cmd, name);
+    }
+
+    let mut data = Vec::new();
+    data.write_u32::<T>(if is_64 { 0xfeedfacf } else { 0xfeedface }).unwrap();
+    data.write_i32::<T>(cputype).unwrap();
+    data.write_i32::<T>(0).unwrap(); // cpusubtype
+    data.write_u32::<T>(filetype).unwrap();
+    data.write_u32::<T>(2 + dylibs.len() as u32).unwrap(); // ncmds
+    data.write_u32::<T>(commands.len() as u32).unwrap();
+    data.write_u32::<T>(0).unwrap(); // flags
+    if is_64 {
+        data.write_u32::<T>(0).unwrap(); // reserved
+    }
+    data.extend_from_slice(&commands);
+
+    data.resize(TEXT_OFFSET as usize, 0);
+    data.resize(LINKEDIT_OFFSET as usize, 0xc3);
+    data.resize((LINKEDIT_OFFSET + LINKEDIT_SIZE) as usize, 0x5a);
+    data
+}
+
+/// An empty directory for the test called `name`, left behind for inspection
+/// when the test fails.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("stealthemoon-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `data` to `path`, creating the directories on the way.
+pub(crate) fn write(path: &Path, data: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, data).unwrap();
+}
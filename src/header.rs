@@ -0,0 +1,772 @@
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use bitflags::bitflags;
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BigEndian, LittleEndian};
+
+use crate::commands::LoadCommand;
+use crate::error::{Error, Result};
+
+pub const MH_MAGIC: u32 = 0xfeedface;
+pub const MH_CIGAM: u32 = 0xcefaedfe;
+pub const MH_MAGIC_64: u32 = 0xfeedfacf;
+pub const MH_CIGAM_64: u32 = 0xcffaedfe;
+
+/// Static archives (`.a`) start with this instead of a Mach-O magic.
+pub const AR_MAGIC: &[u8] = b"!<arch>\n";
+
+pub const CPU_ARCH_ABI64: i32 = 0x01000000;
+pub const CPU_ARCH_ABI64_32: i32 = 0x02000000;
+pub const CPU_TYPE_X86: i32 = 7;
+pub const CPU_TYPE_X86_64: i32 = CPU_TYPE_X86 | CPU_ARCH_ABI64;
+pub const CPU_TYPE_ARM: i32 = 12;
+pub const CPU_TYPE_ARM64: i32 = CPU_TYPE_ARM | CPU_ARCH_ABI64;
+pub const CPU_TYPE_ARM64_32: i32 = CPU_TYPE_ARM | CPU_ARCH_ABI64_32;
+pub const CPU_TYPE_POWERPC: i32 = 18;
+pub const CPU_TYPE_POWERPC64: i32 = CPU_TYPE_POWERPC | CPU_ARCH_ABI64;
+
+/// The high byte of `cpusubtype` holds feature flags rather than the subtype.
+pub const CPU_SUBTYPE_MASK: i32 = 0xff000000u32 as i32;
+pub const CPU_SUBTYPE_X86_ALL: i32 = 3;
+pub const CPU_SUBTYPE_X86_64_H: i32 = 8;
+pub const CPU_SUBTYPE_ARM_ALL: i32 = 0;
+pub const CPU_SUBTYPE_ARM_V6: i32 = 6;
+pub const CPU_SUBTYPE_ARM_V7: i32 = 9;
+pub const CPU_SUBTYPE_ARM_V7F: i32 = 10;
+pub const CPU_SUBTYPE_ARM_V7S: i32 = 11;
+pub const CPU_SUBTYPE_ARM_V7K: i32 = 12;
+pub const CPU_SUBTYPE_ARM_V6M: i32 = 14;
+pub const CPU_SUBTYPE_ARM_V7M: i32 = 15;
+pub const CPU_SUBTYPE_ARM_V7EM: i32 = 16;
+pub const CPU_SUBTYPE_ARM64_ALL: i32 = 0;
+pub const CPU_SUBTYPE_ARM64_V8: i32 = 1;
+pub const CPU_SUBTYPE_ARM64E: i32 = 2;
+pub const CPU_SUBTYPE_ARM64_32_V8: i32 = 1;
+pub const CPU_SUBTYPE_POWERPC_ALL: i32 = 0;
+
+/// arm64e only: the ptrauth ABI version in `CPU_SUBTYPE_ARM64E_PTRAUTH_MASK` is meaningful.
+pub const CPU_SUBTYPE_ARM64E_VERSIONED_PTRAUTH_ABI_MASK: u32 = 0x80000000;
+/// arm64e only: the image follows the kernel ptrauth ABI rather than the userspace one.
+pub const CPU_SUBTYPE_ARM64E_KERNEL_PTRAUTH_ABI_MASK: u32 = 0x40000000;
+pub const CPU_SUBTYPE_ARM64E_PTRAUTH_MASK: u32 = 0x3f000000;
+/// x86_64 only: a 64-bit library, which older tools set on every x86_64 image.
+pub const CPU_SUBTYPE_LIB64: u32 = 0x80000000;
+
+pub const MH_OBJECT: u32 = 0x1;
+pub const MH_EXECUTE: u32 = 0x2;
+pub const MH_FVMLIB: u32 = 0x3;
+pub const MH_CORE: u32 = 0x4;
+pub const MH_PRELOAD: u32 = 0x5;
+pub const MH_DYLIB: u32 = 0x6;
+pub const MH_DYLINKER: u32 = 0x7;
+pub const MH_BUNDLE: u32 = 0x8;
+pub const MH_DYLIB_STUB: u32 = 0x9;
+pub const MH_DSYM: u32 = 0xa;
+pub const MH_KEXT_BUNDLE: u32 = 0xb;
+pub const MH_FILESET: u32 = 0xc;
+
+/// Architecture names as `lipo` and `-arch` spell them.
+const ARCHES: &[(&str, i32, i32)] = &[
+    ("i386", CPU_TYPE_X86, CPU_SUBTYPE_X86_ALL),
+    ("x86_64", CPU_TYPE_X86_64, CPU_SUBTYPE_X86_ALL),
+    ("x86_64h", CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_H),
+    ("arm", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_ALL),
+    ("armv6", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V6),
+    ("armv7", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7),
+    ("armv7f", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7F),
+    ("armv7s", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7S),
+    ("armv7k", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7K),
+    ("armv6m", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V6M),
+    ("armv7m", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7M),
+    ("armv7em", CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7EM),
+    ("arm64", CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL),
+    ("arm64v8", CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_V8),
+    ("arm64e", CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64E),
+    ("arm64_32", CPU_TYPE_ARM64_32, CPU_SUBTYPE_ARM64_32_V8),
+    ("ppc", CPU_TYPE_POWERPC, CPU_SUBTYPE_POWERPC_ALL),
+    ("ppc64", CPU_TYPE_POWERPC64, CPU_SUBTYPE_POWERPC_ALL),
+];
+
+/// The processor an image runs on, the `cputype` of its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuType {
+    I386,
+    X86_64,
+    Arm,
+    Arm64,
+    Arm64_32,
+    PowerPC,
+    PowerPC64,
+    Unknown(i32),
+}
+
+impl CpuType {
+    pub fn new(cputype: i32) -> CpuType {
+        match cputype {
+            CPU_TYPE_X86 => CpuType::I386,
+            CPU_TYPE_X86_64 => CpuType::X86_64,
+            CPU_TYPE_ARM => CpuType::Arm,
+            CPU_TYPE_ARM64 => CpuType::Arm64,
+            CPU_TYPE_ARM64_32 => CpuType::Arm64_32,
+            CPU_TYPE_POWERPC => CpuType::PowerPC,
+            CPU_TYPE_POWERPC64 => CpuType::PowerPC64,
+            _ => CpuType::Unknown(cputype),
+        }
+    }
+
+    pub fn raw(self) -> i32 {
+        match self {
+            CpuType::I386 => CPU_TYPE_X86,
+            CpuType::X86_64 => CPU_TYPE_X86_64,
+            CpuType::Arm => CPU_TYPE_ARM,
+            CpuType::Arm64 => CPU_TYPE_ARM64,
+            CpuType::Arm64_32 => CPU_TYPE_ARM64_32,
+            CpuType::PowerPC => CPU_TYPE_POWERPC,
+            CpuType::PowerPC64 => CPU_TYPE_POWERPC64,
+            CpuType::Unknown(cputype) => cputype,
+        }
+    }
+
+    /// The `CPU_TYPE_*` constant, `None` for unknown types.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            CpuType::I386 => Some("CPU_TYPE_I386"),
+            CpuType::X86_64 => Some("CPU_TYPE_X86_64"),
+            CpuType::Arm => Some("CPU_TYPE_ARM"),
+            CpuType::Arm64 => Some("CPU_TYPE_ARM64"),
+            CpuType::Arm64_32 => Some("CPU_TYPE_ARM64_32"),
+            CpuType::PowerPC => Some("CPU_TYPE_POWERPC"),
+            CpuType::PowerPC64 => Some("CPU_TYPE_POWERPC64"),
+            CpuType::Unknown(_) => None,
+        }
+    }
+}
+
+/// The processor model within a [`CpuType`], the low bytes of `cpusubtype`.
+/// The capability bits of the high byte are decoded separately, see
+/// [`MachHeader::ptrauth_abi`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuSubtype {
+    I386All,
+    X86_64All,
+    X86_64H,
+    ArmAll,
+    ArmV6,
+    ArmV7,
+    ArmV7F,
+    ArmV7S,
+    ArmV7K,
+    ArmV6M,
+    ArmV7M,
+    ArmV7EM,
+    Arm64All,
+    Arm64V8,
+    Arm64E,
+    Arm64_32V8,
+    PowerPCAll,
+    /// A subtype this crate does not know, or one that does not go with the
+    /// CPU type, with the capability bits masked out.
+    Unknown(i32),
+}
+
+impl CpuSubtype {
+    /// Subtype numbers only mean something for a given CPU type.
+    pub fn new(cputype: i32, cpusubtype: i32) -> CpuSubtype {
+        match (cputype, cpusubtype & !CPU_SUBTYPE_MASK) {
+            (CPU_TYPE_X86, CPU_SUBTYPE_X86_ALL) => CpuSubtype::I386All,
+            (CPU_TYPE_X86_64, CPU_SUBTYPE_X86_ALL) => CpuSubtype::X86_64All,
+            (CPU_TYPE_X86_64, CPU_SUBTYPE_X86_64_H) => CpuSubtype::X86_64H,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_ALL) => CpuSubtype::ArmAll,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V6) => CpuSubtype::ArmV6,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7) => CpuSubtype::ArmV7,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7F) => CpuSubtype::ArmV7F,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7S) => CpuSubtype::ArmV7S,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7K) => CpuSubtype::ArmV7K,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V6M) => CpuSubtype::ArmV6M,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7M) => CpuSubtype::ArmV7M,
+            (CPU_TYPE_ARM, CPU_SUBTYPE_ARM_V7EM) => CpuSubtype::ArmV7EM,
+            (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_ALL) => CpuSubtype::Arm64All,
+            (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64_V8) => CpuSubtype::Arm64V8,
+            (CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64E) => CpuSubtype::Arm64E,
+            (CPU_TYPE_ARM64_32, CPU_SUBTYPE_ARM64_32_V8) => CpuSubtype::Arm64_32V8,
+            (CPU_TYPE_POWERPC | CPU_TYPE_POWERPC64, CPU_SUBTYPE_POWERPC_ALL) => CpuSubtype::PowerPCAll,
+            (_, cpusubtype) => CpuSubtype::Unknown(cpusubtype),
+        }
+    }
+
+    /// The `CPU_SUBTYPE_*` constant, `None` for unknown subtypes.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            CpuSubtype::I386All => Some("CPU_SUBTYPE_I386_ALL"),
+            CpuSubtype::X86_64All => Some("CPU_SUBTYPE_X86_64_ALL"),
+            CpuSubtype::X86_64H => Some("CPU_SUBTYPE_X86_64_H"),
+            CpuSubtype::ArmAll => Some("CPU_SUBTYPE_ARM_ALL"),
+            CpuSubtype::ArmV6 => Some("CPU_SUBTYPE_ARM_V6"),
+            CpuSubtype::ArmV7 => Some("CPU_SUBTYPE_ARM_V7"),
+            CpuSubtype::ArmV7F => Some("CPU_SUBTYPE_ARM_V7F"),
+            CpuSubtype::ArmV7S => Some("CPU_SUBTYPE_ARM_V7S"),
+            CpuSubtype::ArmV7K => Some("CPU_SUBTYPE_ARM_V7K"),
+            CpuSubtype::ArmV6M => Some("CPU_SUBTYPE_ARM_V6M"),
+            CpuSubtype::ArmV7M => Some("CPU_SUBTYPE_ARM_V7M"),
+            CpuSubtype::ArmV7EM => Some("CPU_SUBTYPE_ARM_V7EM"),
+            CpuSubtype::Arm64All => Some("CPU_SUBTYPE_ARM64_ALL"),
+            CpuSubtype::Arm64V8 => Some("CPU_SUBTYPE_ARM64_V8"),
+            CpuSubtype::Arm64E => Some("CPU_SUBTYPE_ARM64E"),
+            CpuSubtype::Arm64_32V8 => Some("CPU_SUBTYPE_ARM64_32_V8"),
+            CpuSubtype::PowerPCAll => Some("CPU_SUBTYPE_POWERPC_ALL"),
+            CpuSubtype::Unknown(_) => None,
+        }
+    }
+}
+
+/// The pointer authentication ABI an arm64e image was built for, from the
+/// capability bits of its `cpusubtype`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PtrauthAbi {
+    pub version: u8,
+    /// The kernel ABI rather than the userspace one.
+    pub kernel: bool,
+}
+
+impl PtrauthAbi {
+    /// `None` for other architectures than arm64e, and for arm64e images
+    /// built before the ABI was versioned.
+    pub fn new(cputype: i32, cpusubtype: i32) -> Option<PtrauthAbi> {
+        let capabilities = cpusubtype as u32;
+        if CpuSubtype::new(cputype, cpusubtype) != CpuSubtype::Arm64E
+            || capabilities & CPU_SUBTYPE_ARM64E_VERSIONED_PTRAUTH_ABI_MASK == 0
+        {
+            return None;
+        }
+
+        Some(PtrauthAbi {
+            version: ((capabilities & CPU_SUBTYPE_ARM64E_PTRAUTH_MASK) >> 24) as u8,
+            kernel: capabilities & CPU_SUBTYPE_ARM64E_KERNEL_PTRAUTH_ABI_MASK != 0,
+        })
+    }
+}
+
+/// What kind of image a Mach-O file is, the `filetype` of its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// `MH_OBJECT`, a relocatable object file.
+    Object,
+    Execute,
+    FvmLib,
+    Core,
+    Preload,
+    Dylib,
+    Dylinker,
+    Bundle,
+    /// `MH_DYLIB_STUB`, a shared library without sections.
+    DylibStub,
+    /// `MH_DSYM`, debug information split off an image.
+    Dsym,
+    KextBundle,
+    /// `MH_FILESET`, a kernel collection holding several images.
+    Fileset,
+    Unknown(u32),
+}
+
+impl FileType {
+    pub fn new(filetype: u32) -> FileType {
+        match filetype {
+            MH_OBJECT => FileType::Object,
+            MH_EXECUTE => FileType::Execute,
+            MH_FVMLIB => FileType::FvmLib,
+            MH_CORE => FileType::Core,
+            MH_PRELOAD => FileType::Preload,
+            MH_DYLIB => FileType::Dylib,
+            MH_DYLINKER => FileType::Dylinker,
+            MH_BUNDLE => FileType::Bundle,
+            MH_DYLIB_STUB => FileType::DylibStub,
+            MH_DSYM => FileType::Dsym,
+            MH_KEXT_BUNDLE => FileType::KextBundle,
+            MH_FILESET => FileType::Fileset,
+            _ => FileType::Unknown(filetype),
+        }
+    }
+
+    pub fn raw(self) -> u32 {
+        match self {
+            FileType::Object => MH_OBJECT,
+            FileType::Execute => MH_EXECUTE,
+            FileType::FvmLib => MH_FVMLIB,
+            FileType::Core => MH_CORE,
+            FileType::Preload => MH_PRELOAD,
+            FileType::Dylib => MH_DYLIB,
+            FileType::Dylinker => MH_DYLINKER,
+            FileType::Bundle => MH_BUNDLE,
+            FileType::DylibStub => MH_DYLIB_STUB,
+            FileType::Dsym => MH_DSYM,
+            FileType::KextBundle => MH_KEXT_BUNDLE,
+            FileType::Fileset => MH_FILESET,
+            FileType::Unknown(filetype) => filetype,
+        }
+    }
+
+    /// The `MH_*` constant, `None` for unknown file types.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            FileType::Object => Some("MH_OBJECT"),
+            FileType::Execute => Some("MH_EXECUTE"),
+            FileType::FvmLib => Some("MH_FVMLIB"),
+            FileType::Core => Some("MH_CORE"),
+            FileType::Preload => Some("MH_PRELOAD"),
+            FileType::Dylib => Some("MH_DYLIB"),
+            FileType::Dylinker => Some("MH_DYLINKER"),
+            FileType::Bundle => Some("MH_BUNDLE"),
+            FileType::DylibStub => Some("MH_DYLIB_STUB"),
+            FileType::Dsym => Some("MH_DSYM"),
+            FileType::KextBundle => Some("MH_KEXT_BUNDLE"),
+            FileType::Fileset => Some("MH_FILESET"),
+            FileType::Unknown(_) => None,
+        }
+    }
+}
+
+bitflags! {
+    /// The `flags` of a Mach-O header.
+    pub struct HeaderFlags: u32 {
+        /// No undefined references, the image is statically linked.
+        const NOUNDEFS = 0x1;
+        const INCRLINK = 0x2;
+        /// Input of the dynamic linker, which cannot be linked statically again.
+        const DYLDLINK = 0x4;
+        const BINDATLOAD = 0x8;
+        const PREBOUND = 0x10;
+        const SPLIT_SEGS = 0x20;
+        const LAZY_INIT = 0x40;
+        /// Symbols are looked up in the library they were linked against.
+        const TWOLEVEL = 0x80;
+        const FORCE_FLAT = 0x100;
+        const NOMULTIDEFS = 0x200;
+        const NOFIXPREBINDING = 0x400;
+        const PREBINDABLE = 0x800;
+        const ALLMODSBOUND = 0x1000;
+        const SUBSECTIONS_VIA_SYMBOLS = 0x2000;
+        const CANONICAL = 0x4000;
+        const WEAK_DEFINES = 0x8000;
+        const BINDS_TO_WEAK = 0x10000;
+        const ALLOW_STACK_EXECUTION = 0x20000;
+        const ROOT_SAFE = 0x40000;
+        const SETUID_SAFE = 0x80000;
+        const NO_REEXPORTED_DYLIBS = 0x100000;
+        /// Loaded at a random address.
+        const PIE = 0x200000;
+        const DEAD_STRIPPABLE_DYLIB = 0x400000;
+        /// Has a `__thread_vars` section of thread-local variables.
+        const HAS_TLV_DESCRIPTORS = 0x800000;
+        const NO_HEAP_EXECUTION = 0x1000000;
+        const APP_EXTENSION_SAFE = 0x2000000;
+        const NLIST_OUTOFSYNC_WITH_DYLDINFO = 0x4000000;
+        const SIM_SUPPORT = 0x8000000;
+        const DYLIB_IN_CACHE = 0x80000000;
+    }
+}
+
+/// Flag names without their `MH_` prefix, as `otool -hv` prints them.
+const HEADER_FLAG_NAMES: &[(HeaderFlags, &str)] = &[
+    (HeaderFlags::NOUNDEFS, "NOUNDEFS"),
+    (HeaderFlags::INCRLINK, "INCRLINK"),
+    (HeaderFlags::DYLDLINK, "DYLDLINK"),
+    (HeaderFlags::BINDATLOAD, "BINDATLOAD"),
+    (HeaderFlags::PREBOUND, "PREBOUND"),
+    (HeaderFlags::SPLIT_SEGS, "SPLIT_SEGS"),
+    (HeaderFlags::LAZY_INIT, "LAZY_INIT"),
+    (HeaderFlags::TWOLEVEL, "TWOLEVEL"),
+    (HeaderFlags::FORCE_FLAT, "FORCE_FLAT"),
+    (HeaderFlags::NOMULTIDEFS, "NOMULTIDEFS"),
+    (HeaderFlags::NOFIXPREBINDING, "NOFIXPREBINDING"),
+    (HeaderFlags::PREBINDABLE, "PREBINDABLE"),
+    (HeaderFlags::ALLMODSBOUND, "ALLMODSBOUND"),
+    (HeaderFlags::SUBSECTIONS_VIA_SYMBOLS, "SUBSECTIONS_VIA_SYMBOLS"),
+    (HeaderFlags::CANONICAL, "CANONICAL"),
+    (HeaderFlags::WEAK_DEFINES, "WEAK_DEFINES"),
+    (HeaderFlags::BINDS_TO_WEAK, "BINDS_TO_WEAK"),
+    (HeaderFlags::ALLOW_STACK_EXECUTION, "ALLOW_STACK_EXECUTION"),
+    (HeaderFlags::ROOT_SAFE, "ROOT_SAFE"),
+    (HeaderFlags::SETUID_SAFE, "SETUID_SAFE"),
+    (HeaderFlags::NO_REEXPORTED_DYLIBS, "NO_REEXPORTED_DYLIBS"),
+    (HeaderFlags::PIE, "PIE"),
+    (HeaderFlags::DEAD_STRIPPABLE_DYLIB, "DEAD_STRIPPABLE_DYLIB"),
+    (HeaderFlags::HAS_TLV_DESCRIPTORS, "HAS_TLV_DESCRIPTORS"),
+    (HeaderFlags::NO_HEAP_EXECUTION, "NO_HEAP_EXECUTION"),
+    (HeaderFlags::APP_EXTENSION_SAFE, "APP_EXTENSION_SAFE"),
+    (HeaderFlags::NLIST_OUTOFSYNC_WITH_DYLDINFO, "NLIST_OUTOFSYNC_WITH_DYLDINFO"),
+    (HeaderFlags::SIM_SUPPORT, "SIM_SUPPORT"),
+    (HeaderFlags::DYLIB_IN_CACHE, "DYLIB_IN_CACHE"),
+];
+
+impl HeaderFlags {
+    /// The names of the flags that are set, in bit order.
+    pub fn names(self) -> Vec<&'static str> {
+        HEADER_FLAG_NAMES.iter().filter(|(flag, _)| self.contains(*flag)).map(|(_, name)| *name).collect()
+    }
+}
+
+/// Unknown values print as their number, so that nothing is lost.
+impl fmt::Display for CpuType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.raw()),
+        }
+    }
+}
+
+impl fmt::Display for CpuSubtype {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuSubtype::Unknown(cpusubtype) => write!(f, "{}", cpusubtype),
+            known => write!(f, "{}", known.name().unwrap_or_default()),
+        }
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:#x}", self.raw()),
+        }
+    }
+}
+
+impl fmt::Display for HeaderFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.names().join(" "))
+    }
+}
+
+/// Named values serialize as their constant names rather than numbers.
+#[cfg(feature = "serde")]
+macro_rules! serialize_as_string {
+    ($($type:ty),*) => {
+        $(impl serde::Serialize for $type {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        })*
+    };
+}
+
+#[cfg(feature = "serde")]
+serialize_as_string!(CpuType, CpuSubtype, FileType);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HeaderFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.names())
+    }
+}
+
+/// The raw header as it is in the file. The accessors decode its numbers
+/// into the types above.
+#[derive(Debug, Clone)]
+pub struct MachHeader {
+    pub magic: u32,
+    pub cputype: i32,
+    pub cpusubtype: i32,
+    pub filetype: u32,
+    pub ncmds: u32,
+    pub sizeofcmds: u32,
+    pub flags: u32,
+    pub reserved: u32,
+}
+
+impl MachHeader {
+    /// Size of `mach_header` or `mach_header_64`, where the load commands start.
+    pub fn size(is_64: bool) -> u64 {
+        if is_64 {
+            32 // 64-bit header size
+        } else {
+            28 // 32-bit header size
+        }
+    }
+
+    pub fn cpu_type(&self) -> CpuType {
+        CpuType::new(self.cputype)
+    }
+
+    pub fn cpu_subtype(&self) -> CpuSubtype {
+        CpuSubtype::new(self.cputype, self.cpusubtype)
+    }
+
+    /// The capability bits in the high byte of `cpusubtype`, shifted down.
+    pub fn capabilities(&self) -> u8 {
+        (self.cpusubtype as u32 >> 24) as u8
+    }
+
+    /// See [`PtrauthAbi::new`].
+    pub fn ptrauth_abi(&self) -> Option<PtrauthAbi> {
+        PtrauthAbi::new(self.cputype, self.cpusubtype)
+    }
+
+    pub fn file_type(&self) -> FileType {
+        FileType::new(self.filetype)
+    }
+
+    /// The known flags, the raw `flags` keep any other bit.
+    pub fn header_flags(&self) -> HeaderFlags {
+        HeaderFlags::from_bits_truncate(self.flags)
+    }
+}
+
+/// The header with its numbers decoded, as `otool --json` prints it.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MachHeader {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("MachHeader", 9)?;
+        state.serialize_field("magic", &self.magic)?;
+        state.serialize_field("cputype", &self.cpu_type())?;
+        state.serialize_field("cpusubtype", &self.cpu_subtype())?;
+        state.serialize_field("capabilities", &self.capabilities())?;
+        state.serialize_field("ptrauth_abi", &self.ptrauth_abi())?;
+        state.serialize_field("filetype", &self.file_type())?;
+        state.serialize_field("ncmds", &self.ncmds)?;
+        state.serialize_field("sizeofcmds", &self.sizeofcmds)?;
+        state.serialize_field("flags", &self.header_flags())?;
+        state.end()
+    }
+}
+
+/// The architecture name Apple's tools print for a CPU type.
+pub fn cpu_type_name(cputype: i32) -> Option<&'static str> {
+    match cputype {
+        CPU_TYPE_X86 => Some("i386"),
+        CPU_TYPE_X86_64 => Some("x86_64"),
+        CPU_TYPE_ARM => Some("arm"),
+        CPU_TYPE_ARM64 => Some("arm64"),
+        CPU_TYPE_ARM64_32 => Some("arm64_32"),
+        CPU_TYPE_POWERPC => Some("ppc"),
+        CPU_TYPE_POWERPC64 => Some("ppc64"),
+        _ => None,
+    }
+}
+
+/// The architecture name of a CPU type and subtype, such as `arm64e`. The
+/// feature flags in the high byte of the subtype are ignored.
+pub fn arch_name(cputype: i32, cpusubtype: i32) -> Option<&'static str> {
+    let cpusubtype = cpusubtype & !CPU_SUBTYPE_MASK;
+
+    ARCHES
+        .iter()
+        .find(|(_, arch_cputype, arch_cpusubtype)| *arch_cputype == cputype && *arch_cpusubtype == cpusubtype)
+        .map(|(name, _, _)| *name)
+}
+
+/// The CPU type and subtype an architecture name stands for.
+pub fn parse_arch(name: &str) -> Option<(i32, i32)> {
+    ARCHES.iter().find(|(arch, _, _)| *arch == name).map(|(_, cputype, cpusubtype)| (*cputype, *cpusubtype))
+}
+
+/// The architecture name for messages, falling back to the bare CPU type
+/// name and then to the number.
+pub fn describe_arch(cputype: i32, cpusubtype: i32) -> String {
+    match arch_name(cputype, cpusubtype).or_else(|| cpu_type_name(cputype)) {
+        Some(name) => name.to_string(),
+        None => format!("cputype {}", cputype),
+    }
+}
+
+/// Reads the magic and works out the word size and byte order of a thin Mach-O file.
+pub fn macho_kind(data: &[u8]) -> Result<(bool, bool)> {
+    let magic = Cursor::new(data).read_u32::<BigEndian>()?;
+
+    match magic {
+        MH_MAGIC => Ok((false, false)),
+        MH_CIGAM => Ok((false, true)),
+        MH_MAGIC_64 => Ok((true, false)),
+        MH_CIGAM_64 => Ok((true, true)),
+        _ if data.starts_with(AR_MAGIC) => Err(Error::StaticArchive),
+        _ => Err(Error::NotMachO(magic)),
+    }
+}
+
+/// Reads the header and the raw load commands of a thin Mach-O file.
+pub fn parse_macho(data: &[u8]) -> Result<(MachHeader, Vec<LoadCommand>)> {
+    let (is_64, is_little_endian) = macho_kind(data)?;
+    let mut cursor = Cursor::new(data);
+
+    if is_little_endian {
+        read_macho::<LittleEndian>(&mut cursor, is_64)
+    } else {
+        read_macho::<BigEndian>(&mut cursor, is_64)
+    }
+}
+
+fn read_macho<T: ByteOrder>(cursor: &mut Cursor<&[u8]>, is_64: bool) -> Result<(MachHeader, Vec<LoadCommand>)> {
+    let header = read_header::<T>(cursor, is_64)?;
+
+    let mut load_commands = Vec::new();
+    for index in 0..header.ncmds as usize {
+        let cmd = cursor.read_u32::<T>()?;
+        let cmdsize = cursor.read_u32::<T>()?;
+        if cmdsize < 8 {
+            return Err(Error::MalformedLoadCommand { index });
+        }
+        // Check before allocating, a bogus cmdsize would otherwise ask for up to 4 GiB
+        let remaining = (cursor.get_ref().len() as u64).saturating_sub(cursor.position());
+        if (cmdsize - 8) as u64 > remaining {
+            return Err(Error::Truncated);
+        }
+        let mut data = vec![0u8; (cmdsize - 8) as usize];
+        cursor.read_exact(&mut data)?;
+        load_commands.push(LoadCommand { cmd, cmdsize, data });
+    }
+
+    Ok((header, load_commands))
+}
+
+pub fn read_header<T: ByteOrder>(cursor: &mut Cursor<&[u8]>, is_64: bool) -> Result<MachHeader> {
+    let magic = cursor.read_u32::<T>()?;
+    let cputype = cursor.read_i32::<T>()?;
+    let cpusubtype = cursor.read_i32::<T>()?;
+    let filetype = cursor.read_u32::<T>()?;
+    let ncmds = cursor.read_u32::<T>()?;
+    let sizeofcmds = cursor.read_u32::<T>()?;
+    let flags = cursor.read_u32::<T>()?;
+    let reserved = if is_64 { cursor.read_u32::<T>()? } else { 0 };
+
+    Ok(MachHeader {
+        magic,
+        cputype,
+        cpusubtype,
+        filetype,
+        ncmds,
+        sizeofcmds,
+        flags,
+        reserved,
+    })
+}
+
+pub fn write_header<T: ByteOrder>(cursor: &mut Cursor<&mut Vec<u8>>, header: &MachHeader, is_64: bool) -> Result<()> {
+    cursor.write_u32::<T>(header.magic)?;
+    cursor.write_i32::<T>(header.cputype)?;
+    cursor.write_i32::<T>(header.cpusubtype)?;
+    cursor.write_u32::<T>(header.filetype)?;
+    cursor.write_u32::<T>(header.ncmds)?;
+    cursor.write_u32::<T>(header.sizeofcmds)?;
+    cursor.write_u32::<T>(header.flags)?;
+    if is_64 {
+        cursor.write_u32::<T>(header.reserved)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn load_commands_past_the_end_of_the_file_are_truncated() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        let (_, load_commands) = parse_macho(&data).unwrap();
+        assert_eq!(load_commands.len(), 2);
+
+        // cmdsize of the first load command
+        LittleEndian::write_u32(&mut data[MachHeader::size(true) as usize + 4..], 0xffff_fff0);
+
+        assert!(matches!(parse_macho(&data), Err(Error::Truncated)));
+    }
+
+    /// An arm64 executable header with the given subtype and flags.
+    fn arm64e_header(cpusubtype: u32, flags: u32) -> MachHeader {
+        MachHeader {
+            magic: MH_MAGIC_64,
+            cputype: CPU_TYPE_ARM64,
+            cpusubtype: cpusubtype as i32,
+            filetype: MH_EXECUTE,
+            ncmds: 0,
+            sizeofcmds: 0,
+            flags,
+            reserved: 0,
+        }
+    }
+
+    #[test]
+    fn cpu_subtypes_depend_on_the_cpu_type() {
+        assert_eq!(CpuType::new(CPU_TYPE_ARM64_32), CpuType::Arm64_32);
+        assert_eq!(CpuType::new(CPU_TYPE_ARM64_32).raw(), CPU_TYPE_ARM64_32);
+        assert_eq!(CpuType::new(99).to_string(), "99");
+        assert_eq!(CpuSubtype::new(CPU_TYPE_X86_64, 3), CpuSubtype::X86_64All);
+        assert_eq!(CpuSubtype::new(CPU_TYPE_ARM64, 2), CpuSubtype::Arm64E);
+        // The same number is another model for another CPU type
+        assert_eq!(CpuSubtype::new(CPU_TYPE_ARM, 2), CpuSubtype::Unknown(2));
+        // The capability bits are not part of the subtype
+        assert_eq!(CpuSubtype::new(CPU_TYPE_X86_64, 0x8000_0003u32 as i32), CpuSubtype::X86_64All);
+        assert_eq!(CpuSubtype::new(CPU_TYPE_ARM64, 0x8000_0007u32 as i32).to_string(), "7");
+        assert_eq!(CpuSubtype::Arm64_32V8.to_string(), "CPU_SUBTYPE_ARM64_32_V8");
+    }
+
+    #[test]
+    fn arm64e_capabilities_hold_the_ptrauth_abi() {
+        let header = arm64e_header(0x8000_0002, 0);
+        assert_eq!(header.capabilities(), 0x80);
+        assert_eq!(header.ptrauth_abi(), Some(PtrauthAbi { version: 0, kernel: false }));
+
+        let kernel = arm64e_header(0xc500_0002, 0);
+        assert_eq!(kernel.ptrauth_abi(), Some(PtrauthAbi { version: 5, kernel: true }));
+
+        // Unversioned arm64e, and the same bits on other subtypes, carry no ABI
+        assert_eq!(arm64e_header(0x0000_0002, 0).ptrauth_abi(), None);
+        assert_eq!(arm64e_header(0x8000_0000, 0).ptrauth_abi(), None);
+    }
+
+    #[test]
+    fn file_types_and_flags_have_their_constant_names() {
+        for filetype in MH_OBJECT..=MH_FILESET {
+            let decoded = FileType::new(filetype);
+            assert_eq!(decoded.raw(), filetype);
+            assert!(decoded.to_string().starts_with("MH_"), "{}", decoded);
+        }
+        assert_eq!(FileType::new(0x42), FileType::Unknown(0x42));
+        assert_eq!(FileType::new(0x42).to_string(), "0x42");
+
+        let header = arm64e_header(0x8000_0002, 0x0020_0085 | 0x4000_0000);
+        assert_eq!(header.header_flags().names(), ["NOUNDEFS", "DYLDLINK", "TWOLEVEL", "PIE"]);
+        assert_eq!(header.header_flags().to_string(), "NOUNDEFS DYLDLINK TWOLEVEL PIE");
+    }
+
+    #[test]
+    fn architectures_are_named_like_lipo_does() {
+        assert_eq!(parse_arch("arm64e"), Some((CPU_TYPE_ARM64, CPU_SUBTYPE_ARM64E)));
+        assert_eq!(parse_arch("armv8"), None);
+        assert_eq!(describe_arch(CPU_TYPE_ARM64, 0x8000_0002u32 as i32), "arm64e");
+        assert_eq!(describe_arch(CPU_TYPE_ARM64, 7), "arm64");
+        assert_eq!(describe_arch(42, 0), "cputype 42");
+    }
+
+    #[test]
+    fn static_archives_are_told_apart_from_other_files() {
+        assert!(matches!(macho_kind(b"!<arch>\n#1/20           "), Err(Error::StaticArchive)));
+        assert!(matches!(macho_kind(b"\x7fELF\x02\x01\x01"), Err(Error::NotMachO(0x7f454c46))));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn headers_serialize_with_their_numbers_decoded() {
+        let json = serde_json::to_value(arm64e_header(0x8000_0002, 0x0020_0085)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "magic": MH_MAGIC_64,
+                "cputype": "CPU_TYPE_ARM64",
+                "cpusubtype": "CPU_SUBTYPE_ARM64E",
+                "capabilities": 0x80,
+                "ptrauth_abi": { "version": 0, "kernel": false },
+                "filetype": "MH_EXECUTE",
+                "ncmds": 0,
+                "sizeofcmds": 0,
+                "flags": ["NOUNDEFS", "DYLDLINK", "TWOLEVEL", "PIE"],
+            })
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use byteorder::{BigEndian, LittleEndian};
+
+use crate::codesign::existing_signature;
+use crate::commands::{decode_commands, BuildVersionCommand, Command, DylibCommand, DylibKind, Section};
+use crate::error::Result;
+use crate::header::{macho_kind, parse_macho, MachHeader};
+use crate::signature::CodeSignature;
+use crate::symbols::SymbolTable;
+
+/// One architecture of a Mach-O file, with its load commands decoded.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub header: MachHeader,
+    pub is_64: bool,
+    pub is_little_endian: bool,
+    pub commands: Vec<Command>,
+    /// The bytes of this architecture alone, starting at its `mach_header`.
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Parses a thin Mach-O file.
+    pub fn parse(data: &[u8]) -> Result<Image> {
+        let (is_64, is_little_endian) = macho_kind(data)?;
+        let (header, load_commands) = parse_macho(data)?;
+
+        let commands = if is_little_endian {
+            decode_commands::<LittleEndian>(&load_commands, is_64)
+        } else {
+            decode_commands::<BigEndian>(&load_commands, is_64)
+        };
+
+        Ok(Image { header, is_64, is_little_endian, commands, data: data.to_vec() })
+    }
+
+    /// The `LC_RPATH` paths in the order dyld searches them.
+    pub fn rpaths(&self) -> Vec<&str> {
+        self.commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::Rpath(rpath) => Some(rpath.path.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The `LC_ID_DYLIB` of a shared library, with its install name and versions.
+    pub fn id_dylib(&self) -> Option<&DylibCommand> {
+        self.dylibs().find(|dylib| dylib.kind() == DylibKind::Id)
+    }
+
+    /// The libraries the image links against, in load order.
+    pub fn dependencies(&self) -> Vec<&DylibCommand> {
+        self.dylibs().filter(|dylib| dylib.kind() != DylibKind::Id).collect()
+    }
+
+    fn dylibs(&self) -> impl Iterator<Item = &DylibCommand> {
+        self.commands.iter().filter_map(|command| match command {
+            Command::Dylib(dylib) => Some(dylib),
+            _ => None,
+        })
+    }
+
+    /// Every section in load order, which is how `n_sect` counts them from 1.
+    pub fn sections(&self) -> impl Iterator<Item = &Section> {
+        self.commands.iter().flat_map(|command| match command {
+            Command::Segment(segment) => segment.sections.as_slice(),
+            _ => &[],
+        })
+    }
+
+    /// The platforms the image was built for with their minimum OS and SDK
+    /// versions, legacy `LC_VERSION_MIN_*` commands included, in load order.
+    pub fn build_versions(&self) -> Vec<BuildVersionCommand> {
+        self.commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::BuildVersion(build_version) => Some(build_version.clone()),
+                Command::VersionMin(version_min) => Some(version_min.to_build_version()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The decoded code signature, `None` when the image is not signed.
+    pub fn code_signature(&self) -> Result<Option<CodeSignature>> {
+        existing_signature(self).map(CodeSignature::parse).transpose()
+    }
+
+    /// The decoded symbol table, `None` when the image has no `LC_SYMTAB`.
+    pub fn symbol_table(&self) -> Result<Option<SymbolTable>> {
+        SymbolTable::parse(self)
+    }
+}
@@ -0,0 +1,123 @@
+use crate::commands::{command_name, Command};
+use crate::error::Result;
+use crate::fat::{parse_fat_header, FatArch};
+use crate::header::MachHeader;
+use crate::macho::MachO;
+use crate::signature::SignatureSummary;
+
+/// Everything there is to know about a file without looking at its contents:
+/// the fat header, and the header, load commands and signature of each slice.
+///
+/// With the `serde` feature this serializes to the JSON that `otool --json`
+/// prints, so that other tools can check binaries without parsing Mach-O.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Inspection {
+    /// The `fat_arch` table, empty for thin files.
+    pub fat_arches: Vec<FatArch>,
+    /// One entry per architecture, in file order.
+    pub images: Vec<ImageInspection>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ImageInspection {
+    pub header: MachHeader,
+    pub load_commands: Vec<LoadCommandInspection>,
+    /// `None` when the image is unsigned.
+    pub code_signature: Option<SignatureSummary>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LoadCommandInspection {
+    /// `LC_*` name, or the command number in hex when it is not a known one.
+    pub name: String,
+    pub cmdsize: u32,
+    pub command: Command,
+}
+
+impl Inspection {
+    pub fn new(macho: &MachO) -> Result<Inspection> {
+        let fat_arches = if macho.is_fat() { parse_fat_header(macho.as_bytes())?.1 } else { Vec::new() };
+
+        let mut images = Vec::new();
+        for image in macho.images()? {
+            let load_commands = image
+                .commands
+                .iter()
+                .map(|command| LoadCommandInspection {
+                    name: command_name(command.cmd()).map_or_else(|| format!("{:#x}", command.cmd()), str::to_string),
+                    cmdsize: command.cmdsize(image.is_64),
+                    command: command.clone(),
+                })
+                .collect();
+            let code_signature = match image.code_signature()? {
+                Some(signature) => Some(signature.summary(&image.data)?),
+                None => None,
+            };
+
+            images.push(ImageInspection { header: image.header.clone(), load_commands, code_signature });
+        }
+
+        Ok(Inspection { fat_arches, images })
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::codesign::{sign_adhoc, SigningOptions};
+    use crate::commands::LC_ID_DYLIB;
+    use crate::fat::FatBinary;
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, CPU_TYPE_X86_64, MH_DYLIB};
+
+    #[test]
+    fn json_describes_every_slice_of_a_universal_file() {
+        let x86_64 = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_DYLIB);
+        let mut arm64 = fixtures::linking(true, true, CPU_TYPE_ARM64, MH_DYLIB, &[(LC_ID_DYLIB, "@rpath/libfoo.dylib")]);
+        sign_adhoc(&mut arm64, &SigningOptions { identifier: "libfoo".to_string(), ..SigningOptions::default() }).unwrap();
+        let universal = FatBinary::create(&[x86_64, arm64]).unwrap().to_bytes();
+
+        let inspection = Inspection::new(&MachO::parse(universal).unwrap()).unwrap();
+        let json = serde_json::to_value(&inspection).unwrap();
+
+        assert_eq!(
+            json["fat_arches"][1],
+            json!({ "cputype": CPU_TYPE_ARM64, "cpusubtype": 0, "offset": 0x4000, "size": 4448, "align": 14, "reserved": 0 })
+        );
+        let images = json["images"].as_array().unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0]["code_signature"], json!(null));
+
+        let arm64 = &images[1];
+        assert_eq!(arm64["header"]["cputype"], "CPU_TYPE_ARM64");
+        assert_eq!(arm64["header"]["filetype"], "MH_DYLIB");
+        assert_eq!(arm64["header"]["flags"], json!([]));
+        let names: Vec<_> = arm64["load_commands"].as_array().unwrap().iter().map(|command| &command["name"]).collect();
+        assert_eq!(names, ["LC_SEGMENT_64", "LC_SEGMENT_64", "LC_ID_DYLIB", "LC_CODE_SIGNATURE"]);
+        assert_eq!(
+            arm64["load_commands"][2],
+            json!({
+                "name": "LC_ID_DYLIB",
+                "cmdsize": 48,
+                "command": {
+                    "kind": "dylib",
+                    "cmd": LC_ID_DYLIB,
+                    "cmdsize": 48,
+                    "name_offset": 24,
+                    "name": "@rpath/libfoo.dylib",
+                    "timestamp": 2,
+                    "current_version": 0x10203,
+                    "compatibility_version": 0x10000,
+                },
+            })
+        );
+        assert_eq!(arm64["code_signature"]["identifier"], "libfoo");
+        assert_eq!(arm64["code_signature"]["hash_type"], "sha256");
+        assert_eq!(arm64["code_signature"]["hashes_valid"], true);
+    }
+}
@@ -0,0 +1,39 @@
+//! Reading and editing Mach-O files without Apple's toolchain.
+//!
+//! [`MachO`] is the entry point: parse a thin or universal binary from bytes,
+//! apply edits, and write the result back out.
+
+pub mod bundle;
+pub mod codesign;
+pub mod commands;
+pub mod error;
+pub mod fat;
+pub mod header;
+pub mod image;
+pub mod inspect;
+pub mod macho;
+pub mod resolve;
+pub mod signature;
+pub mod symbols;
+pub mod tree;
+pub mod version;
+
+mod dylib;
+mod edit;
+#[cfg(test)]
+mod fixtures;
+mod prefix;
+mod rpath;
+
+pub use bundle::Bundler;
+pub use codesign::SigningOptions;
+pub use error::{Error, Result};
+pub use header::{parse_macho, MachHeader};
+pub use image::Image;
+pub use inspect::Inspection;
+pub use macho::MachO;
+pub use resolve::Resolver;
+pub use rpath::DuplicateRpath;
+pub use signature::CodeSignature;
+pub use symbols::SymbolTable;
+pub use tree::DependencyTree;
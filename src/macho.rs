@@ -0,0 +1,275 @@
+use crate::codesign::{self, SigningOptions};
+use crate::commands::BuildVersionCommand;
+use crate::dylib;
+use crate::error::{Error, Result};
+use crate::fat::{self, FatBinary};
+use crate::header::{parse_macho, FileType};
+use crate::image::Image;
+use crate::prefix;
+use crate::rpath::{self, DuplicateRpath};
+use crate::version;
+
+/// The images dyld loads, whose load commands can be rewritten and which can
+/// be signed. Other files either have no room after their load commands, like
+/// object files, or carry load commands that describe another image, like dSYMs.
+const LINKED_FILE_TYPES: &[FileType] = &[FileType::Execute, FileType::Dylib, FileType::Bundle];
+
+/// Prefix replacement never grows a load command, so object files have room
+/// for it too.
+const PREFIX_FILE_TYPES: &[FileType] = &[FileType::Object, FileType::Execute, FileType::Dylib, FileType::Bundle];
+
+/// Object files also record the platform they were compiled for.
+const VERSIONED_FILE_TYPES: &[FileType] = &[FileType::Object, FileType::Execute, FileType::Dylib, FileType::Bundle];
+
+/// A Mach-O file held in memory: either a thin image or a universal binary.
+///
+/// Edits are applied to every architecture unless the document has been
+/// narrowed down to one CPU type with [`MachO::select_cputype`]. Slices that
+/// were signed get a fresh ad-hoc signature after each edit, since any change
+/// to the header invalidates the old one, and so do unsigned arm64 images,
+/// which Apple Silicon only runs when signed.
+#[derive(Debug, Clone)]
+pub struct MachO {
+    data: Vec<u8>,
+    cputype: Option<i32>,
+    duplicate_rpath: DuplicateRpath,
+    auto_sign: bool,
+    signing_identifier: Option<String>,
+}
+
+impl MachO {
+    /// Parses a thin or universal Mach-O file, checking that every slice has a
+    /// readable header and load commands.
+    pub fn parse(data: Vec<u8>) -> Result<MachO> {
+        if fat::is_fat(&data) {
+            for slice in &FatBinary::parse(&data)?.slices {
+                parse_macho(slice)?;
+            }
+        } else {
+            parse_macho(&data)?;
+        }
+
+        Ok(MachO {
+            data,
+            cputype: None,
+            duplicate_rpath: DuplicateRpath::default(),
+            auto_sign: true,
+            signing_identifier: None,
+        })
+    }
+
+    pub fn is_fat(&self) -> bool {
+        fat::is_fat(&self.data)
+    }
+
+    /// Restricts the following edits to the slice with this CPU type, or lifts
+    /// the restriction when given `None`.
+    pub fn select_cputype(&mut self, cputype: Option<i32>) {
+        self.cputype = cputype;
+    }
+
+    /// Chooses whether adding an rpath that already exists fails or is skipped.
+    pub fn set_duplicate_rpath(&mut self, duplicate_rpath: DuplicateRpath) {
+        self.duplicate_rpath = duplicate_rpath;
+    }
+
+    /// Turns re-signing after each edit on or off. It is on by default.
+    pub fn set_auto_sign(&mut self, auto_sign: bool) {
+        self.auto_sign = auto_sign;
+    }
+
+    /// Identifier for re-signed slices whose old signature has none we can
+    /// read, usually the file name.
+    pub fn set_signing_identifier(&mut self, identifier: &str) {
+        self.signing_identifier = Some(identifier.to_string());
+    }
+
+    /// Decodes every architecture, in the order of the `fat_arch` table.
+    pub fn images(&self) -> Result<Vec<Image>> {
+        if self.is_fat() {
+            FatBinary::parse(&self.data)?.slices.iter().map(|slice| Image::parse(slice)).collect()
+        } else {
+            Ok(vec![Image::parse(&self.data)?])
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Adds `path` as the last `LC_RPATH`.
+    pub fn add_rpath(&mut self, path: &str) -> Result<()> {
+        let duplicate_rpath = self.duplicate_rpath;
+        self.edit(LINKED_FILE_TYPES, |slice| rpath::add_rpath(slice, path, duplicate_rpath))
+    }
+
+    /// Adds `path` as an `LC_RPATH` searched before all existing ones.
+    pub fn prepend_rpath(&mut self, path: &str) -> Result<()> {
+        let duplicate_rpath = self.duplicate_rpath;
+        self.edit(LINKED_FILE_TYPES, |slice| rpath::prepend_rpath(slice, path, duplicate_rpath))
+    }
+
+    /// Removes the `LC_RPATH` for `path`.
+    pub fn delete_rpath(&mut self, path: &str) -> Result<()> {
+        self.edit(LINKED_FILE_TYPES, |slice| rpath::delete_rpath(slice, path))
+    }
+
+    /// Rewrites the `LC_RPATH` for `old_path` to `new_path`.
+    pub fn change_rpath(&mut self, old_path: &str, new_path: &str) -> Result<()> {
+        self.edit(LINKED_FILE_TYPES, |slice| rpath::change_rpath(slice, old_path, new_path))
+    }
+
+    /// Sets the install name of a dylib (`LC_ID_DYLIB`).
+    pub fn set_id(&mut self, name: &str) -> Result<()> {
+        self.edit(LINKED_FILE_TYPES, |slice| dylib::set_id(slice, name))
+    }
+
+    /// Rewrites every load of the dylib `old_name` to load `new_name` instead.
+    pub fn change_dylib(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.edit(LINKED_FILE_TYPES, |slice| dylib::change_dylib(slice, old_name, new_name))
+    }
+
+    /// Sets the `LC_BUILD_VERSION` for its platform, replacing the one the
+    /// file has for that platform or converting an `LC_VERSION_MIN_*`.
+    pub fn set_build_version(&mut self, build_version: &BuildVersionCommand) -> Result<()> {
+        self.edit(VERSIONED_FILE_TYPES, |slice| version::set_build_version(slice, build_version))
+    }
+
+    /// Sets the legacy `LC_VERSION_MIN_*` for `platform`, converting an
+    /// `LC_BUILD_VERSION` for the same platform.
+    pub fn set_version_min(&mut self, platform: u32, minos: u32, sdk: u32) -> Result<()> {
+        self.edit(VERSIONED_FILE_TYPES, |slice| version::set_version_min(slice, platform, minos, sdk))
+    }
+
+    /// Removes the build version of `platform`, or every one when `None`.
+    pub fn remove_build_version(&mut self, platform: Option<u32>) -> Result<()> {
+        self.edit(VERSIONED_FILE_TYPES, |slice| version::remove_build_version(slice, platform))
+    }
+
+    /// Moves the file to macOS, the iOS simulator or Mac Catalyst, checking
+    /// that it can run there. Versions that are not given carry over.
+    pub fn retarget_platform(&mut self, platform: u32, minos: Option<u32>, sdk: Option<u32>) -> Result<()> {
+        self.edit(VERSIONED_FILE_TYPES, |slice| version::retarget_platform(slice, platform, minos, sdk))
+    }
+
+    /// Replaces the placeholder prefix `old` with `new`, which must not be
+    /// longer, in load command paths and in the strings of data sections.
+    /// Returns the number of strings that changed, over all edited slices.
+    pub fn replace_prefix(&mut self, old: &[u8], new: &[u8]) -> Result<usize> {
+        let mut count = 0;
+        self.edit(PREFIX_FILE_TYPES, |slice| {
+            count += prefix::replace_prefix(slice, old, new)?;
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    /// Signs the selected slices ad hoc, replacing whatever signature they had.
+    pub fn sign_adhoc(&mut self, options: &SigningOptions) -> Result<()> {
+        fat::edit_slices(&mut self.data, self.cputype, |slice| {
+            check_file_type(slice, LINKED_FILE_TYPES)?;
+            codesign::sign_adhoc(slice, options)
+        })
+    }
+
+    /// Strips the code signature from the selected slices, like
+    /// `codesign --remove-signature`.
+    pub fn remove_signature(&mut self) -> Result<()> {
+        fat::edit_slices(&mut self.data, self.cputype, |slice| {
+            check_file_type(slice, LINKED_FILE_TYPES)?;
+            codesign::remove_signature(slice)
+        })
+    }
+
+    /// Applies `edit` to the selected slices, which must all be of one of
+    /// `file_types`, and re-signs them as [`MachO`] describes.
+    fn edit<F>(&mut self, file_types: &[FileType], mut edit: F) -> Result<()>
+    where
+        F: FnMut(&mut Vec<u8>) -> Result<()>,
+    {
+        let auto_sign = self.auto_sign;
+        let identifier = self.signing_identifier.as_deref();
+
+        fat::edit_slices(&mut self.data, self.cputype, |slice| {
+            check_file_type(slice, file_types)?;
+            edit(slice)?;
+            if auto_sign {
+                codesign::resign_adhoc(slice, identifier)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+fn check_file_type(slice: &[u8], file_types: &[FileType]) -> Result<()> {
+    let file_type = parse_macho(slice)?.0.file_type();
+    if !file_types.contains(&file_type) {
+        return Err(Error::UnsupportedFileType(file_type));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, CPU_TYPE_X86_64, MH_EXECUTE, MH_OBJECT};
+
+    fn universal() -> MachO {
+        let x86_64 = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        let arm64 = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        MachO::parse(FatBinary::create(&[x86_64, arm64]).unwrap().to_bytes()).unwrap()
+    }
+
+    #[test]
+    fn files_that_are_not_mach_o_are_rejected() {
+        assert!(matches!(MachO::parse(vec![0; 64]), Err(Error::NotMachO(0))));
+        assert!(matches!(MachO::parse(vec![0xcf, 0xfa, 0xed, 0xfe]), Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn edits_apply_to_every_slice_and_sign_arm64() {
+        let mut macho = universal();
+        assert!(macho.is_fat());
+
+        macho.add_rpath("@loader_path/../lib").unwrap();
+
+        let images = macho.images().unwrap();
+        assert_eq!(images.len(), 2);
+        for image in &images {
+            assert_eq!(image.rpaths(), ["@loader_path/../lib"]);
+        }
+        assert!(images[0].code_signature().unwrap().is_none());
+        let signature = images[1].code_signature().unwrap().expect("arm64 slice is not signed");
+        assert_eq!(signature.verify(&images[1].data).unwrap(), []);
+    }
+
+    #[test]
+    fn selected_slices_are_the_only_ones_edited() {
+        let mut macho = universal();
+        macho.select_cputype(Some(CPU_TYPE_ARM64));
+        macho.set_auto_sign(false);
+
+        macho.add_rpath("@loader_path/../lib").unwrap();
+
+        let images = macho.images().unwrap();
+        assert!(images[0].rpaths().is_empty());
+        assert_eq!(images[1].rpaths(), ["@loader_path/../lib"]);
+        assert!(images[1].code_signature().unwrap().is_none());
+    }
+
+    #[test]
+    fn edits_check_the_file_type() {
+        let data = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_OBJECT);
+        let mut macho = MachO::parse(data.clone()).unwrap();
+
+        let error = macho.add_rpath("@loader_path").unwrap_err();
+
+        assert!(matches!(error, Error::UnsupportedFileType(FileType::Object)), "{:?}", error);
+        assert_eq!(macho.into_bytes(), data);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::commands::Command;
+use crate::edit::edit_load_commands;
+use crate::error::{Error, Result};
+use crate::image::Image;
+
+/// Replaces every occurrence of the placeholder `old` with `new` in a thin
+/// file, the way conda relocates packages built under a long dummy prefix.
+/// Returns the number of strings that changed.
+///
+/// Strings in load commands are edited as decoded fields and keep their
+/// command size. In the data sections, each C string that contains `old` is
+/// rewritten in place and padded with NULs up to its old length, so no offset
+/// in the file changes. Sections holding instructions are left alone.
+pub(crate) fn replace_prefix(data: &mut Vec<u8>, old: &[u8], new: &[u8]) -> Result<usize> {
+    if new.len() > old.len() {
+        return Err(Error::PrefixTooLong { old: old.len(), new: new.len() });
+    }
+    if old.is_empty() {
+        return Ok(0);
+    }
+
+    let mut count = 0;
+    edit_load_commands(data, |commands, _| {
+        for command in commands.iter_mut() {
+            let strings = match command {
+                Command::Dylib(dylib) => vec![&mut dylib.name],
+                Command::Dylinker(dylinker) => vec![&mut dylinker.name],
+                Command::Rpath(rpath) => vec![&mut rpath.path],
+                Command::LinkerOption(option) => option.strings.iter_mut().collect(),
+                _ => continue,
+            };
+
+            for string in strings {
+                if let Some(replaced) = replace_all(string.as_bytes(), old, new) {
+                    *string = String::from_utf8(replaced).map_err(|_| Error::InvalidString)?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    let image = Image::parse(data)?;
+    for section in image.sections() {
+        if section.is_zerofill() || section.contains_code() || section.offset == 0 {
+            continue;
+        }
+        let start = section.offset as usize;
+        let contents = data.get_mut(start..start + section.size as usize).ok_or(Error::Truncated)?;
+        count += replace_in_strings(contents, old, new);
+    }
+
+    Ok(count)
+}
+
+/// Rewrites every NUL-terminated string in `contents` that contains `old`,
+/// from the first match to its terminator, keeping its length.
+fn replace_in_strings(contents: &mut [u8], old: &[u8], new: &[u8]) -> usize {
+    let mut count = 0;
+    let mut position = 0;
+
+    while let Some(found) = find(&contents[position..], old) {
+        let start = position + found;
+        let end = contents[start..].iter().position(|&byte| byte == 0).map_or(contents.len(), |end| start + end);
+
+        let replaced = replace_all(&contents[start..end], old, new).unwrap_or_default();
+        contents[start..start + replaced.len()].copy_from_slice(&replaced);
+        contents[start + replaced.len()..end].fill(0);
+
+        count += 1;
+        position = end;
+    }
+
+    count
+}
+
+/// `haystack` with every `old` replaced by `new`, or `None` if there is none.
+fn replace_all(haystack: &[u8], old: &[u8], new: &[u8]) -> Option<Vec<u8>> {
+    let mut replaced = Vec::with_capacity(haystack.len());
+    let mut rest = haystack;
+
+    while let Some(found) = find(rest, old) {
+        replaced.extend_from_slice(&rest[..found]);
+        replaced.extend_from_slice(new);
+        rest = &rest[found + old.len()..];
+    }
+
+    if rest.len() == haystack.len() {
+        return None;
+    }
+    replaced.extend_from_slice(rest);
+    Some(replaced)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::*;
+    use crate::commands::LC_LOAD_DYLIB;
+    use crate::fixtures;
+    use crate::header::{MachHeader, CPU_TYPE_ARM64, MH_EXECUTE};
+    use crate::rpath::{add_rpath, DuplicateRpath};
+
+    const OLD: &[u8] = b"/placeholder_placeholder";
+
+    /// An executable loading a library and searching an rpath under the
+    /// placeholder, with strings mentioning it in its only section.
+    fn placeholder_image(section_flags: u32) -> Vec<u8> {
+        let dylibs = [(LC_LOAD_DYLIB, "/placeholder_placeholder/lib/libfoo.dylib")];
+        let mut data = fixtures::linking(true, true, CPU_TYPE_ARM64, MH_EXECUTE, &dylibs);
+        add_rpath(&mut data, "/placeholder_placeholder/lib", DuplicateRpath::Error).unwrap();
+
+        // The flags of __text, the first section of the first segment
+        let flags = MachHeader::size(true) as usize + 72 + 64;
+        LittleEndian::write_u32(&mut data[flags..], section_flags);
+        let strings = b"/placeholder_placeholder/share\0unrelated\0see /placeholder_placeholder/etc/x.conf\0";
+        let text = fixtures::TEXT_OFFSET as usize;
+        data[text..text + strings.len()].copy_from_slice(strings);
+        data
+    }
+
+    #[test]
+    fn prefixes_are_replaced_in_load_commands_and_data() {
+        let mut data = placeholder_image(0);
+        let sizeofcmds = Image::parse(&data).unwrap().header.sizeofcmds;
+
+        assert_eq!(replace_prefix(&mut data, OLD, b"/opt/pkg").unwrap(), 4);
+
+        let image = Image::parse(&data).unwrap();
+        assert_eq!(image.dependencies()[0].name, "/opt/pkg/lib/libfoo.dylib");
+        assert_eq!(image.rpaths(), ["/opt/pkg/lib"]);
+        // Nothing moves, the strings are padded with NULs instead
+        assert_eq!(image.header.sizeofcmds, sizeofcmds);
+        let text = fixtures::TEXT_OFFSET as usize;
+        let mut expected = b"/opt/pkg/share".to_vec();
+        expected.resize(31, 0);
+        expected.extend_from_slice(b"unrelated\0see /opt/pkg/etc/x.conf");
+        expected.resize(81, 0);
+        assert_eq!(data[text..text + expected.len()], expected);
+    }
+
+    #[test]
+    fn sections_holding_code_are_left_alone() {
+        // S_ATTR_PURE_INSTRUCTIONS
+        let mut data = placeholder_image(0x8000_0000);
+        let text = fixtures::TEXT_OFFSET as usize;
+        let original = data[text..fixtures::LINKEDIT_OFFSET as usize].to_vec();
+
+        assert_eq!(replace_prefix(&mut data, OLD, b"/opt/pkg").unwrap(), 2);
+        assert_eq!(data[text..fixtures::LINKEDIT_OFFSET as usize], original);
+    }
+
+    #[test]
+    fn longer_prefixes_are_refused() {
+        let mut data = placeholder_image(0);
+        let original = data.clone();
+
+        let error = replace_prefix(&mut data, OLD, b"/a/much/longer/prefix/than/before").unwrap_err();
+
+        assert!(matches!(error, Error::PrefixTooLong { old: 24, new: 33 }), "{:?}", error);
+        assert_eq!(data, original);
+        assert_eq!(replace_prefix(&mut data, OLD, OLD).unwrap(), 4);
+        assert_eq!(data, original);
+    }
+}
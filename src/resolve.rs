@@ -0,0 +1,320 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::commands::DylibKind;
+use crate::error::{Error, Result};
+use crate::header::FileType;
+use crate::image::Image;
+use crate::macho::MachO;
+
+/// The rpaths of one image on the load chain, with where that image lives so
+/// that `@loader_path` in its rpaths can be expanded.
+#[derive(Debug, Clone)]
+pub struct RpathScope {
+    pub loader: PathBuf,
+    pub rpaths: Vec<String>,
+}
+
+impl RpathScope {
+    /// The rpaths of `image`, which was read from `loader`.
+    pub fn of(loader: &Path, image: &Image) -> RpathScope {
+        RpathScope {
+            loader: loader.to_path_buf(),
+            rpaths: image.rpaths().into_iter().map(str::to_string).collect(),
+        }
+    }
+}
+
+/// One dependency of a binary and where it was found.
+#[derive(Debug)]
+pub struct Dependency {
+    /// The name in the load command, such as `@rpath/libfoo.dylib`.
+    pub install_name: String,
+    pub kind: DylibKind,
+    /// The file dyld would load, or [`Error::LibraryNotFound`] with every
+    /// candidate that was tried.
+    pub path: Result<PathBuf>,
+}
+
+/// Finds dependencies on disk the way dyld does: `@loader_path` and
+/// `@executable_path` are expanded, and `@rpath` is tried against the rpaths
+/// of the loading image first, then against those of every image further up
+/// the load chain, ending with the executable.
+///
+/// Absolute paths, from install names or rpaths, are looked up under the
+/// root when one is set, so that a macOS tree unpacked elsewhere can be
+/// checked.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    root: Option<PathBuf>,
+    executable_path: Option<PathBuf>,
+    cputype: Option<i32>,
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver::default()
+    }
+
+    /// Looks up absolute paths below `root` instead of `/`.
+    pub fn set_root(&mut self, root: Option<&Path>) {
+        self.root = root.map(Path::to_path_buf);
+    }
+
+    /// The main executable, for `@executable_path` and for the rpaths every
+    /// image inherits. Executables resolved on their own stand in for it.
+    pub fn set_executable_path(&mut self, executable_path: Option<&Path>) {
+        self.executable_path = executable_path.map(Path::to_path_buf);
+    }
+
+    pub fn executable_path(&self) -> Option<&Path> {
+        self.executable_path.as_deref()
+    }
+
+    /// Reads the load commands of this architecture in universal binaries
+    /// rather than those of the first one.
+    pub fn select_cputype(&mut self, cputype: Option<i32>) {
+        self.cputype = cputype;
+    }
+
+    /// Resolves every dependency of the binary at `path`, in load order.
+    pub fn resolve_dependencies(&self, path: &Path) -> Result<Vec<Dependency>> {
+        let image = self.load(path)?;
+
+        let mut inherited = Vec::new();
+        if let Some(executable_path) = &self.executable_path {
+            if !same_file(executable_path, path) {
+                let executable = self.load(executable_path)?;
+                inherited.push(RpathScope::of(executable_path, &executable));
+            }
+        }
+
+        Ok(self.resolve_image(path, &image, &inherited))
+    }
+
+    /// Resolves the dependencies of an already parsed `image` that lives at
+    /// `path`, with `inherited` holding the rpaths of the images that loaded
+    /// it, closest first.
+    pub fn resolve_image(&self, path: &Path, image: &Image, inherited: &[RpathScope]) -> Vec<Dependency> {
+        let mut scopes = vec![RpathScope::of(path, image)];
+        scopes.extend_from_slice(inherited);
+
+        // An executable is its own @executable_path
+        let executable_path = match &self.executable_path {
+            Some(executable_path) => Some(executable_path.as_path()),
+            None if image.header.file_type() == FileType::Execute => Some(path),
+            None => None,
+        };
+
+        image
+            .dependencies()
+            .into_iter()
+            .map(|dylib| Dependency {
+                install_name: dylib.name.clone(),
+                kind: dylib.kind(),
+                path: self.resolve(&dylib.name, path, executable_path, &scopes),
+            })
+            .collect()
+    }
+
+    /// Finds the file an install name refers to when loaded from `loader`.
+    pub fn resolve(
+        &self,
+        install_name: &str,
+        loader: &Path,
+        executable_path: Option<&Path>,
+        scopes: &[RpathScope],
+    ) -> Result<PathBuf> {
+        let mut tried = Vec::new();
+
+        if let Some(tail) = install_name.strip_prefix("@rpath/") {
+            for scope in scopes {
+                for rpath in &scope.rpaths {
+                    let candidate = format!("{}/{}", rpath.trim_end_matches('/'), tail);
+                    if let Some(path) = self.try_candidate(&candidate, &scope.loader, executable_path, &mut tried) {
+                        return Ok(path);
+                    }
+                }
+            }
+        } else if let Some(path) = self.try_candidate(install_name, loader, executable_path, &mut tried) {
+            return Ok(path);
+        }
+
+        Err(Error::LibraryNotFound { name: install_name.to_string(), tried })
+    }
+
+    fn try_candidate(
+        &self,
+        candidate: &str,
+        loader: &Path,
+        executable_path: Option<&Path>,
+        tried: &mut Vec<PathBuf>,
+    ) -> Option<PathBuf> {
+        let path = match self.expand(candidate, loader, executable_path) {
+            Some(path) => normalize(&path),
+            None => PathBuf::from(candidate),
+        };
+
+        if path.is_file() {
+            return Some(path);
+        }
+        tried.push(path);
+        None
+    }
+
+    /// Expands the `@` prefixes of `path` and places absolute paths under the
+    /// root. `None` when `@executable_path` is used without an executable.
+    fn expand(&self, path: &str, loader: &Path, executable_path: Option<&Path>) -> Option<PathBuf> {
+        let directory = |file: &Path| file.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        if let Some(tail) = path.strip_prefix("@loader_path") {
+            Some(directory(loader).join(tail.trim_start_matches('/')))
+        } else if let Some(tail) = path.strip_prefix("@executable_path") {
+            Some(directory(executable_path?).join(tail.trim_start_matches('/')))
+        } else if let (Some(root), Some(tail)) = (&self.root, path.strip_prefix('/')) {
+            Some(root.join(tail))
+        } else {
+            Some(PathBuf::from(path))
+        }
+    }
+
+    /// Reads the image at `path`, picking the selected architecture.
+    pub(crate) fn load(&self, path: &Path) -> Result<Image> {
+        let images = MachO::parse(std::fs::read(path)?)?.images()?;
+
+        match self.cputype {
+            Some(cputype) => images
+                .into_iter()
+                .find(|image| image.header.cputype == cputype)
+                .ok_or(Error::NoMatchingArchitecture(cputype)),
+            // Only a universal binary without any architecture has no image
+            None => images.into_iter().next().ok_or(Error::Truncated),
+        }
+    }
+}
+
+/// Whether two paths lead to the same file, following symlinks when both
+/// exist and comparing them lexically otherwise.
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => normalize(a) == normalize(b),
+    }
+}
+
+/// Removes `.` and folds `..` into the preceding component without touching
+/// the filesystem, so that reported paths read like the ones dyld prints.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{LC_ID_DYLIB, LC_LOAD_DYLIB};
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, MH_DYLIB, MH_EXECUTE};
+    use crate::rpath::{add_rpath, DuplicateRpath};
+
+    /// An arm64 image linking `dependencies`, with `rpaths` in that order.
+    fn image(filetype: u32, dependencies: &[&str], rpaths: &[&str]) -> Vec<u8> {
+        let mut dylibs = Vec::new();
+        if filetype == MH_DYLIB {
+            dylibs.push((LC_ID_DYLIB, "@rpath/self.dylib"));
+        }
+        dylibs.extend(dependencies.iter().map(|&name| (LC_LOAD_DYLIB, name)));
+
+        let mut data = fixtures::linking(true, true, CPU_TYPE_ARM64, filetype, &dylibs);
+        for rpath in rpaths {
+            add_rpath(&mut data, rpath, DuplicateRpath::Error).unwrap();
+        }
+        data
+    }
+
+    fn tried(dependency: &Dependency) -> &[PathBuf] {
+        match &dependency.path {
+            Err(Error::LibraryNotFound { tried, .. }) => tried,
+            other => panic!("expected LibraryNotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rpaths_of_the_loader_come_before_those_of_the_executable() {
+        let dir = fixtures::temp_dir("rpath-order");
+        let tool = dir.join("bin/tool");
+        let libfoo = dir.join("lib/libfoo.dylib");
+        fixtures::write(&tool, &image(MH_EXECUTE, &["@rpath/libfoo.dylib"], &["@executable_path/../lib"]));
+        fixtures::write(&libfoo, &image(MH_DYLIB, &["@rpath/libbar.dylib"], &["@loader_path/private"]));
+        fixtures::write(&dir.join("lib/private/libbar.dylib"), b"");
+        fixtures::write(&dir.join("lib/libbar.dylib"), b"");
+
+        let mut resolver = Resolver::new();
+        resolver.set_executable_path(Some(&tool));
+        let dependencies = resolver.resolve_dependencies(&libfoo).unwrap();
+        assert_eq!(dependencies[0].path.as_ref().unwrap(), &dir.join("lib/private/libbar.dylib"));
+
+        std::fs::remove_file(dir.join("lib/private/libbar.dylib")).unwrap();
+        let dependencies = resolver.resolve_dependencies(&libfoo).unwrap();
+        assert_eq!(dependencies[0].path.as_ref().unwrap(), &dir.join("lib/libbar.dylib"));
+
+        std::fs::remove_file(dir.join("lib/libbar.dylib")).unwrap();
+        let dependencies = resolver.resolve_dependencies(&libfoo).unwrap();
+        assert_eq!(tried(&dependencies[0]), [dir.join("lib/private/libbar.dylib"), dir.join("lib/libbar.dylib")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn an_executable_spelled_differently_is_not_searched_twice() {
+        let dir = fixtures::temp_dir("executable-spelling");
+        let tool = dir.join("bin/tool");
+        fixtures::write(&tool, &image(MH_EXECUTE, &["@rpath/libfoo.dylib"], &["@executable_path/../lib"]));
+
+        let mut resolver = Resolver::new();
+        resolver.set_executable_path(Some(&dir.join("bin/./tool")));
+        let dependencies = resolver.resolve_dependencies(&dir.join("bin/../bin/tool")).unwrap();
+
+        assert_eq!(tried(&dependencies[0]), [dir.join("lib/libfoo.dylib")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn absolute_install_names_are_looked_up_under_the_root() {
+        let dir = fixtures::temp_dir("root");
+        let tool = dir.join("tool");
+        fixtures::write(&tool, &image(MH_EXECUTE, &["/usr/lib/libfoo.dylib", "/usr/lib/libbar.dylib"], &[]));
+        fixtures::write(&dir.join("root/usr/lib/libfoo.dylib"), b"");
+
+        let mut resolver = Resolver::new();
+        resolver.set_root(Some(&dir.join("root")));
+        let dependencies = resolver.resolve_dependencies(&tool).unwrap();
+
+        assert_eq!(dependencies[0].path.as_ref().unwrap(), &dir.join("root/usr/lib/libfoo.dylib"));
+        assert_eq!(tried(&dependencies[1]), [dir.join("root/usr/lib/libbar.dylib")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn paths_are_normalized_lexically() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+        assert_eq!(normalize(Path::new("../a/../../b")), Path::new("../../b"));
+    }
+}
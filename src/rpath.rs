@@ -0,0 +1,277 @@
+use crate::commands::{Command, RpathCommand};
+use crate::edit::edit_load_commands;
+use crate::error::{Error, Result};
+
+/// What adding an rpath that is already present should do.
+///
+/// dyld refuses to launch binaries with two identical `LC_RPATH` commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateRpath {
+    /// Fail with [`Error::DuplicateRpath`], like `install_name_tool` does.
+    #[default]
+    Error,
+    /// Leave the file untouched.
+    Ignore,
+}
+
+/// Adds `new_path` as an `LC_RPATH` after the existing load commands of a thin file.
+pub(crate) fn add_rpath(data: &mut Vec<u8>, new_path: &str, duplicate: DuplicateRpath) -> Result<()> {
+    edit_load_commands(data, |commands, is_64| {
+        if is_duplicate(commands, new_path, duplicate)? {
+            return Ok(());
+        }
+        commands.push(Command::Rpath(RpathCommand::new(new_path, is_64)));
+        Ok(())
+    })
+}
+
+/// Adds `new_path` as an `LC_RPATH` searched before all existing ones.
+pub(crate) fn prepend_rpath(data: &mut Vec<u8>, new_path: &str, duplicate: DuplicateRpath) -> Result<()> {
+    edit_load_commands(data, |commands, is_64| {
+        if is_duplicate(commands, new_path, duplicate)? {
+            return Ok(());
+        }
+        let first_rpath = commands
+            .iter()
+            .position(|command| matches!(command, Command::Rpath(_)))
+            .unwrap_or(commands.len());
+        commands.insert(first_rpath, Command::Rpath(RpathCommand::new(new_path, is_64)));
+        Ok(())
+    })
+}
+
+/// Removes the `LC_RPATH` for `path`. The commands after it move up and the
+/// freed bytes at the end of the region become padding again.
+pub(crate) fn delete_rpath(data: &mut Vec<u8>, path: &str) -> Result<()> {
+    edit_load_commands(data, |commands, _| {
+        let index = find_rpath(commands, path)?;
+        commands.remove(index);
+        Ok(())
+    })
+}
+
+/// Replaces the `LC_RPATH` for `old_path` with `new_path`, keeping its position
+/// in the search order.
+///
+/// The command is resized to fit the new path, like a dylib rename: a longer
+/// path moves the commands behind it down into the header padding, and a
+/// shorter one gives the bytes back. Changing to a path that is already present
+/// is always an error, since it would leave the file with a duplicate.
+pub(crate) fn change_rpath(data: &mut Vec<u8>, old_path: &str, new_path: &str) -> Result<()> {
+    edit_load_commands(data, |commands, is_64| {
+        let index = find_rpath(commands, old_path)?;
+        if old_path != new_path {
+            is_duplicate(commands, new_path, DuplicateRpath::Error)?;
+        }
+        if let Command::Rpath(rpath) = &mut commands[index] {
+            rpath.path = new_path.to_string();
+            rpath.cmdsize = RpathCommand::size_for(new_path, is_64);
+        }
+        Ok(())
+    })
+}
+
+fn find_rpath(commands: &[Command], path: &str) -> Result<usize> {
+    commands
+        .iter()
+        .position(|command| matches!(command, Command::Rpath(rpath) if rpath.path == path))
+        .ok_or_else(|| Error::RpathNotFound(path.to_string()))
+}
+
+/// Checks whether `path` is already an rpath. Returns `true` when the caller
+/// should skip adding it, or an error when the policy forbids duplicates.
+fn is_duplicate(commands: &[Command], path: &str, duplicate: DuplicateRpath) -> Result<bool> {
+    let exists = commands.iter().any(|command| matches!(command, Command::Rpath(rpath) if rpath.path == path));
+
+    match (exists, duplicate) {
+        (false, _) => Ok(false),
+        (true, DuplicateRpath::Error) => Err(Error::DuplicateRpath(path.to_string())),
+        (true, DuplicateRpath::Ignore) => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+    use super::*;
+    use crate::commands::LC_RPATH;
+    use crate::error::Error;
+    use crate::fixtures;
+    use crate::header::{parse_macho, MachHeader, CPU_TYPE_POWERPC, CPU_TYPE_POWERPC64, CPU_TYPE_X86, CPU_TYPE_X86_64, MH_EXECUTE};
+    use crate::image::Image;
+
+    #[test]
+    fn add_rpath_writes_big_endian_files_in_their_byte_order() {
+        for (is_64, cputype) in [(false, CPU_TYPE_POWERPC), (true, CPU_TYPE_POWERPC64)] {
+            let mut data = fixtures::thin(is_64, false, cputype, MH_EXECUTE);
+            let (header, _) = parse_macho(&data).unwrap();
+
+            add_rpath(&mut data, "@loader_path/../lib", DuplicateRpath::Error).unwrap();
+
+            // ncmds and sizeofcmds, then the new command right after the old ones
+            assert_eq!(BigEndian::read_u32(&data[16..]), header.ncmds + 1);
+            let size = RpathCommand::size_for("@loader_path/../lib", is_64);
+            assert_eq!(BigEndian::read_u32(&data[20..]), header.sizeofcmds + size);
+            let start = MachHeader::size(is_64) as usize + header.sizeofcmds as usize;
+            assert_eq!(BigEndian::read_u32(&data[start..]), LC_RPATH);
+            assert_eq!(BigEndian::read_u32(&data[start + 4..]), size);
+            assert_eq!(BigEndian::read_u32(&data[start + 8..]), 12);
+
+            let image = Image::parse(&data).unwrap();
+            assert!(!image.is_little_endian);
+            assert_eq!(image.rpaths(), ["@loader_path/../lib"]);
+        }
+    }
+
+    #[test]
+    fn add_rpath_fills_header_padding_without_moving_contents() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        let original = data.clone();
+
+        add_rpath(&mut data, "@executable_path/../Frameworks", DuplicateRpath::Error).unwrap();
+
+        assert_eq!(data.len(), original.len());
+        assert_eq!(data[fixtures::TEXT_OFFSET as usize..], original[fixtures::TEXT_OFFSET as usize..]);
+    }
+
+    #[test]
+    fn not_enough_padding_leaves_the_file_untouched() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        let original = data.clone();
+        let (header, _) = parse_macho(&data).unwrap();
+        let long_path = "x".repeat(fixtures::TEXT_OFFSET as usize);
+
+        let error = add_rpath(&mut data, &long_path, DuplicateRpath::Error).unwrap_err();
+
+        let available = fixtures::TEXT_OFFSET - MachHeader::size(true) - header.sizeofcmds as u64;
+        let needed = RpathCommand::size_for(&long_path, true) as u64;
+        assert!(
+            matches!(error, Error::NotEnoughPadding { needed: n, available: a } if n == needed && a == available),
+            "{:?}",
+            error
+        );
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn rpath_commands_are_word_aligned_with_the_path_at_offset_12() {
+        // 12 bytes of fields, 12 of path and a terminator: 28 in 32-bit files, 32 in 64-bit ones
+        for (is_64, cputype, cmdsize) in [(false, CPU_TYPE_X86, 28), (true, CPU_TYPE_X86_64, 32)] {
+            let mut data = fixtures::thin(is_64, true, cputype, MH_EXECUTE);
+            let (header, _) = parse_macho(&data).unwrap();
+
+            add_rpath(&mut data, "@loader_path", DuplicateRpath::Error).unwrap();
+
+            let start = MachHeader::size(is_64) as usize + header.sizeofcmds as usize;
+            let command = &data[start..start + cmdsize];
+            assert_eq!(LittleEndian::read_u32(&command[4..]), cmdsize as u32);
+            assert_eq!(LittleEndian::read_u32(&command[8..]), 12);
+            assert_eq!(&command[12..24], b"@loader_path");
+            assert!(command[24..].iter().all(|&byte| byte == 0));
+            assert_eq!(parse_macho(&data).unwrap().0.sizeofcmds, header.sizeofcmds + cmdsize as u32);
+        }
+    }
+
+    /// A 64-bit little-endian executable with `paths` as its rpaths, in order.
+    fn with_rpaths(paths: &[&str]) -> Vec<u8> {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+        for path in paths {
+            add_rpath(&mut data, path, DuplicateRpath::Error).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn delete_rpath_closes_the_gap_and_clears_the_freed_bytes() {
+        let mut data = with_rpaths(&["/a", "/b"]);
+        let (header, _) = parse_macho(&data).unwrap();
+
+        delete_rpath(&mut data, "/a").unwrap();
+
+        let (after, _) = parse_macho(&data).unwrap();
+        assert_eq!(after.ncmds, header.ncmds - 1);
+        assert_eq!(after.sizeofcmds, header.sizeofcmds - RpathCommand::size_for("/a", true));
+        assert_eq!(Image::parse(&data).unwrap().rpaths(), ["/b"]);
+        let end = MachHeader::size(true) as usize + after.sizeofcmds as usize;
+        assert!(data[end..fixtures::TEXT_OFFSET as usize].iter().all(|&byte| byte == 0));
+
+        assert!(matches!(delete_rpath(&mut data, "/a"), Err(Error::RpathNotFound(path)) if path == "/a"));
+    }
+
+    #[test]
+    fn change_rpath_resizes_the_command_in_place() {
+        let mut data = with_rpaths(&["/a", "/b"]);
+        let (header, _) = parse_macho(&data).unwrap();
+        let longer = "/opt/homebrew/Cellar/example/1.0/lib";
+
+        change_rpath(&mut data, "/a", longer).unwrap();
+
+        let (grown, _) = parse_macho(&data).unwrap();
+        assert_eq!(grown.ncmds, header.ncmds);
+        let growth = RpathCommand::size_for(longer, true) - RpathCommand::size_for("/a", true);
+        assert_eq!(grown.sizeofcmds, header.sizeofcmds + growth);
+        assert_eq!(Image::parse(&data).unwrap().rpaths(), [longer, "/b"]);
+
+        change_rpath(&mut data, longer, "/c").unwrap();
+
+        let (shrunk, _) = parse_macho(&data).unwrap();
+        assert_eq!(shrunk.sizeofcmds, header.sizeofcmds);
+        assert_eq!(Image::parse(&data).unwrap().rpaths(), ["/c", "/b"]);
+    }
+
+    #[test]
+    fn change_rpath_refuses_to_create_a_duplicate() {
+        let mut data = with_rpaths(&["/a", "/b"]);
+        let original = data.clone();
+
+        let error = change_rpath(&mut data, "/a", "/b").unwrap_err();
+
+        assert!(matches!(error, Error::DuplicateRpath(ref path) if path == "/b"), "{:?}", error);
+        assert_eq!(data, original);
+        // Changing a path to itself is not a duplicate
+        change_rpath(&mut data, "/a", "/a").unwrap();
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn change_rpath_without_room_leaves_the_file_untouched() {
+        let mut data = with_rpaths(&["/a"]);
+        let original = data.clone();
+
+        let error = change_rpath(&mut data, "/a", &"x".repeat(fixtures::TEXT_OFFSET as usize)).unwrap_err();
+
+        assert!(matches!(error, Error::NotEnoughPadding { .. }), "{:?}", error);
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn duplicate_rpaths_follow_the_policy() {
+        let mut data = with_rpaths(&["/a"]);
+        let original = data.clone();
+
+        let error = add_rpath(&mut data, "/a", DuplicateRpath::Error).unwrap_err();
+        assert!(matches!(error, Error::DuplicateRpath(ref path) if path == "/a"), "{:?}", error);
+        let error = prepend_rpath(&mut data, "/a", DuplicateRpath::Error).unwrap_err();
+        assert!(matches!(error, Error::DuplicateRpath(ref path) if path == "/a"), "{:?}", error);
+        assert_eq!(data, original);
+
+        add_rpath(&mut data, "/a", DuplicateRpath::Ignore).unwrap();
+        prepend_rpath(&mut data, "/a", DuplicateRpath::Ignore).unwrap();
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn prepended_rpaths_are_searched_first() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_X86_64, MH_EXECUTE);
+
+        // Without rpaths there is nothing to go before, so it lands at the end
+        prepend_rpath(&mut data, "/b", DuplicateRpath::Error).unwrap();
+        add_rpath(&mut data, "/c", DuplicateRpath::Error).unwrap();
+        prepend_rpath(&mut data, "/a", DuplicateRpath::Error).unwrap();
+
+        let image = Image::parse(&data).unwrap();
+        assert_eq!(image.rpaths(), ["/a", "/b", "/c"]);
+        assert_eq!(image.commands.len(), 5);
+    }
+}
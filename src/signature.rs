@@ -0,0 +1,452 @@
+use std::io::{self, Cursor};
+use byteorder::{BigEndian, ReadBytesExt};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::codesign::{
+    CSMAGIC_BLOBWRAPPER, CSMAGIC_CODEDIRECTORY, CSMAGIC_EMBEDDED_DER_ENTITLEMENTS, CSMAGIC_EMBEDDED_ENTITLEMENTS,
+    CSMAGIC_EMBEDDED_SIGNATURE, CSMAGIC_REQUIREMENTS, CSSLOT_ALTERNATE_CODEDIRECTORIES,
+    CSSLOT_ALTERNATE_CODEDIRECTORY_MAX, CSSLOT_CODEDIRECTORY, CSSLOT_DER_ENTITLEMENTS, CSSLOT_ENTITLEMENTS,
+    CSSLOT_INFOSLOT, CSSLOT_REQUIREMENTS, CSSLOT_RESOURCEDIR, CSSLOT_SIGNATURESLOT, CS_HASHTYPE_SHA1,
+    CS_HASHTYPE_SHA256, CS_HASHTYPE_SHA256_TRUNCATED, CS_HASHTYPE_SHA384,
+};
+use crate::error::{Error, Result};
+
+/// CDHashes are truncated to the length of a SHA-1 digest.
+pub const CS_CDHASH_LEN: usize = 20;
+
+/// One entry of the SuperBlob index together with the blob it points at.
+#[derive(Debug, Clone)]
+pub struct Blob {
+    pub slot: u32,
+    /// Offset of the blob from the start of the SuperBlob.
+    pub offset: u32,
+    pub magic: u32,
+    /// The whole blob, magic and length included.
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    /// The blob without its magic and length.
+    pub fn payload(&self) -> &[u8] {
+        &self.data[8..]
+    }
+}
+
+/// A decoded `CS_CodeDirectory`. Fields newer than the directory's version are zero.
+#[derive(Debug, Clone)]
+pub struct CodeDirectory {
+    /// The SuperBlob slot the directory was found in.
+    pub slot: u32,
+    pub version: u32,
+    pub flags: u32,
+    pub hash_type: u8,
+    pub hash_size: u8,
+    pub platform: u8,
+    /// Code pages are `1 << page_size_shift` bytes, 0 hashes the code as a single page.
+    pub page_size_shift: u8,
+    pub code_limit: u64,
+    pub identifier: String,
+    pub team_id: Option<String>,
+    pub exec_seg_base: u64,
+    pub exec_seg_limit: u64,
+    pub exec_seg_flags: u64,
+    /// Version of the hardened runtime the binary was built against.
+    pub runtime: u32,
+    /// Hashes of special slots 1 to n, slot 1 first.
+    pub special_slot_hashes: Vec<Vec<u8>>,
+    pub code_hashes: Vec<Vec<u8>>,
+    /// Hash of the whole directory, `None` when its hash type is unknown.
+    pub cdhash: Option<Vec<u8>>,
+}
+
+/// One requirement of the internal requirement set.
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    /// `kSecHostRequirementType` (1) to `kSecPluginRequirementType` (5).
+    pub kind: u32,
+    /// The compiled requirement blob.
+    pub data: Vec<u8>,
+}
+
+/// A hash in a CodeDirectory that no longer matches what it covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashMismatch {
+    /// Page `page` of the file, checked against the directory in slot `directory`.
+    CodePage { directory: u32, page: usize },
+    /// The blob in special slot `slot`, checked against the directory in slot `directory`.
+    SpecialSlot { directory: u32, slot: u32 },
+}
+
+/// The facts about a signature that policy checks look at, flattened into
+/// plain values.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SignatureSummary {
+    pub identifier: Option<String>,
+    pub team_id: Option<String>,
+    pub version: u32,
+    pub flags: u32,
+    /// `sha256`, `sha1`, ... or `None` for unknown hash types.
+    pub hash_type: Option<String>,
+    /// CDHash of the primary CodeDirectory in lowercase hex.
+    pub cdhash: Option<String>,
+    pub page_size: u64,
+    pub code_limit: u64,
+    pub code_slots: usize,
+    pub special_slots: usize,
+    /// Number of alternate CodeDirectories.
+    pub alternate_code_directories: usize,
+    pub requirements: usize,
+    /// XML entitlements, when they are valid UTF-8.
+    pub entitlements: Option<String>,
+    pub der_entitlements_size: Option<usize>,
+    pub has_cms_signature: bool,
+    /// Whether every page and special slot hash matches the file.
+    pub hashes_valid: bool,
+}
+
+/// An embedded code signature, the SuperBlob `LC_CODE_SIGNATURE` points at.
+#[derive(Debug, Clone)]
+pub struct CodeSignature {
+    /// Every blob in SuperBlob index order.
+    pub blobs: Vec<Blob>,
+    /// The primary CodeDirectory first, then the alternate ones.
+    pub code_directories: Vec<CodeDirectory>,
+}
+
+impl CodeSignature {
+    /// Parses an embedded signature SuperBlob.
+    pub fn parse(signature: &[u8]) -> Result<CodeSignature> {
+        let blobs = parse_superblob(signature).map_err(|_| Error::MalformedSignature)?;
+
+        let mut code_directories = Vec::new();
+        for blob in &blobs {
+            let is_directory = blob.slot == CSSLOT_CODEDIRECTORY
+                || (CSSLOT_ALTERNATE_CODEDIRECTORIES..CSSLOT_ALTERNATE_CODEDIRECTORIES + CSSLOT_ALTERNATE_CODEDIRECTORY_MAX)
+                    .contains(&blob.slot);
+            if is_directory && blob.magic == CSMAGIC_CODEDIRECTORY {
+                let directory =
+                    parse_code_directory(blob.slot, &blob.data).map_err(|_| Error::MalformedSignature)?;
+                code_directories.push(directory);
+            }
+        }
+        code_directories.sort_by_key(|directory| directory.slot);
+
+        Ok(CodeSignature { blobs, code_directories })
+    }
+
+    /// The blob in `slot`, if the SuperBlob has one.
+    pub fn blob(&self, slot: u32) -> Option<&Blob> {
+        self.blobs.iter().find(|blob| blob.slot == slot)
+    }
+
+    /// The primary CodeDirectory.
+    pub fn code_directory(&self) -> Option<&CodeDirectory> {
+        self.code_directories.iter().find(|directory| directory.slot == CSSLOT_CODEDIRECTORY)
+    }
+
+    /// The internal requirement set, empty when the signature has none.
+    pub fn requirements(&self) -> Result<Vec<Requirement>> {
+        match self.blob(CSSLOT_REQUIREMENTS) {
+            Some(blob) if blob.magic == CSMAGIC_REQUIREMENTS => {
+                parse_requirements(&blob.data).map_err(|_| Error::MalformedSignature)
+            }
+            Some(_) => Err(Error::MalformedSignature),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Entitlements as an XML property list.
+    pub fn entitlements(&self) -> Option<&[u8]> {
+        self.payload(CSSLOT_ENTITLEMENTS, CSMAGIC_EMBEDDED_ENTITLEMENTS)
+    }
+
+    /// Entitlements in their DER encoding.
+    pub fn der_entitlements(&self) -> Option<&[u8]> {
+        self.payload(CSSLOT_DER_ENTITLEMENTS, CSMAGIC_EMBEDDED_DER_ENTITLEMENTS)
+    }
+
+    /// The CMS signature, `None` for ad-hoc signatures whose wrapper is empty.
+    pub fn cms_signature(&self) -> Option<&[u8]> {
+        self.payload(CSSLOT_SIGNATURESLOT, CSMAGIC_BLOBWRAPPER).filter(|cms| !cms.is_empty())
+    }
+
+    /// Summarizes the signature of `data`, the thin image it belongs to.
+    pub fn summary(&self, data: &[u8]) -> Result<SignatureSummary> {
+        let directory = self.code_directory();
+        let hex = |bytes: &Vec<u8>| bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        Ok(SignatureSummary {
+            identifier: directory.map(|directory| directory.identifier.clone()),
+            team_id: directory.and_then(|directory| directory.team_id.clone()),
+            version: directory.map_or(0, |directory| directory.version),
+            flags: directory.map_or(0, |directory| directory.flags),
+            hash_type: directory.and_then(|directory| hash_type_name(directory.hash_type)).map(str::to_string),
+            cdhash: directory.and_then(|directory| directory.cdhash.as_ref()).map(hex),
+            page_size: directory.map_or(0, CodeDirectory::page_size),
+            code_limit: directory.map_or(0, |directory| directory.code_limit),
+            code_slots: directory.map_or(0, |directory| directory.code_hashes.len()),
+            special_slots: directory.map_or(0, |directory| directory.special_slot_hashes.len()),
+            alternate_code_directories: self.code_directories.len().saturating_sub(directory.is_some() as usize),
+            requirements: self.requirements()?.len(),
+            entitlements: self.entitlements().and_then(|xml| String::from_utf8(xml.to_vec()).ok()),
+            der_entitlements_size: self.der_entitlements().map(<[u8]>::len),
+            has_cms_signature: self.cms_signature().is_some(),
+            hashes_valid: directory.is_some() && self.verify(data).is_ok_and(|mismatches| mismatches.is_empty()),
+        })
+    }
+
+    fn payload(&self, slot: u32, magic: u32) -> Option<&[u8]> {
+        self.blob(slot).filter(|blob| blob.magic == magic).map(Blob::payload)
+    }
+
+    /// Checks every CodeDirectory against the file it signs: the page hashes
+    /// against `data` up to the code limit, and the special slot hashes against
+    /// the blobs embedded next to it.
+    ///
+    /// `data` is the thin image the signature belongs to. Slots that describe
+    /// files outside the binary (`Info.plist`, resources) are not checked.
+    pub fn verify(&self, data: &[u8]) -> Result<Vec<HashMismatch>> {
+        let mut mismatches = Vec::new();
+
+        for directory in &self.code_directories {
+            let code = data.get(..directory.code_limit as usize).ok_or(Error::Truncated)?;
+            let pages: Vec<&[u8]> = match directory.page_size() {
+                0 => vec![code],
+                page_size => code.chunks(page_size as usize).collect(),
+            };
+
+            for page in 0..pages.len().max(directory.code_hashes.len()) {
+                let matches = match (pages.get(page), directory.code_hashes.get(page)) {
+                    (Some(bytes), Some(expected)) => directory.hash(bytes)? == *expected,
+                    _ => false,
+                };
+                if !matches {
+                    mismatches.push(HashMismatch::CodePage { directory: directory.slot, page });
+                }
+            }
+
+            for (slot, expected) in (1..).zip(&directory.special_slot_hashes) {
+                let matches = match self.blob(slot) {
+                    Some(blob) => directory.hash(&blob.data)? == *expected,
+                    None => slot == CSSLOT_INFOSLOT || slot == CSSLOT_RESOURCEDIR || expected.iter().all(|&b| b == 0),
+                };
+                if !matches {
+                    mismatches.push(HashMismatch::SpecialSlot { directory: directory.slot, slot });
+                }
+            }
+        }
+
+        Ok(mismatches)
+    }
+}
+
+impl CodeDirectory {
+    /// Size of a code page in bytes, 0 when the code is hashed as a single page.
+    pub fn page_size(&self) -> u64 {
+        match self.page_size_shift {
+            0 => 0,
+            shift => 1u64 << shift,
+        }
+    }
+
+    /// The hash stored for special slot `slot`.
+    pub fn special_slot_hash(&self, slot: u32) -> Option<&[u8]> {
+        self.special_slot_hashes.get((slot as usize).checked_sub(1)?).map(Vec::as_slice)
+    }
+
+    /// Hashes `bytes` the way this directory does, truncated to its hash size.
+    pub fn hash(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut digest = digest(self.hash_type, bytes)?;
+        digest.truncate(self.hash_size as usize);
+        Ok(digest)
+    }
+}
+
+/// The name `codesign` uses for a hash type.
+pub fn hash_type_name(hash_type: u8) -> Option<&'static str> {
+    match hash_type {
+        CS_HASHTYPE_SHA1 => Some("sha1"),
+        CS_HASHTYPE_SHA256 => Some("sha256"),
+        CS_HASHTYPE_SHA256_TRUNCATED => Some("sha256-truncated"),
+        CS_HASHTYPE_SHA384 => Some("sha384"),
+        _ => None,
+    }
+}
+
+fn digest(hash_type: u8, bytes: &[u8]) -> Result<Vec<u8>> {
+    match hash_type {
+        CS_HASHTYPE_SHA1 => Ok(Sha1::digest(bytes).to_vec()),
+        CS_HASHTYPE_SHA256 | CS_HASHTYPE_SHA256_TRUNCATED => Ok(Sha256::digest(bytes).to_vec()),
+        CS_HASHTYPE_SHA384 => Ok(Sha384::digest(bytes).to_vec()),
+        _ => Err(Error::UnsupportedHashType(hash_type)),
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed code signature")
+}
+
+/// Reads the `(type, offset)` index shared by SuperBlobs and requirement sets
+/// and cuts out the blobs it points at.
+fn parse_blob_index(bytes: &[u8], magic: u32) -> io::Result<Vec<(u32, u32, &[u8])>> {
+    let mut cursor = Cursor::new(bytes);
+    if cursor.read_u32::<BigEndian>()? != magic {
+        return Err(malformed());
+    }
+    let _length = cursor.read_u32::<BigEndian>()?;
+    let count = cursor.read_u32::<BigEndian>()?;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let kind = cursor.read_u32::<BigEndian>()?;
+        let offset = cursor.read_u32::<BigEndian>()?;
+
+        let start = offset as usize;
+        let length = bytes
+            .get(start + 4..start + 8)
+            .map(|length| u32::from_be_bytes(length.try_into().unwrap()) as usize)
+            .ok_or_else(malformed)?;
+        let blob = bytes.get(start..start + length).filter(|_| length >= 8).ok_or_else(malformed)?;
+        entries.push((kind, offset, blob));
+    }
+
+    Ok(entries)
+}
+
+fn parse_superblob(signature: &[u8]) -> io::Result<Vec<Blob>> {
+    let entries = parse_blob_index(signature, CSMAGIC_EMBEDDED_SIGNATURE)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|(slot, offset, data)| {
+            let magic = u32::from_be_bytes(data[..4].try_into().unwrap());
+            Blob { slot, offset, magic, data: data.to_vec() }
+        })
+        .collect())
+}
+
+fn parse_requirements(blob: &[u8]) -> io::Result<Vec<Requirement>> {
+    parse_blob_index(blob, CSMAGIC_REQUIREMENTS)
+        .map(|entries| entries.into_iter().map(|(kind, _, data)| Requirement { kind, data: data.to_vec() }).collect())
+}
+
+/// Reads the NUL terminated string at `offset`.
+fn c_string(blob: &[u8], offset: u32) -> io::Result<String> {
+    let tail = blob.get(offset as usize..).ok_or_else(malformed)?;
+    let end = tail.iter().position(|&byte| byte == 0).ok_or_else(malformed)?;
+
+    String::from_utf8(tail[..end].to_vec()).map_err(|_| malformed())
+}
+
+fn parse_code_directory(slot: u32, blob: &[u8]) -> io::Result<CodeDirectory> {
+    let mut cursor = Cursor::new(blob);
+    let _magic = cursor.read_u32::<BigEndian>()?;
+    let _length = cursor.read_u32::<BigEndian>()?;
+    let version = cursor.read_u32::<BigEndian>()?;
+    let flags = cursor.read_u32::<BigEndian>()?;
+    let hash_offset = cursor.read_u32::<BigEndian>()?;
+    let ident_offset = cursor.read_u32::<BigEndian>()?;
+    let n_special_slots = cursor.read_u32::<BigEndian>()?;
+    let n_code_slots = cursor.read_u32::<BigEndian>()?;
+    let code_limit = cursor.read_u32::<BigEndian>()?;
+    let hash_size = cursor.read_u8()?;
+    let hash_type = cursor.read_u8()?;
+    let platform = cursor.read_u8()?;
+    let page_size_shift = cursor.read_u8()?;
+    // A page can't be larger than the address space
+    if page_size_shift >= 64 {
+        return Err(malformed());
+    }
+    let _spare2 = cursor.read_u32::<BigEndian>()?;
+
+    // Later versions append fields, older directories simply end earlier
+    let _scatter_offset = if version >= 0x20100 { cursor.read_u32::<BigEndian>()? } else { 0 };
+    let team_offset = if version >= 0x20200 { cursor.read_u32::<BigEndian>()? } else { 0 };
+    let code_limit_64 = if version >= 0x20300 {
+        let _spare3 = cursor.read_u32::<BigEndian>()?;
+        cursor.read_u64::<BigEndian>()?
+    } else {
+        0
+    };
+    let (exec_seg_base, exec_seg_limit, exec_seg_flags) = if version >= 0x20400 {
+        (cursor.read_u64::<BigEndian>()?, cursor.read_u64::<BigEndian>()?, cursor.read_u64::<BigEndian>()?)
+    } else {
+        (0, 0, 0)
+    };
+    let runtime = if version >= 0x20500 { cursor.read_u32::<BigEndian>()? } else { 0 };
+
+    // Special slot hashes sit right before `hashOffset`, slot -n first
+    let hash = |index: i64| -> io::Result<Vec<u8>> {
+        let start = hash_offset as i64 + index * hash_size as i64;
+        let start = usize::try_from(start).map_err(|_| malformed())?;
+        blob.get(start..start + hash_size as usize).map(<[u8]>::to_vec).ok_or_else(malformed)
+    };
+    let special_slot_hashes = (1..=n_special_slots as i64).map(|slot| hash(-slot)).collect::<io::Result<_>>()?;
+    let code_hashes = (0..n_code_slots as i64).map(hash).collect::<io::Result<_>>()?;
+
+    Ok(CodeDirectory {
+        slot,
+        version,
+        flags,
+        hash_type,
+        hash_size,
+        platform,
+        page_size_shift,
+        code_limit: if code_limit_64 != 0 { code_limit_64 } else { code_limit as u64 },
+        identifier: c_string(blob, ident_offset)?,
+        team_id: if team_offset != 0 { Some(c_string(blob, team_offset)?) } else { None },
+        exec_seg_base,
+        exec_seg_limit,
+        exec_seg_flags,
+        runtime,
+        special_slot_hashes,
+        code_hashes,
+        cdhash: digest(hash_type, blob).ok().map(|mut cdhash| {
+            cdhash.truncate(CS_CDHASH_LEN);
+            cdhash
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, ByteOrder};
+
+    use crate::codesign::{sign_adhoc, SigningOptions};
+    use crate::error::Error;
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, MH_EXECUTE};
+    use crate::image::Image;
+
+    #[test]
+    fn page_size_shifts_of_64_and_more_are_malformed() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        sign_adhoc(&mut data, &SigningOptions { identifier: "tool".to_string(), ..SigningOptions::default() }).unwrap();
+        let signature_offset = fixtures::LINKEDIT_OFFSET as usize + fixtures::LINKEDIT_SIZE as usize;
+        // The CodeDirectory comes first in the SuperBlob index, its pageSize byte is at offset 39
+        let directory_offset = signature_offset + BigEndian::read_u32(&data[signature_offset + 16..]) as usize;
+        let signature = Image::parse(&data).unwrap().code_signature().unwrap().unwrap();
+        assert_eq!(signature.code_directory().unwrap().page_size(), 0x1000);
+
+        data[directory_offset + 39] = 64;
+
+        let image = Image::parse(&data).unwrap();
+        assert!(matches!(image.code_signature(), Err(Error::MalformedSignature)));
+    }
+
+    #[test]
+    fn summaries_of_files_cut_short_report_invalid_hashes() {
+        let mut data = fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE);
+        sign_adhoc(&mut data, &SigningOptions { identifier: "tool".to_string(), ..SigningOptions::default() }).unwrap();
+        let signature = Image::parse(&data).unwrap().code_signature().unwrap().unwrap();
+        assert!(signature.summary(&data).unwrap().hashes_valid);
+
+        // The code limit lies past the end, which verify reports as truncated
+        let summary = signature.summary(&data[..fixtures::TEXT_OFFSET as usize]).unwrap();
+
+        assert!(!summary.hashes_valid);
+        assert_eq!(summary.identifier.as_deref(), Some("tool"));
+    }
+}
@@ -0,0 +1,368 @@
+use std::io::Cursor;
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+
+use crate::commands::{Command, DysymtabCommand, SymtabCommand};
+use crate::error::{Error, Result};
+use crate::image::Image;
+
+/// Set on debugger (stab) entries, which use the whole of `n_type` for their own kinds.
+pub const N_STAB: u8 = 0xe0;
+pub const N_PEXT: u8 = 0x10;
+pub const N_TYPE: u8 = 0x0e;
+pub const N_EXT: u8 = 0x01;
+
+pub const N_UNDF: u8 = 0x0;
+pub const N_ABS: u8 = 0x2;
+pub const N_SECT: u8 = 0xe;
+pub const N_PBUD: u8 = 0xc;
+pub const N_INDR: u8 = 0xa;
+
+pub const NO_SECT: u8 = 0;
+
+pub const REFERENCED_DYNAMICALLY: u16 = 0x0010;
+pub const N_NO_DEAD_STRIP: u16 = 0x0020;
+pub const N_WEAK_REF: u16 = 0x0040;
+/// `N_WEAK_DEF` on a definition, `N_REF_TO_WEAK` on an undefined symbol.
+pub const N_WEAK_DEF: u16 = 0x0080;
+pub const N_ALT_ENTRY: u16 = 0x0200;
+pub const N_COLD_FUNC: u16 = 0x0400;
+
+pub const SELF_LIBRARY_ORDINAL: u8 = 0x0;
+pub const DYNAMIC_LOOKUP_ORDINAL: u8 = 0xfe;
+pub const EXECUTABLE_ORDINAL: u8 = 0xff;
+
+/// Indirect symbol table entries for symbols that were made local or absolute.
+pub const INDIRECT_SYMBOL_LOCAL: u32 = 0x80000000;
+pub const INDIRECT_SYMBOL_ABS: u32 = 0x40000000;
+
+/// What a symbol is, from the `N_TYPE` bits of `n_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "snake_case"))]
+pub enum SymbolType {
+    /// `N_UNDF`, defined in another image. A non-zero value makes it a
+    /// common symbol of that size.
+    Undefined,
+    /// `N_ABS`, the value is an address that does not move.
+    Absolute,
+    /// `N_SECT`, defined in the section `n_sect`, counting from 1.
+    Section,
+    /// `N_PBUD`, undefined but prebound to the value.
+    PreboundUndefined,
+    /// `N_INDR`, the same as the symbol whose name `n_value` indexes.
+    Indirect,
+    /// A debugger entry with this stab type.
+    Debug(u8),
+    Unknown(u8),
+}
+
+/// Where the definition of an undefined symbol is looked up, from the high
+/// byte of `n_desc` in two-level namespace images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "snake_case"))]
+pub enum LibraryOrdinal {
+    /// The image itself.
+    SelfImage,
+    /// The dependency at this index of [`Image::dependencies`], counting from 0.
+    Dependency(usize),
+    /// Every loaded image, in load order, as in a flat namespace.
+    DynamicLookup,
+    /// The main executable, for plugins.
+    Executable,
+}
+
+/// An `nlist` or `nlist_64` entry with its name looked up in the string table.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Symbol {
+    pub name: String,
+    pub n_type: u8,
+    pub n_sect: u8,
+    pub n_desc: u16,
+    pub n_value: u64,
+}
+
+impl Symbol {
+    pub fn symbol_type(&self) -> SymbolType {
+        if self.n_type & N_STAB != 0 {
+            return SymbolType::Debug(self.n_type);
+        }
+
+        match self.n_type & N_TYPE {
+            N_UNDF => SymbolType::Undefined,
+            N_ABS => SymbolType::Absolute,
+            N_SECT => SymbolType::Section,
+            N_PBUD => SymbolType::PreboundUndefined,
+            N_INDR => SymbolType::Indirect,
+            n_type => SymbolType::Unknown(n_type),
+        }
+    }
+
+    pub fn is_debug(&self) -> bool {
+        self.n_type & N_STAB != 0
+    }
+
+    /// Visible to other images. Private externs are not: the static linker
+    /// made them local, and only kept the bit to say so.
+    pub fn is_external(&self) -> bool {
+        !self.is_debug() && self.n_type & N_EXT != 0
+    }
+
+    pub fn is_private_external(&self) -> bool {
+        !self.is_debug() && self.n_type & N_PEXT != 0
+    }
+
+    /// Undefined and not a common symbol.
+    pub fn is_undefined(&self) -> bool {
+        matches!(self.symbol_type(), SymbolType::Undefined | SymbolType::PreboundUndefined) && !self.is_common()
+    }
+
+    /// A tentative definition in an object file, whose value is its size.
+    pub fn is_common(&self) -> bool {
+        self.symbol_type() == SymbolType::Undefined && self.is_external() && self.n_value != 0
+    }
+
+    /// An undefined symbol that may be missing at runtime.
+    pub fn is_weak_reference(&self) -> bool {
+        !self.is_debug() && self.n_desc & N_WEAK_REF != 0
+    }
+
+    /// A definition that others of the same name may override.
+    pub fn is_weak_definition(&self) -> bool {
+        !self.is_debug() && !self.is_undefined() && self.n_desc & N_WEAK_DEF != 0
+    }
+
+    /// The library an undefined symbol binds to, `None` for other symbols.
+    /// Only meaningful in images with the `TWOLEVEL` header flag.
+    pub fn library_ordinal(&self) -> Option<LibraryOrdinal> {
+        if !self.is_undefined() {
+            return None;
+        }
+
+        match (self.n_desc >> 8) as u8 {
+            SELF_LIBRARY_ORDINAL => Some(LibraryOrdinal::SelfImage),
+            DYNAMIC_LOOKUP_ORDINAL => Some(LibraryOrdinal::DynamicLookup),
+            EXECUTABLE_ORDINAL => Some(LibraryOrdinal::Executable),
+            ordinal => Some(LibraryOrdinal::Dependency(ordinal as usize - 1)),
+        }
+    }
+}
+
+/// The symbols of an image, with the groups `LC_DYSYMTAB` sorts them into.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SymbolTable {
+    /// Every entry in file order, debug entries included.
+    pub symbols: Vec<Symbol>,
+    pub dysymtab: Option<DysymtabCommand>,
+    /// Symbol indices for the stubs and pointers of the sections that have
+    /// them, or `INDIRECT_SYMBOL_LOCAL` and `INDIRECT_SYMBOL_ABS`.
+    pub indirect_symbols: Vec<u32>,
+}
+
+impl SymbolTable {
+    /// Reads the table `LC_SYMTAB` points at, `None` when the image has none.
+    pub fn parse(image: &Image) -> Result<Option<SymbolTable>> {
+        let mut symtab = None;
+        let mut dysymtab = None;
+        for command in &image.commands {
+            match command {
+                Command::Symtab(command) => symtab = Some(command),
+                Command::Dysymtab(command) => dysymtab = Some(command),
+                _ => {}
+            }
+        }
+        let Some(symtab) = symtab else {
+            return Ok(None);
+        };
+
+        let (symbols, indirect_symbols) = if image.is_little_endian {
+            read_table::<LittleEndian>(image, symtab, dysymtab)?
+        } else {
+            read_table::<BigEndian>(image, symtab, dysymtab)?
+        };
+
+        Ok(Some(SymbolTable { symbols, dysymtab: dysymtab.cloned(), indirect_symbols }))
+    }
+
+    /// Local symbols, debug entries included.
+    pub fn locals(&self) -> &[Symbol] {
+        self.group(|dysymtab| (dysymtab.ilocalsym, dysymtab.nlocalsym), |symbol| !symbol.is_external())
+    }
+
+    /// The symbols the image exports.
+    pub fn defined_externals(&self) -> &[Symbol] {
+        self.group(|dysymtab| (dysymtab.iextdefsym, dysymtab.nextdefsym), |symbol| {
+            symbol.is_external() && !symbol.is_undefined()
+        })
+    }
+
+    /// The symbols other images have to provide.
+    pub fn undefined(&self) -> &[Symbol] {
+        self.group(|dysymtab| (dysymtab.iundefsym, dysymtab.nundefsym), |symbol| {
+            symbol.is_external() && symbol.is_undefined()
+        })
+    }
+
+    /// The range `LC_DYSYMTAB` gives for a group. Without one the linker
+    /// still sorts the table by group, so the range is found by testing.
+    fn group<R, P>(&self, range: R, predicate: P) -> &[Symbol]
+    where
+        R: Fn(&DysymtabCommand) -> (u32, u32),
+        P: Fn(&Symbol) -> bool,
+    {
+        if let Some(dysymtab) = &self.dysymtab {
+            let (start, count) = range(dysymtab);
+            return self.symbols.get(start as usize..start as usize + count as usize).unwrap_or(&[]);
+        }
+
+        let start = self.symbols.iter().position(&predicate).unwrap_or(self.symbols.len());
+        let count = self.symbols[start..].iter().take_while(|symbol| predicate(symbol)).count();
+        &self.symbols[start..start + count]
+    }
+}
+
+fn read_table<T: ByteOrder>(
+    image: &Image,
+    symtab: &SymtabCommand,
+    dysymtab: Option<&DysymtabCommand>,
+) -> Result<(Vec<Symbol>, Vec<u32>)> {
+    let entry_size = if image.is_64 { 16 } else { 12 };
+    let start = symtab.symoff as usize;
+    let entries = image.data.get(start..start + symtab.nsyms as usize * entry_size).ok_or(Error::Truncated)?;
+    let start = symtab.stroff as usize;
+    let strings = image.data.get(start..start + symtab.strsize as usize).ok_or(Error::Truncated)?;
+
+    let mut cursor = Cursor::new(entries);
+    let mut symbols = Vec::with_capacity(symtab.nsyms as usize);
+    for _ in 0..symtab.nsyms {
+        let n_strx = cursor.read_u32::<T>()?;
+        let n_type = cursor.read_u8()?;
+        let n_sect = cursor.read_u8()?;
+        let n_desc = cursor.read_u16::<T>()?;
+        let n_value = if image.is_64 { cursor.read_u64::<T>()? } else { cursor.read_u32::<T>()? as u64 };
+
+        // Names are bytes, usually but not always UTF-8
+        let name = strings.get(n_strx as usize..).unwrap_or_default();
+        let name = &name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())];
+
+        symbols.push(Symbol { name: String::from_utf8_lossy(name).into_owned(), n_type, n_sect, n_desc, n_value });
+    }
+
+    let mut indirect_symbols = Vec::new();
+    if let Some(dysymtab) = dysymtab {
+        let start = dysymtab.indirectsymoff as usize;
+        let table = image.data.get(start..start + dysymtab.nindirectsyms as usize * 4).ok_or(Error::Truncated)?;
+        let mut cursor = Cursor::new(table);
+        for _ in 0..dysymtab.nindirectsyms {
+            indirect_symbols.push(cursor.read_u32::<T>()?);
+        }
+    }
+
+    Ok((symbols, indirect_symbols))
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, CPU_TYPE_POWERPC, MH_EXECUTE};
+
+    /// `(name, n_type, n_sect, n_desc, n_value)`, sorted the way the linker
+    /// groups them: locals, then defined externals, then undefined ones.
+    const SYMBOLS: &[(&str, u8, u8, u16, u64)] = &[
+        ("_main.c", 0x64, NO_SECT, 0, 0), // N_SO
+        ("_helper", N_SECT, 1, 0, 0x800),
+        ("_hidden", N_SECT | N_PEXT, 1, 0, 0x810),
+        ("_main", N_SECT | N_EXT, 1, 0, 0x820),
+        ("_buffer", N_UNDF | N_EXT, NO_SECT, 0, 64),
+        ("_printf", N_UNDF | N_EXT, NO_SECT, 1 << 8, 0),
+        ("_maybe", N_UNDF | N_EXT, NO_SECT, (DYNAMIC_LOOKUP_ORDINAL as u16) << 8 | N_WEAK_REF, 0),
+    ];
+
+    /// An image with `SYMBOLS` in an `LC_SYMTAB` table after its `__LINKEDIT`.
+    fn image_with_symbols<T: ByteOrder>(is_64: bool, cputype: i32) -> Image {
+        let is_little_endian = T::read_u16(&[1, 0]) == 1;
+        let mut image = Image::parse(&fixtures::thin(is_64, is_little_endian, cputype, MH_EXECUTE)).unwrap();
+
+        let mut strings = vec![b' ', 0];
+        let symoff = image.data.len() as u32;
+        for &(name, n_type, n_sect, n_desc, n_value) in SYMBOLS {
+            image.data.write_u32::<T>(strings.len() as u32).unwrap();
+            image.data.extend_from_slice(&[n_type, n_sect]);
+            image.data.write_u16::<T>(n_desc).unwrap();
+            if is_64 {
+                image.data.write_u64::<T>(n_value).unwrap();
+            } else {
+                image.data.write_u32::<T>(n_value as u32).unwrap();
+            }
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+        let stroff = image.data.len() as u32;
+        image.data.extend_from_slice(&strings);
+
+        let symtab = SymtabCommand { symoff, nsyms: SYMBOLS.len() as u32, stroff, strsize: strings.len() as u32 };
+        image.commands.push(Command::Symtab(symtab));
+        image
+    }
+
+    #[test]
+    fn nlist_entries_are_read_in_both_sizes_and_byte_orders() {
+        let little_endian_64 = image_with_symbols::<LittleEndian>(true, CPU_TYPE_ARM64);
+        let big_endian_32 = image_with_symbols::<BigEndian>(false, CPU_TYPE_POWERPC);
+        for image in [little_endian_64, big_endian_32] {
+            let table = SymbolTable::parse(&image).unwrap().unwrap();
+
+            let decoded: Vec<_> = table
+                .symbols
+                .iter()
+                .map(|symbol| (symbol.name.as_str(), symbol.n_type, symbol.n_sect, symbol.n_desc, symbol.n_value))
+                .collect();
+            assert_eq!(decoded, SYMBOLS);
+            assert!(table.indirect_symbols.is_empty());
+        }
+    }
+
+    #[test]
+    fn symbols_are_classified_by_type_and_description() {
+        let image = image_with_symbols::<LittleEndian>(true, CPU_TYPE_ARM64);
+        let table = SymbolTable::parse(&image).unwrap().unwrap();
+        let [stab, helper, hidden, main, buffer, printf, maybe] = table.symbols.as_slice() else {
+            panic!("expected {} symbols", SYMBOLS.len());
+        };
+
+        assert_eq!(stab.symbol_type(), SymbolType::Debug(0x64));
+        assert!(stab.is_debug() && !stab.is_external());
+        assert_eq!(helper.symbol_type(), SymbolType::Section);
+        assert!(!helper.is_external() && !hidden.is_external() && hidden.is_private_external());
+        assert!(main.is_external() && !main.is_undefined());
+        assert!(buffer.is_common() && !buffer.is_undefined());
+        assert_eq!(printf.library_ordinal(), Some(LibraryOrdinal::Dependency(0)));
+        assert_eq!(maybe.library_ordinal(), Some(LibraryOrdinal::DynamicLookup));
+        assert!(maybe.is_weak_reference() && !maybe.is_weak_definition());
+        assert_eq!(main.library_ordinal(), None);
+    }
+
+    #[test]
+    fn groups_are_found_without_a_dysymtab() {
+        let image = image_with_symbols::<LittleEndian>(true, CPU_TYPE_ARM64);
+        let table = SymbolTable::parse(&image).unwrap().unwrap();
+        let names = |symbols: &[Symbol]| symbols.iter().map(|symbol| symbol.name.clone()).collect::<Vec<_>>();
+
+        assert_eq!(names(table.locals()), ["_main.c", "_helper", "_hidden"]);
+        assert_eq!(names(table.defined_externals()), ["_main", "_buffer"]);
+        assert_eq!(names(table.undefined()), ["_printf", "_maybe"]);
+    }
+
+    #[test]
+    fn tables_past_the_end_of_the_file_are_truncated() {
+        let mut image = image_with_symbols::<LittleEndian>(true, CPU_TYPE_ARM64);
+        image.data.truncate(image.data.len() - 1);
+
+        assert!(matches!(SymbolTable::parse(&image), Err(Error::Truncated)));
+        let stripped = Image::parse(&fixtures::thin(true, true, CPU_TYPE_ARM64, MH_EXECUTE)).unwrap();
+        assert!(SymbolTable::parse(&stripped).unwrap().is_none());
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::commands::DylibKind;
+use crate::error::{Error, Result};
+use crate::header::FileType;
+use crate::image::Image;
+use crate::resolve::{same_file, Resolver, RpathScope};
+
+/// Install name prefixes of libraries that ship with macOS. Since Big Sur
+/// they only exist inside the dyld shared cache, never as files.
+pub const SYSTEM_PREFIXES: &[&str] = &["/usr/lib/", "/System/"];
+
+/// File name prefixes of the OS libraries that binaries load through `@rpath`,
+/// such as the Swift runtime in `/usr/lib/swift`.
+pub const SYSTEM_RPATH_LIBRARIES: &[&str] = &["libswift"];
+
+/// What became of one dependency while walking the tree.
+#[derive(Debug)]
+pub enum Resolution {
+    /// Found on disk, with its own dependencies listed below it.
+    Found(PathBuf),
+    /// Found on disk, but listed earlier in the tree already, so its
+    /// dependencies are not repeated.
+    AlreadyListed(PathBuf),
+    /// Loads one of the libraries it is loaded by.
+    Cycle(PathBuf),
+    /// Part of the operating system and not expected on disk.
+    System,
+    /// Found on disk, but not a Mach-O file that could be read.
+    Unreadable(PathBuf, Error),
+    /// Not found anywhere dyld would look.
+    Missing(Error),
+}
+
+#[derive(Debug)]
+pub struct DependencyNode {
+    pub install_name: String,
+    pub kind: DylibKind,
+    pub resolution: Resolution,
+    pub dependencies: Vec<DependencyNode>,
+}
+
+/// Every library a binary loads, directly or through other libraries.
+#[derive(Debug)]
+pub struct DependencyTree {
+    pub path: PathBuf,
+    pub dependencies: Vec<DependencyNode>,
+}
+
+/// Whether dyld resolves `install_name` from the shared cache.
+pub fn is_system_library(install_name: &str) -> bool {
+    SYSTEM_PREFIXES.iter().any(|prefix| install_name.starts_with(prefix))
+}
+
+/// Whether `install_name` is an `@rpath/` name of an OS library, which dyld
+/// finds in the shared cache through a system rpath.
+fn is_system_rpath_library(install_name: &str) -> bool {
+    let Some(tail) = install_name.strip_prefix("@rpath/") else {
+        return false;
+    };
+    let leaf = tail.rsplit('/').next().unwrap_or(tail);
+
+    SYSTEM_RPATH_LIBRARIES.iter().any(|prefix| leaf.starts_with(prefix))
+}
+
+impl DependencyTree {
+    /// Walks the dependencies of the binary at `path` depth first.
+    ///
+    /// Every library is expanded once; later loads of the same file show up
+    /// as [`Resolution::AlreadyListed`], and loads of a library that is
+    /// currently being expanded as [`Resolution::Cycle`].
+    pub fn walk(resolver: &Resolver, path: &Path) -> Result<DependencyTree> {
+        let image = resolver.load(path)?;
+
+        // Libraries deeper down inherit the executable's rpaths and @executable_path
+        let mut resolver = resolver.clone();
+        let mut inherited = Vec::new();
+        match resolver.executable_path() {
+            Some(executable_path) if !same_file(executable_path, path) => {
+                let executable = resolver.load(executable_path)?;
+                inherited.push(RpathScope::of(executable_path, &executable));
+            }
+            Some(_) => {}
+            None if image.header.file_type() == FileType::Execute => resolver.set_executable_path(Some(path)),
+            None => {}
+        }
+
+        let mut walker = Walker { resolver: &resolver, ancestors: vec![canonical(path)], listed: HashSet::new() };
+        walker.listed.insert(canonical(path));
+        let dependencies = walker.walk(path, &image, &inherited);
+
+        Ok(DependencyTree { path: path.to_path_buf(), dependencies })
+    }
+
+    /// Every library once, in the order the walk first met it.
+    pub fn flatten(&self) -> Vec<&DependencyNode> {
+        fn collect<'a>(nodes: &'a [DependencyNode], seen: &mut HashSet<String>, flat: &mut Vec<&'a DependencyNode>) {
+            for node in nodes {
+                let key = match &node.resolution {
+                    Resolution::Found(path) | Resolution::AlreadyListed(path) | Resolution::Cycle(path) => {
+                        path.display().to_string()
+                    }
+                    _ => node.install_name.clone(),
+                };
+                if seen.insert(key) {
+                    flat.push(node);
+                }
+                collect(&node.dependencies, seen, flat);
+            }
+        }
+
+        let mut flat = Vec::new();
+        collect(&self.dependencies, &mut HashSet::new(), &mut flat);
+        flat
+    }
+}
+
+struct Walker<'a> {
+    resolver: &'a Resolver,
+    /// The libraries being expanded, from the root down to the current one.
+    ancestors: Vec<PathBuf>,
+    /// Every library expanded so far.
+    listed: HashSet<PathBuf>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, path: &Path, image: &Image, inherited: &[RpathScope]) -> Vec<DependencyNode> {
+        let mut scopes = vec![RpathScope::of(path, image)];
+        scopes.extend_from_slice(inherited);
+        let searches_system_rpath = scopes.iter().flat_map(|scope| &scope.rpaths).any(|rpath| is_system_library(rpath));
+
+        let mut nodes = Vec::new();
+        for dependency in self.resolver.resolve_image(path, image, inherited) {
+            let mut dependencies = Vec::new();
+
+            let resolution = match dependency.path {
+                _ if is_system_library(&dependency.install_name) => Resolution::System,
+                Ok(found) => {
+                    let key = canonical(&found);
+                    if self.ancestors.contains(&key) {
+                        Resolution::Cycle(found)
+                    } else if !self.listed.insert(key.clone()) {
+                        Resolution::AlreadyListed(found)
+                    } else {
+                        match self.resolver.load(&found) {
+                            Ok(library) => {
+                                self.ancestors.push(key);
+                                dependencies = self.walk(&found, &library, &scopes);
+                                self.ancestors.pop();
+                                Resolution::Found(found)
+                            }
+                            Err(e) => Resolution::Unreadable(found, e),
+                        }
+                    }
+                }
+                // The Swift runtime comes from /usr/lib/swift, anything else has to be shipped
+                Err(_) if searches_system_rpath && is_system_rpath_library(&dependency.install_name) => {
+                    Resolution::System
+                }
+                Err(e) => Resolution::Missing(e),
+            };
+
+            nodes.push(DependencyNode {
+                install_name: dependency.install_name,
+                kind: dependency.kind,
+                resolution,
+                dependencies,
+            });
+        }
+
+        nodes
+    }
+}
+
+/// The same library reached through different relative paths or symlinks
+/// should only be expanded once.
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::LC_LOAD_DYLIB;
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64, MH_DYLIB, MH_EXECUTE};
+    use crate::rpath::{add_rpath, DuplicateRpath};
+
+    fn image(filetype: u32, dependencies: &[&str], rpaths: &[&str]) -> Vec<u8> {
+        let dylibs: Vec<_> = dependencies.iter().map(|&name| (LC_LOAD_DYLIB, name)).collect();
+        let mut data = fixtures::linking(true, true, CPU_TYPE_ARM64, filetype, &dylibs);
+        for rpath in rpaths {
+            add_rpath(&mut data, rpath, DuplicateRpath::Error).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn dependencies_are_classified() {
+        let dir = fixtures::temp_dir("tree");
+        let tool = dir.join("bin/tool");
+        fixtures::write(
+            &tool,
+            &image(
+                MH_EXECUTE,
+                &[
+                    "/usr/lib/libSystem.B.dylib",
+                    "@rpath/libswiftCore.dylib",
+                    "@rpath/libfoo.dylib",
+                    "@rpath/libbar.dylib",
+                    "@rpath/libmissing.dylib",
+                ],
+                &["/usr/lib/swift", "@executable_path/../lib"],
+            ),
+        );
+        fixtures::write(&dir.join("lib/libfoo.dylib"), &image(MH_DYLIB, &["@rpath/libbar.dylib"], &[]));
+        fixtures::write(&dir.join("lib/libbar.dylib"), &image(MH_DYLIB, &["@rpath/libfoo.dylib"], &[]));
+
+        let tree = DependencyTree::walk(&Resolver::new(), &tool).unwrap();
+
+        let resolutions: Vec<_> = tree.dependencies.iter().map(|node| &node.resolution).collect();
+        assert!(matches!(
+            resolutions.as_slice(),
+            [
+                Resolution::System,
+                Resolution::System,
+                Resolution::Found(foo),
+                Resolution::AlreadyListed(bar),
+                // Under a system rpath, but not a library the OS provides
+                Resolution::Missing(Error::LibraryNotFound { .. }),
+            ] if *foo == dir.join("lib/libfoo.dylib") && *bar == dir.join("lib/libbar.dylib")
+        ), "{:#?}", resolutions);
+
+        let foo = &tree.dependencies[2];
+        assert!(matches!(&foo.dependencies[0].resolution, Resolution::Found(path) if *path == dir.join("lib/libbar.dylib")));
+        assert!(matches!(&foo.dependencies[0].dependencies[0].resolution, Resolution::Cycle(_)));
+        assert_eq!(tree.flatten().len(), 5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_os_libraries_under_rpath_count_as_system() {
+        assert!(is_system_rpath_library("@rpath/libswiftCore.dylib"));
+        assert!(is_system_rpath_library("@rpath/swift/libswiftFoundation.dylib"));
+        assert!(!is_system_rpath_library("@rpath/libfoo.dylib"));
+        assert!(!is_system_rpath_library("/usr/local/lib/libswiftCore.dylib"));
+    }
+}
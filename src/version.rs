@@ -0,0 +1,505 @@
+use crate::commands::{
+    BuildVersionCommand, Command, DylibKind, VersionMinCommand, LC_CODE_SIGNATURE, LC_VERSION_MIN_IPHONEOS,
+    LC_VERSION_MIN_MACOSX, LC_VERSION_MIN_TVOS, LC_VERSION_MIN_WATCHOS,
+};
+use crate::edit::edit_load_commands;
+use crate::error::{Error, Result};
+use crate::header::{describe_arch, CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+use crate::image::Image;
+
+pub const PLATFORM_MACOS: u32 = 1;
+pub const PLATFORM_IOS: u32 = 2;
+pub const PLATFORM_TVOS: u32 = 3;
+pub const PLATFORM_WATCHOS: u32 = 4;
+pub const PLATFORM_BRIDGEOS: u32 = 5;
+pub const PLATFORM_MACCATALYST: u32 = 6;
+pub const PLATFORM_IOSSIMULATOR: u32 = 7;
+pub const PLATFORM_TVOSSIMULATOR: u32 = 8;
+pub const PLATFORM_WATCHOSSIMULATOR: u32 = 9;
+pub const PLATFORM_DRIVERKIT: u32 = 10;
+pub const PLATFORM_VISIONOS: u32 = 11;
+pub const PLATFORM_VISIONOSSIMULATOR: u32 = 12;
+
+pub const TOOL_CLANG: u32 = 1;
+pub const TOOL_SWIFT: u32 = 2;
+pub const TOOL_LD: u32 = 3;
+pub const TOOL_LLD: u32 = 4;
+
+/// Platform names as `vtool` prints them, with the short names it also accepts.
+const PLATFORMS: &[(u32, &str, &str)] = &[
+    (PLATFORM_MACOS, "MACOS", "macos"),
+    (PLATFORM_IOS, "IOS", "ios"),
+    (PLATFORM_TVOS, "TVOS", "tvos"),
+    (PLATFORM_WATCHOS, "WATCHOS", "watchos"),
+    (PLATFORM_BRIDGEOS, "BRIDGEOS", "bridgeos"),
+    (PLATFORM_MACCATALYST, "MACCATALYST", "maccatalyst"),
+    (PLATFORM_IOSSIMULATOR, "IOSSIMULATOR", "iossim"),
+    (PLATFORM_TVOSSIMULATOR, "TVOSSIMULATOR", "tvossim"),
+    (PLATFORM_WATCHOSSIMULATOR, "WATCHOSSIMULATOR", "watchossim"),
+    (PLATFORM_DRIVERKIT, "DRIVERKIT", "driverkit"),
+    (PLATFORM_VISIONOS, "VISIONOS", "visionos"),
+    (PLATFORM_VISIONOSSIMULATOR, "VISIONOSSIMULATOR", "visionossim"),
+];
+
+const TOOLS: &[(u32, &str)] = &[(TOOL_CLANG, "CLANG"), (TOOL_SWIFT, "SWIFT"), (TOOL_LD, "LD"), (TOOL_LLD, "LLD")];
+
+/// The `PLATFORM_*` name of a platform, such as `MACOS`.
+pub fn platform_name(platform: u32) -> Option<&'static str> {
+    PLATFORMS.iter().find(|(value, _, _)| *value == platform).map(|(_, name, _)| *name)
+}
+
+/// Reads a platform given by name, short name or number, ignoring case.
+pub fn parse_platform(name: &str) -> Option<u32> {
+    PLATFORMS
+        .iter()
+        .find(|(_, long, short)| long.eq_ignore_ascii_case(name) || short.eq_ignore_ascii_case(name))
+        .map(|(value, _, _)| *value)
+        .or_else(|| name.parse().ok())
+}
+
+/// The `TOOL_*` name of a build tool, such as `LD`.
+pub fn tool_name(tool: u32) -> Option<&'static str> {
+    TOOLS.iter().find(|(value, _)| *value == tool).map(|(_, name)| *name)
+}
+
+/// Reads a build tool given by name or number, ignoring case.
+pub fn parse_tool(name: &str) -> Option<u32> {
+    TOOLS.iter().find(|(_, tool)| tool.eq_ignore_ascii_case(name)).map(|(value, _)| *value).or_else(|| name.parse().ok())
+}
+
+/// `X.Y.Z` packed as `xxxx.yy.zz`, leaving out a zero patch version.
+pub fn format_version(version: u32) -> String {
+    match version & 0xff {
+        0 => format!("{}.{}", version >> 16, (version >> 8) & 0xff),
+        patch => format!("{}.{}.{}", version >> 16, (version >> 8) & 0xff, patch),
+    }
+}
+
+/// Packs `X`, `X.Y` or `X.Y.Z` as `xxxx.yy.zz`. `n/a` stands for 0, which is
+/// what an unknown SDK version is stored as.
+pub fn parse_version(version: &str) -> Option<u32> {
+    if version == "n/a" {
+        return Some(0);
+    }
+
+    let parts: Vec<u32> = version.split('.').map(str::parse).collect::<std::result::Result<_, _>>().ok()?;
+    match parts.as_slice() {
+        [major] if *major <= 0xffff => Some(major << 16),
+        [major, minor] if *major <= 0xffff && *minor <= 0xff => Some(major << 16 | minor << 8),
+        [major, minor, patch] if *major <= 0xffff && *minor <= 0xff && *patch <= 0xff => {
+            Some(major << 16 | minor << 8 | patch)
+        }
+        _ => None,
+    }
+}
+
+/// The `LC_VERSION_MIN_*` command that stands for `platform` in older binaries.
+pub fn version_min_command(platform: u32) -> Option<u32> {
+    match platform {
+        PLATFORM_MACOS => Some(LC_VERSION_MIN_MACOSX),
+        PLATFORM_IOS => Some(LC_VERSION_MIN_IPHONEOS),
+        PLATFORM_TVOS => Some(LC_VERSION_MIN_TVOS),
+        PLATFORM_WATCHOS => Some(LC_VERSION_MIN_WATCHOS),
+        _ => None,
+    }
+}
+
+impl VersionMinCommand {
+    /// The platform the command is for. Simulator builds used the same
+    /// commands as devices, so they come out as the device platform.
+    pub fn platform(&self) -> u32 {
+        match self.cmd {
+            LC_VERSION_MIN_MACOSX => PLATFORM_MACOS,
+            LC_VERSION_MIN_IPHONEOS => PLATFORM_IOS,
+            LC_VERSION_MIN_TVOS => PLATFORM_TVOS,
+            _ => PLATFORM_WATCHOS,
+        }
+    }
+
+    /// The same information as an `LC_BUILD_VERSION`, without build tools.
+    pub fn to_build_version(&self) -> BuildVersionCommand {
+        BuildVersionCommand { platform: self.platform(), minos: self.version, sdk: self.sdk, tools: Vec::new() }
+    }
+}
+
+/// The platform a version command is for, `None` for any other command.
+fn version_platform(command: &Command) -> Option<u32> {
+    match command {
+        Command::BuildVersion(build_version) => Some(build_version.platform),
+        Command::VersionMin(version_min) => Some(version_min.platform()),
+        _ => None,
+    }
+}
+
+/// Writes `command` over the version command for the same platform, whether
+/// it is an `LC_BUILD_VERSION` or an `LC_VERSION_MIN_*`, or adds it when the
+/// file has none for that platform. Binaries zippered for macOS and Mac
+/// Catalyst carry one command per platform.
+///
+/// New commands go after the other version commands, or else before
+/// `LC_CODE_SIGNATURE`, which the linker always writes last.
+fn set_version_command(data: &mut Vec<u8>, platform: u32, command: Command) -> Result<()> {
+    edit_load_commands(data, |commands, _| {
+        if let Some(index) = commands.iter().position(|existing| version_platform(existing) == Some(platform)) {
+            commands[index] = command;
+            return Ok(());
+        }
+
+        let index = match commands.iter().rposition(|existing| version_platform(existing).is_some()) {
+            Some(last) => last + 1,
+            None => commands.iter().position(|existing| existing.cmd() == LC_CODE_SIGNATURE).unwrap_or(commands.len()),
+        };
+        commands.insert(index, command);
+        Ok(())
+    })
+}
+
+/// Sets the `LC_BUILD_VERSION` for its platform, converting an
+/// `LC_VERSION_MIN_*` for the same platform, like `vtool -set-build-version`.
+pub(crate) fn set_build_version(data: &mut Vec<u8>, build_version: &BuildVersionCommand) -> Result<()> {
+    set_version_command(data, build_version.platform, Command::BuildVersion(build_version.clone()))
+}
+
+/// Sets the legacy `LC_VERSION_MIN_*` for `platform`, converting an
+/// `LC_BUILD_VERSION` for the same platform, like `vtool -set-version-min`.
+/// Build tool entries have no place in the legacy command and are dropped.
+pub(crate) fn set_version_min(data: &mut Vec<u8>, platform: u32, minos: u32, sdk: u32) -> Result<()> {
+    let cmd = version_min_command(platform).ok_or(Error::NoVersionMinCommand(platform))?;
+
+    set_version_command(data, platform, Command::VersionMin(VersionMinCommand { cmd, version: minos, sdk }))
+}
+
+/// Removes the version commands for `platform`, or all of them when `None`.
+pub(crate) fn remove_build_version(data: &mut Vec<u8>, platform: Option<u32>) -> Result<()> {
+    edit_load_commands(data, |commands, _| {
+        commands.retain(|command| match (version_platform(command), platform) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(existing), Some(platform)) => existing != platform,
+        });
+        Ok(())
+    })
+}
+
+/// The macOS release that shipped alongside each iOS release, from the first
+/// one Mac Catalyst supports. Used to carry a deployment target over when a
+/// binary moves between macOS and the iOS based platforms.
+const MACOS_TO_IOS: &[(u32, u32)] = &[
+    (0x000a_0f00, 0x000d_0100), // 10.15 and 13.1
+    (0x000b_0000, 0x000e_0200), // 11.0 and 14.2
+    (0x000c_0000, 0x000f_0000), // 12.0 and 15.0
+    (0x000d_0000, 0x0010_0000), // 13.0 and 16.0
+    (0x000e_0000, 0x0011_0000), // 14.0 and 17.0
+    (0x000f_0000, 0x0012_0000), // 15.0 and 18.0
+    (0x001a_0000, 0x001a_0000), // 26.0 and 26.0
+];
+
+/// Frameworks that only exist on some platforms, so a binary linking one
+/// cannot move to the others.
+const PLATFORM_FRAMEWORKS: &[(&str, &[u32])] = &[
+    ("/System/Library/Frameworks/AppKit.framework/", &[PLATFORM_MACOS, PLATFORM_MACCATALYST]),
+    ("/System/Library/Frameworks/Cocoa.framework/", &[PLATFORM_MACOS, PLATFORM_MACCATALYST]),
+    ("/System/Library/Frameworks/UIKit.framework/", &[PLATFORM_IOSSIMULATOR]),
+    ("/System/iOSSupport/", &[PLATFORM_MACCATALYST]),
+];
+
+/// Whether `platform` counts its versions like macOS rather than like iOS.
+fn uses_macos_versions(platform: u32) -> bool {
+    platform == PLATFORM_MACOS
+}
+
+/// The version on `to` that corresponds to `version` on `from`: the same
+/// number within the macOS or the iOS family, or else the first release of
+/// the matching year in the other family.
+pub fn equivalent_version(version: u32, from: u32, to: u32) -> u32 {
+    if version == 0 || uses_macos_versions(from) == uses_macos_versions(to) {
+        return version;
+    }
+
+    let pairs: Vec<(u32, u32)> = MACOS_TO_IOS
+        .iter()
+        .map(|&(macos, ios)| if uses_macos_versions(from) { (macos, ios) } else { (ios, macos) })
+        .collect();
+
+    // Anything older than the first pair moves to the oldest release there is
+    pairs.iter().rev().find(|&&(source, _)| source <= version).unwrap_or(&pairs[0]).1
+}
+
+/// The lowest deployment target `platform` supports on this CPU type.
+fn minimum_version(platform: u32, cputype: i32) -> u32 {
+    match (platform, cputype) {
+        (PLATFORM_MACOS, CPU_TYPE_ARM64) => 0x000b_0000,           // 11.0
+        (PLATFORM_MACCATALYST, CPU_TYPE_ARM64) => 0x000e_0000,     // 14.0
+        (PLATFORM_MACCATALYST, _) => 0x000d_0100,                  // 13.1
+        (PLATFORM_IOSSIMULATOR, CPU_TYPE_ARM64) => 0x000e_0000,    // 14.0
+        _ => 0,
+    }
+}
+
+/// Moves a thin image to `platform`, one of macOS, the iOS simulator and Mac
+/// Catalyst, and sets its deployment target and SDK. Versions that are not
+/// given are carried over with [`equivalent_version`].
+///
+/// The image must have a single version command, be built for x86_64 or
+/// arm64, meet the lowest deployment target of the new platform on its CPU
+/// and not link frameworks the new platform does not have.
+pub(crate) fn retarget_platform(data: &mut Vec<u8>, platform: u32, minos: Option<u32>, sdk: Option<u32>) -> Result<()> {
+    let image = Image::parse(data)?;
+    let incompatible = |reason: String| Error::IncompatiblePlatform { platform, reason };
+
+    if !matches!(platform, PLATFORM_MACOS | PLATFORM_IOSSIMULATOR | PLATFORM_MACCATALYST) {
+        return Err(incompatible("only macOS, the iOS simulator and Mac Catalyst are supported".to_string()));
+    }
+
+    let cputype = image.header.cputype;
+    if !matches!(cputype, CPU_TYPE_X86_64 | CPU_TYPE_ARM64) {
+        let arch = describe_arch(cputype, image.header.cpusubtype);
+        return Err(incompatible(format!("{} is not supported", arch)));
+    }
+
+    let current = match image.build_versions().as_slice() {
+        [current] => current.clone(),
+        [] => return Err(incompatible("the file has no LC_BUILD_VERSION or LC_VERSION_MIN".to_string())),
+        _ => return Err(incompatible("the file is built for more than one platform".to_string())),
+    };
+
+    let minos = minos.unwrap_or_else(|| equivalent_version(current.minos, current.platform, platform));
+    let sdk = sdk.unwrap_or_else(|| equivalent_version(current.sdk, current.platform, platform));
+    let required = minimum_version(platform, cputype);
+    if minos < required {
+        return Err(incompatible(format!(
+            "minos {} is below {}, the lowest it supports on {}",
+            format_version(minos),
+            format_version(required),
+            describe_arch(cputype, image.header.cpusubtype)
+        )));
+    }
+
+    for command in &image.commands {
+        match command {
+            Command::Dylib(dylib) if dylib.kind() != DylibKind::Id => {
+                let unavailable = PLATFORM_FRAMEWORKS
+                    .iter()
+                    .any(|(prefix, platforms)| dylib.name.starts_with(prefix) && !platforms.contains(&platform));
+                if unavailable {
+                    return Err(incompatible(format!("{} is not available there", dylib.name)));
+                }
+            }
+            Command::EncryptionInfo(encryption) if encryption.cryptid != 0 => {
+                return Err(incompatible("the file is encrypted".to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    // Keep the build tools, which a legacy command does not have
+    let build_version = BuildVersionCommand { platform, minos, sdk, tools: current.tools };
+    edit_load_commands(data, |commands, _| {
+        if let Some(index) = commands.iter().position(|command| version_platform(command).is_some()) {
+            commands[index] = Command::BuildVersion(build_version);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{BuildToolVersion, LC_BUILD_VERSION, LC_LOAD_DYLIB};
+    use crate::fixtures;
+    use crate::header::{CPU_TYPE_ARM64_32, MH_DYLIB, MH_OBJECT};
+    use crate::macho::MachO;
+
+    fn dylib() -> Vec<u8> {
+        fixtures::thin(true, true, CPU_TYPE_ARM64, MH_DYLIB)
+    }
+
+    fn versions(data: &[u8]) -> Vec<Command> {
+        let image = Image::parse(data).unwrap();
+        image.commands.into_iter().filter(|command| version_platform(command).is_some()).collect()
+    }
+
+    #[test]
+    fn versions_are_packed_as_nibbles() {
+        assert_eq!(parse_version("11"), Some(0x000b_0000));
+        assert_eq!(parse_version("10.15"), Some(0x000a_0f00));
+        assert_eq!(parse_version("1015.7.3"), Some(0x03f7_0703));
+        assert_eq!(parse_version("n/a"), Some(0));
+        assert_eq!(parse_version("10.256"), None);
+        assert_eq!(parse_version("65536"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+        assert_eq!(parse_version("1..2"), None);
+
+        assert_eq!(format_version(0x000a_0f00), "10.15");
+        assert_eq!(format_version(0x03f7_0703), "1015.7.3");
+    }
+
+    #[test]
+    fn platforms_and_tools_are_read_by_name_or_number() {
+        assert_eq!(parse_platform("macos"), Some(PLATFORM_MACOS));
+        assert_eq!(parse_platform("MACCATALYST"), Some(PLATFORM_MACCATALYST));
+        assert_eq!(parse_platform("iossim"), Some(PLATFORM_IOSSIMULATOR));
+        assert_eq!(parse_platform("7"), Some(PLATFORM_IOSSIMULATOR));
+        assert_eq!(parse_platform("plan9"), None);
+        assert_eq!(platform_name(PLATFORM_VISIONOS), Some("VISIONOS"));
+        assert_eq!(parse_tool("ld"), Some(TOOL_LD));
+        assert_eq!(tool_name(TOOL_SWIFT), Some("SWIFT"));
+    }
+
+    #[test]
+    fn version_min_commands_convert_to_build_versions_and_back() {
+        let mut data = dylib();
+        let sizeofcmds = Image::parse(&data).unwrap().header.sizeofcmds;
+
+        set_version_min(&mut data, PLATFORM_MACOS, 0x000a_0d00, 0x000b_0000).unwrap();
+        assert!(matches!(
+            versions(&data).as_slice(),
+            [Command::VersionMin(VersionMinCommand {
+                cmd: LC_VERSION_MIN_MACOSX,
+                version: 0x000a_0d00,
+                sdk: 0x000b_0000,
+            })]
+        ));
+
+        let tools = vec![BuildToolVersion { tool: TOOL_LD, version: 0x03f7_0700 }];
+        let build_version =
+            BuildVersionCommand { platform: PLATFORM_MACOS, minos: 0x000b_0000, sdk: 0x000e_0000, tools };
+        set_build_version(&mut data, &build_version).unwrap();
+        assert!(matches!(
+            versions(&data).as_slice(),
+            [Command::BuildVersion(BuildVersionCommand {
+                platform: PLATFORM_MACOS,
+                minos: 0x000b_0000,
+                sdk: 0x000e_0000,
+                tools,
+            })] if tools.len() == 1 && tools[0].tool == TOOL_LD && tools[0].version == 0x03f7_0700
+        ));
+        // 24 bytes for the command and 8 for its one tool
+        assert_eq!(Image::parse(&data).unwrap().header.sizeofcmds, sizeofcmds + 32);
+
+        // The legacy command has no room for tools
+        set_version_min(&mut data, PLATFORM_MACOS, 0x000b_0000, 0x000e_0000).unwrap();
+        assert!(Image::parse(&data).unwrap().build_versions()[0].tools.is_empty());
+        assert!(matches!(
+            set_version_min(&mut data, PLATFORM_MACCATALYST, 0, 0),
+            Err(Error::NoVersionMinCommand(PLATFORM_MACCATALYST))
+        ));
+    }
+
+    #[test]
+    fn zippered_binaries_keep_one_command_per_platform() {
+        let mut data = dylib();
+        let macos = BuildVersionCommand { platform: PLATFORM_MACOS, minos: 0x000a_0f00, sdk: 0, tools: Vec::new() };
+        let catalyst =
+            BuildVersionCommand { platform: PLATFORM_MACCATALYST, minos: 0x000d_0100, sdk: 0, tools: Vec::new() };
+
+        set_build_version(&mut data, &macos).unwrap();
+        set_build_version(&mut data, &catalyst).unwrap();
+        set_build_version(&mut data, &BuildVersionCommand { minos: 0x000b_0000, ..macos.clone() }).unwrap();
+
+        let platforms = |data: &[u8]| -> Vec<(u32, u32)> {
+            let build_versions = Image::parse(data).unwrap().build_versions();
+            build_versions.iter().map(|version| (version.platform, version.minos)).collect()
+        };
+        assert_eq!(platforms(&data), [(PLATFORM_MACOS, 0x000b_0000), (PLATFORM_MACCATALYST, 0x000d_0100)]);
+
+        remove_build_version(&mut data, Some(PLATFORM_MACOS)).unwrap();
+        assert_eq!(platforms(&data), [(PLATFORM_MACCATALYST, 0x000d_0100)]);
+
+        remove_build_version(&mut data, None).unwrap();
+        assert_eq!(versions(&data).len(), 0);
+        assert!(Image::parse(&data).unwrap().commands.iter().all(|command| command.cmd() != LC_BUILD_VERSION));
+    }
+
+    /// An image for `cputype` built for macOS 11.0 with the 14.0 SDK, linking `dylibs`.
+    fn macos_image(cputype: i32, filetype: u32, dylibs: &[&str]) -> Vec<u8> {
+        let dylibs: Vec<_> = dylibs.iter().map(|&name| (LC_LOAD_DYLIB, name)).collect();
+        let mut data = fixtures::linking(true, true, cputype, filetype, &dylibs);
+        let tools = vec![BuildToolVersion { tool: TOOL_LD, version: 0x03f7_0700 }];
+        let build_version =
+            BuildVersionCommand { platform: PLATFORM_MACOS, minos: 0x000b_0000, sdk: 0x000e_0000, tools };
+        set_build_version(&mut data, &build_version).unwrap();
+        data
+    }
+
+    fn build_version(data: &[u8]) -> (u32, u32, u32, usize) {
+        let build_versions = Image::parse(data).unwrap().build_versions();
+        assert_eq!(build_versions.len(), 1);
+        let version = &build_versions[0];
+        (version.platform, version.minos, version.sdk, version.tools.len())
+    }
+
+    #[test]
+    fn object_files_move_to_the_simulator_and_back() {
+        let mut macho = MachO::parse(macos_image(CPU_TYPE_ARM64, MH_OBJECT, &[])).unwrap();
+
+        macho.retarget_platform(PLATFORM_IOSSIMULATOR, None, None).unwrap();
+        let simulator = macho.into_bytes();
+        // macOS 11.0 shipped with iOS 14.2, and the tools are kept
+        assert_eq!(build_version(&simulator), (PLATFORM_IOSSIMULATOR, 0x000e_0200, 0x0011_0000, 1));
+        // Objects are signed when they are linked
+        assert!(Image::parse(&simulator).unwrap().code_signature().unwrap().is_none());
+
+        let mut macho = MachO::parse(simulator).unwrap();
+        macho.retarget_platform(PLATFORM_MACOS, None, None).unwrap();
+        assert_eq!(build_version(&macho.into_bytes()), (PLATFORM_MACOS, 0x000b_0000, 0x000e_0000, 1));
+    }
+
+    #[test]
+    fn retargeted_dylibs_are_signed_again() {
+        let mut macho = MachO::parse(macos_image(CPU_TYPE_ARM64, MH_DYLIB, &["/usr/lib/libSystem.B.dylib"])).unwrap();
+
+        macho.retarget_platform(PLATFORM_MACCATALYST, Some(0x000e_0000), Some(0x0011_0000)).unwrap();
+
+        let data = macho.into_bytes();
+        assert_eq!(build_version(&data), (PLATFORM_MACCATALYST, 0x000e_0000, 0x0011_0000, 1));
+        let image = Image::parse(&data).unwrap();
+        let signature = image.code_signature().unwrap().unwrap();
+        assert!(signature.verify(&image.data).unwrap().is_empty());
+    }
+
+    #[test]
+    fn files_that_cannot_run_on_the_new_platform_are_refused() {
+        let refused = |data: Vec<u8>, platform: u32, minos: Option<u32>| {
+            let mut data = data;
+            let original = data.clone();
+            let error = retarget_platform(&mut data, platform, minos, None).unwrap_err();
+            assert_eq!(data, original);
+            match error {
+                Error::IncompatiblePlatform { reason, .. } => reason,
+                error => panic!("expected IncompatiblePlatform, got {:?}", error),
+            }
+        };
+        let appkit = "/System/Library/Frameworks/AppKit.framework/Versions/C/AppKit";
+
+        assert_eq!(
+            refused(macos_image(CPU_TYPE_ARM64, MH_DYLIB, &[appkit]), PLATFORM_IOSSIMULATOR, None),
+            format!("{} is not available there", appkit)
+        );
+        assert_eq!(
+            refused(macos_image(CPU_TYPE_ARM64, MH_DYLIB, &[]), PLATFORM_MACCATALYST, Some(0x000d_0100)),
+            "minos 13.1 is below 14.0, the lowest it supports on arm64"
+        );
+        assert_eq!(
+            refused(macos_image(CPU_TYPE_ARM64_32, MH_OBJECT, &[]), PLATFORM_IOSSIMULATOR, None),
+            "arm64_32 is not supported"
+        );
+        assert_eq!(
+            refused(macos_image(CPU_TYPE_ARM64, MH_OBJECT, &[]), PLATFORM_IOS, None),
+            "only macOS, the iOS simulator and Mac Catalyst are supported"
+        );
+        assert_eq!(
+            refused(fixtures::thin(true, true, CPU_TYPE_X86_64, MH_OBJECT), PLATFORM_IOSSIMULATOR, None),
+            "the file has no LC_BUILD_VERSION or LC_VERSION_MIN"
+        );
+    }
+
+    #[test]
+    fn equivalent_versions_follow_the_release_years() {
+        assert_eq!(equivalent_version(0x000c_0300, PLATFORM_MACOS, PLATFORM_MACCATALYST), 0x000f_0000);
+        assert_eq!(equivalent_version(0x000a_0e00, PLATFORM_MACOS, PLATFORM_IOSSIMULATOR), 0x000d_0100);
+        assert_eq!(equivalent_version(0x0011_0400, PLATFORM_IOSSIMULATOR, PLATFORM_MACOS), 0x000e_0000);
+        assert_eq!(equivalent_version(0x0011_0400, PLATFORM_IOSSIMULATOR, PLATFORM_MACCATALYST), 0x0011_0400);
+        assert_eq!(equivalent_version(0, PLATFORM_MACOS, PLATFORM_IOSSIMULATOR), 0);
+    }
+}